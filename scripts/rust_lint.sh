@@ -26,6 +26,9 @@ set -e
 set -x
 
 cargo +nightly xclippy
+# The api and graphql servers are behind features, lint them too
+cargo +nightly xclippy --features api
+cargo +nightly xclippy --all-features
 
 # We require the nightly build of cargo fmt
 # to provide stricter rust formatting.
@@ -0,0 +1,194 @@
+use super::{handlers::ApiError, ApiState};
+use crate::models::change_event::{
+    ChangeEvent, ChangeEventFilter, ChangeEventType, StoredChangeEvent,
+};
+use aptos_indexer_processor_sdk::utils::convert::standardize_address;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::Stream;
+use serde::Deserialize;
+use std::{convert::Infallible, str::FromStr, sync::Arc};
+use tokio::sync::{
+    broadcast::{error::RecvError, Receiver},
+    mpsc,
+};
+use tracing::warn;
+
+/// Number of stored changes replayed per query when resuming
+const REPLAY_BATCH_SIZE: i64 = 500;
+/// Number of events buffered per connection before the stream applies backpressure
+const CONNECTION_BUFFER_SIZE: usize = 1000;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EventParams {
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    /// Matches the sender or the receiver
+    pub wallet: Option<String>,
+    /// Comma separated event types, e.g. `listing_created,bid_placed`
+    pub types: Option<String>,
+    /// Replays the stored changes published after the change with this id before streaming
+    /// new ones
+    pub from_id: Option<i64>,
+    /// Replays the stored changes of the transactions after this `tx_index` before streaming
+    /// new ones. Ignored when `from_id` or `Last-Event-ID` is set.
+    pub from_tx_index: Option<i64>,
+}
+
+impl EventParams {
+    fn filter(&self) -> Result<ChangeEventFilter, ApiError> {
+        let event_types = self
+            .types
+            .as_deref()
+            .map(|types| {
+                types
+                    .split(',')
+                    .map(|event_type| {
+                        ChangeEventType::from_str(event_type.trim()).map_err(|_| {
+                            ApiError::BadRequest(format!("Unknown event type {event_type}"))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+
+        Ok(ChangeEventFilter {
+            collection_id: self.collection_id.as_deref().map(standardize_address),
+            nft_id: self.nft_id.as_deref().map(standardize_address),
+            wallet: self.wallet.as_deref().map(standardize_address),
+            event_types,
+        })
+    }
+}
+
+/// Streams the changes matching the query as server-sent events. The id of each event is its
+/// id in the `change_events` outbox, which follows the publish order across pipelines, so a
+/// client that reconnects with `Last-Event-ID` (or `from_id`) first receives the stored changes
+/// it missed, without duplicates. A client that only knows the last transaction it processed
+/// resumes with `from_tx_index` instead. Changes older than the outbox retention are not
+/// replayed.
+pub async fn stream_events(
+    State(state): State<Arc<ApiState>>,
+    headers: HeaderMap,
+    Query(params): Query<EventParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let filter = params.filter()?;
+    let from_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .or(params.from_id);
+    // The changes of the transactions up to `from_tx_index` are skipped, even if published later
+    let after_tx_index = match from_id {
+        Some(_) => None,
+        None => params.from_tx_index,
+    };
+
+    // Subscribe before replaying so that changes committed meanwhile aren't missed
+    let receiver = state.event_bus.subscribe();
+    let (sender, mut events) = mpsc::channel(CONNECTION_BUFFER_SIZE);
+    tokio::spawn(async move {
+        if let Err(e) =
+            forward_events(state, filter, from_id, after_tx_index, receiver, sender).await
+        {
+            warn!("Event stream stopped: {:?}", e);
+        }
+    });
+
+    let stream = futures::stream::poll_fn(move |cx| events.poll_recv(cx).map(|e| e.map(Ok)));
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn forward_events(
+    state: Arc<ApiState>,
+    filter: ChangeEventFilter,
+    from_id: Option<i64>,
+    after_tx_index: Option<i64>,
+    mut receiver: Receiver<Arc<StoredChangeEvent>>,
+    sender: mpsc::Sender<Event>,
+) -> anyhow::Result<()> {
+    let from_id = match (from_id, after_tx_index) {
+        (None, Some(tx_index)) => {
+            let mut conn = state.db_pool.get().await?;
+            Some(ChangeEvent::get_resume_id(&mut conn, tx_index).await?)
+        },
+        _ => from_id,
+    };
+    // Changes published while replaying are both stored and received, skip them once live
+    let mut last_id = from_id;
+    if let Some(mut after_id) = from_id {
+        loop {
+            let changes = {
+                let mut conn = state.db_pool.get().await?;
+                ChangeEvent::get_since(&mut conn, &filter, after_id, REPLAY_BATCH_SIZE).await?
+            };
+
+            for change in changes
+                .iter()
+                .filter(|change| !is_up_to_tx_index(change, after_tx_index))
+            {
+                if sender.send(to_sse_event(change)).await.is_err() {
+                    return Ok(());
+                }
+            }
+
+            match changes.last() {
+                Some(last) => {
+                    after_id = last.id;
+                    last_id = Some(last.id);
+                    if (changes.len() as i64) < REPLAY_BATCH_SIZE {
+                        break;
+                    }
+                },
+                None => break,
+            }
+        }
+    }
+
+    loop {
+        let received = tokio::select! {
+            received = receiver.recv() => received,
+            // The client disconnected
+            _ = sender.closed() => return Ok(()),
+        };
+
+        match received {
+            Ok(change) => {
+                let is_replayed = last_id.map_or(false, |last_id| change.id <= last_id);
+                if !is_replayed
+                    && !is_up_to_tx_index(&change, after_tx_index)
+                    && filter.matches(&change.event)
+                    && sender.send(to_sse_event(&change)).await.is_err()
+                {
+                    return Ok(());
+                }
+            },
+            Err(RecvError::Lagged(skipped)) => {
+                // The client reconnects with `Last-Event-ID` and replays what it missed
+                let lagged = Event::default().event("lagged").data(skipped.to_string());
+                let _ = sender.send(lagged).await;
+                return Ok(());
+            },
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+/// Returns true if the change was made by a transaction up to `after_tx_index`.
+fn is_up_to_tx_index(change: &StoredChangeEvent, after_tx_index: Option<i64>) -> bool {
+    match (change.event.tx_index, after_tx_index) {
+        (Some(tx_index), Some(after_tx_index)) => tx_index <= after_tx_index,
+        _ => false,
+    }
+}
+
+fn to_sse_event(change: &StoredChangeEvent) -> Event {
+    Event::default()
+        .id(change.id.to_string())
+        .event(change.event.event_type.to_string())
+        .json_data(&change.event)
+        .unwrap_or_else(|_| Event::default().comment("failed to serialize change"))
+}
@@ -0,0 +1,103 @@
+use crate::{
+    models::db::{attributes::Attribute, collection::Collection, listing::Listing},
+    postgres::postgres_utils::ArcDbPool,
+};
+use async_graphql::dataloader::Loader;
+use std::{collections::HashMap, sync::Arc};
+
+/// Batches the lookups of the nested relations of a page of nfts into one query each.
+pub struct DbLoader {
+    db_pool: ArcDbPool,
+}
+
+impl DbLoader {
+    pub fn new(db_pool: ArcDbPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+/// Key of the collection of an nft
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CollectionId(pub String);
+
+/// Key of the active listings of an nft
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ListingsOf(pub String);
+
+/// Key of the attributes of an nft
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AttributesOf(pub String);
+
+impl Loader<CollectionId> for DbLoader {
+    type Error = Arc<anyhow::Error>;
+    type Value = Collection;
+
+    async fn load(
+        &self,
+        keys: &[CollectionId],
+    ) -> Result<HashMap<CollectionId, Self::Value>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0.clone()).collect::<Vec<_>>();
+        let mut conn = self.db_pool.get().await.map_err(|e| Arc::new(e.into()))?;
+        let collections = Collection::get_by_ids(&mut conn, &ids)
+            .await
+            .map_err(|e| Arc::new(e.into()))?;
+
+        Ok(collections
+            .into_iter()
+            .map(|collection| (CollectionId(collection.id.clone()), collection))
+            .collect())
+    }
+}
+
+impl Loader<ListingsOf> for DbLoader {
+    type Error = Arc<anyhow::Error>;
+    type Value = Vec<Listing>;
+
+    async fn load(
+        &self,
+        keys: &[ListingsOf],
+    ) -> Result<HashMap<ListingsOf, Self::Value>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0.clone()).collect::<Vec<_>>();
+        let mut conn = self.db_pool.get().await.map_err(|e| Arc::new(e.into()))?;
+        let listings = Listing::get_active_by_nft_ids(&mut conn, &ids)
+            .await
+            .map_err(|e| Arc::new(e.into()))?;
+
+        let mut by_nft: HashMap<ListingsOf, Self::Value> = HashMap::new();
+        for listing in listings {
+            if let Some(nft_id) = listing.nft_id.clone() {
+                by_nft.entry(ListingsOf(nft_id)).or_default().push(listing);
+            }
+        }
+
+        Ok(by_nft)
+    }
+}
+
+impl Loader<AttributesOf> for DbLoader {
+    type Error = Arc<anyhow::Error>;
+    type Value = Vec<Attribute>;
+
+    async fn load(
+        &self,
+        keys: &[AttributesOf],
+    ) -> Result<HashMap<AttributesOf, Self::Value>, Self::Error> {
+        let ids = keys.iter().map(|key| key.0.clone()).collect::<Vec<_>>();
+        let mut conn = self.db_pool.get().await.map_err(|e| Arc::new(e.into()))?;
+        let attributes = Attribute::get_by_nft_ids(&mut conn, &ids)
+            .await
+            .map_err(|e| Arc::new(e.into()))?;
+
+        let mut by_nft: HashMap<AttributesOf, Self::Value> = HashMap::new();
+        for attribute in attributes {
+            if let Some(nft_id) = attribute.nft_id.clone() {
+                by_nft
+                    .entry(AttributesOf(nft_id))
+                    .or_default()
+                    .push(attribute);
+            }
+        }
+
+        Ok(by_nft)
+    }
+}
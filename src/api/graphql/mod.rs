@@ -0,0 +1,275 @@
+use super::ApiState;
+use crate::models::db::{
+    action::{Action, ActionFilter},
+    attributes::Attribute,
+    bid::{Bid, BidFilter},
+    collection::Collection,
+    commission::Commission,
+    listing::{Listing, ListingFilter},
+    nft::{Nft, NftFilter},
+};
+use aptos_indexer_processor_sdk::utils::convert::standardize_address;
+use async_graphql::{
+    connection::{Connection, CursorType, Edge},
+    dataloader::DataLoader,
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Json, Object, OutputType, Result,
+    Schema,
+};
+use loaders::{AttributesOf, CollectionId, DbLoader, ListingsOf};
+use std::sync::Arc;
+
+pub mod loaders;
+
+pub type NftSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn build_schema(state: Arc<ApiState>) -> NftSchema {
+    let loader = DataLoader::new(DbLoader::new(state.db_pool.clone()), tokio::spawn);
+
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(loader)
+        .data(state)
+        .finish()
+}
+
+/// Offset and size of the page after the `after` cursor. Cursors are the position of the
+/// item in the result set.
+fn page_window(state: &ApiState, after: Option<String>, first: Option<i32>) -> Result<(i64, i64)> {
+    let offset = match after {
+        Some(after) => usize::decode_cursor(&after)? as i64 + 1,
+        None => 0,
+    };
+    let page_size = first
+        .map(i64::from)
+        .unwrap_or(state.config.default_page_size)
+        .clamp(1, state.config.max_page_size.max(1));
+
+    Ok((offset, page_size))
+}
+
+/// Builds the connection from a page fetched with one extra item, which tells whether there
+/// is a next page.
+fn into_connection<T: OutputType>(
+    mut items: Vec<T>,
+    offset: i64,
+    page_size: i64,
+) -> Connection<usize, T> {
+    let has_next_page = items.len() as i64 > page_size;
+    items.truncate(page_size as usize);
+
+    let mut connection = Connection::new(offset > 0, has_next_page);
+    connection.edges.extend(
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| Edge::new(offset as usize + i, item)),
+    );
+
+    connection
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn nft(&self, ctx: &Context<'_>, id: String) -> Result<Option<Nft>> {
+        let state = ctx.data::<Arc<ApiState>>()?;
+        let mut conn = state.db_pool.get().await?;
+
+        Ok(Nft::get_by_id(&mut conn, &standardize_address(&id)).await?)
+    }
+
+    async fn nfts(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<NftFilter>,
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> Result<Connection<usize, Nft>> {
+        let state = ctx.data::<Arc<ApiState>>()?;
+        let (offset, page_size) = page_window(state, after, first)?;
+        let mut conn = state.db_pool.get().await?;
+
+        let nfts = Nft::get_filtered(
+            &mut conn,
+            &filter.unwrap_or_default(),
+            page_size + 1,
+            offset,
+        )
+        .await?;
+
+        Ok(into_connection(nfts, offset, page_size))
+    }
+
+    async fn collection(&self, ctx: &Context<'_>, id: String) -> Result<Option<Collection>> {
+        let state = ctx.data::<Arc<ApiState>>()?;
+        let mut conn = state.db_pool.get().await?;
+
+        Ok(Collection::get_by_id(&mut conn, &standardize_address(&id)).await?)
+    }
+
+    async fn listings(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<ListingFilter>,
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> Result<Connection<usize, Listing>> {
+        let state = ctx.data::<Arc<ApiState>>()?;
+        let (offset, page_size) = page_window(state, after, first)?;
+        let mut conn = state.db_pool.get().await?;
+
+        let listings = Listing::get_filtered(
+            &mut conn,
+            &filter.unwrap_or_default(),
+            page_size + 1,
+            offset,
+        )
+        .await?;
+
+        Ok(into_connection(listings, offset, page_size))
+    }
+
+    async fn bids(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<BidFilter>,
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> Result<Connection<usize, Bid>> {
+        let state = ctx.data::<Arc<ApiState>>()?;
+        let (offset, page_size) = page_window(state, after, first)?;
+        let mut conn = state.db_pool.get().await?;
+
+        let bids = Bid::get_filtered(
+            &mut conn,
+            &filter.unwrap_or_default(),
+            page_size + 1,
+            offset,
+        )
+        .await?;
+
+        Ok(into_connection(bids, offset, page_size))
+    }
+
+    async fn actions(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<ActionFilter>,
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> Result<Connection<usize, Action>> {
+        let state = ctx.data::<Arc<ApiState>>()?;
+        let (offset, page_size) = page_window(state, after, first)?;
+        let mut conn = state.db_pool.get().await?;
+
+        let actions = Action::get_filtered(
+            &mut conn,
+            &filter.unwrap_or_default(),
+            page_size + 1,
+            offset,
+        )
+        .await?;
+
+        Ok(into_connection(actions, offset, page_size))
+    }
+
+    /// Royalty of an nft, or of its collection when the nft has none of its own
+    async fn commission(
+        &self,
+        ctx: &Context<'_>,
+        nft_id: String,
+        collection_id: Option<String>,
+    ) -> Result<Option<Commission>> {
+        let state = ctx.data::<Arc<ApiState>>()?;
+        let mut conn = state.db_pool.get().await?;
+        let collection_id = collection_id.map(|id| standardize_address(&id));
+
+        Ok(Commission::get_by_nft(
+            &mut conn,
+            &standardize_address(&nft_id),
+            collection_id.as_deref(),
+        )
+        .await?)
+    }
+}
+
+#[ComplexObject]
+impl Nft {
+    async fn properties(&self) -> Option<Json<serde_json::Value>> {
+        self.properties.clone().map(Json)
+    }
+
+    async fn collection(&self, ctx: &Context<'_>) -> Result<Option<Collection>> {
+        let Some(collection_id) = self.collection_id.clone() else {
+            return Ok(None);
+        };
+
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        Ok(loader.load_one(CollectionId(collection_id)).await?)
+    }
+
+    /// Active listings of the nft, cheapest first
+    async fn listings(&self, ctx: &Context<'_>) -> Result<Vec<Listing>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        let listings = loader.load_one(ListingsOf(self.id.clone())).await?;
+
+        Ok(listings.unwrap_or_default())
+    }
+
+    async fn attributes(&self, ctx: &Context<'_>) -> Result<Vec<Attribute>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        let attributes = loader.load_one(AttributesOf(self.id.clone())).await?;
+
+        Ok(attributes.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::api_config::ApiConfig, utils::event_bus::EventBus};
+    use aptos_indexer_processor_sdk::postgres::utils::database::new_db_pool;
+
+    #[test]
+    fn test_schema_exposes_nested_relations() {
+        let sdl = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+            .finish()
+            .sdl();
+
+        for field in [
+            "collection: Collection",
+            "listings: [Listing!]!",
+            "attributes: [Attribute!]!",
+            "nfts(filter: NftFilter, after: String, first: Int): NftConnection!",
+        ] {
+            assert!(sdl.contains(field), "missing `{field}` in schema");
+        }
+    }
+
+    /// Runs against the database of `DATABASE_URL`, e.g.
+    /// `DATABASE_URL=postgresql://localhost:5432/nft cargo test --features graphql -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_query_nfts_against_local_postgres() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let db_pool = new_db_pool(&url, Some(2)).await.unwrap();
+        let schema = build_schema(Arc::new(ApiState {
+            config: ApiConfig::default(),
+            db_pool,
+            event_bus: EventBus::default(),
+        }));
+
+        let response = schema
+            .execute(
+                r#"{
+                    nfts(first: 2, filter: { traits: [{ attrType: "eyes", value: "blue" }] }) {
+                        pageInfo { hasNextPage endCursor }
+                        edges { node { id collection { id } listings { price } attributes { value } } }
+                    }
+                }"#,
+            )
+            .await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+    }
+}
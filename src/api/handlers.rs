@@ -0,0 +1,299 @@
+use super::{
+    pagination::{ListParams, Page},
+    ApiState,
+};
+use crate::models::db::{
+    action::Action,
+    bid::Bid,
+    collection::Collection,
+    collection_stats::CollectionStats,
+    listing::Listing,
+    market_depth::{MarketDepth, COLLECTION_DEPTH_TYPE, NFT_DEPTH_TYPE},
+    nft::Nft,
+    sale_fee::SaleFee,
+};
+use aptos_indexer_processor_sdk::{
+    postgres::utils::database::DbPoolConnection, utils::convert::standardize_address,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::error;
+
+pub type ApiResult<T> = Result<Json<T>, ApiError>;
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Internal(String),
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(e: diesel::result::Error) -> Self {
+        error!("Api query failed: {:?}", e);
+        ApiError::Internal("Failed to query the database".to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            ApiError::Internal(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+        };
+
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionDetails {
+    #[serde(flatten)]
+    pub collection: Collection,
+    pub stats: Option<CollectionStats>,
+}
+
+async fn get_conn(state: &ApiState) -> Result<DbPoolConnection<'_>, ApiError> {
+    state.db_pool.get().await.map_err(|e| {
+        error!("Failed to get database connection: {:?}", e);
+        ApiError::Internal("Database is unavailable".to_string())
+    })
+}
+
+pub async fn get_collection(
+    State(state): State<Arc<ApiState>>,
+    Path(collection_id): Path<String>,
+) -> ApiResult<CollectionDetails> {
+    let collection_id = standardize_address(&collection_id);
+    let mut conn = get_conn(&state).await?;
+
+    let collection = Collection::get_by_id(&mut conn, &collection_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Collection {collection_id} not found")))?;
+    let stats = CollectionStats::get_by_collection_id(&mut conn, &collection_id).await?;
+
+    Ok(Json(CollectionDetails { collection, stats }))
+}
+
+pub async fn get_collection_nfts(
+    State(state): State<Arc<ApiState>>,
+    Path(collection_id): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<Nft>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let nfts = Nft::get_by_collection(
+        &mut conn,
+        &standardize_address(&collection_id),
+        page_size,
+        Page::<Nft>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(nfts, page, page_size)))
+}
+
+pub async fn get_collection_listings(
+    State(state): State<Arc<ApiState>>,
+    Path(collection_id): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<Listing>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let listings = Listing::get_active_by_collection(
+        &mut conn,
+        &standardize_address(&collection_id),
+        page_size,
+        Page::<Listing>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(listings, page, page_size)))
+}
+
+pub async fn get_collection_bids(
+    State(state): State<Arc<ApiState>>,
+    Path(collection_id): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<Bid>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let bids = Bid::get_by_collection(
+        &mut conn,
+        &standardize_address(&collection_id),
+        params.status.as_deref(),
+        page_size,
+        Page::<Bid>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(bids, page, page_size)))
+}
+
+pub async fn get_nft_bids(
+    State(state): State<Arc<ApiState>>,
+    Path(nft_id): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<Bid>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let bids = Bid::get_by_nft(
+        &mut conn,
+        &standardize_address(&nft_id),
+        params.status.as_deref(),
+        page_size,
+        Page::<Bid>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(bids, page, page_size)))
+}
+
+pub async fn get_collection_depth(
+    State(state): State<Arc<ApiState>>,
+    Path(collection_id): Path<String>,
+) -> ApiResult<MarketDepth> {
+    let collection_id = standardize_address(&collection_id);
+    let mut conn = get_conn(&state).await?;
+
+    let depth = MarketDepth::get(&mut conn, COLLECTION_DEPTH_TYPE, &collection_id)
+        .await?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("No market depth for collection {collection_id}"))
+        })?;
+
+    Ok(Json(depth))
+}
+
+pub async fn get_nft_depth(
+    State(state): State<Arc<ApiState>>,
+    Path(nft_id): Path<String>,
+) -> ApiResult<MarketDepth> {
+    let nft_id = standardize_address(&nft_id);
+    let mut conn = get_conn(&state).await?;
+
+    if let Some(depth) = MarketDepth::get(&mut conn, NFT_DEPTH_TYPE, &nft_id).await? {
+        return Ok(Json(depth));
+    }
+
+    // Nfts without listings or solo bids only have the collection bids of their collection
+    let collection_id = Nft::get_by_id(&mut conn, &nft_id)
+        .await?
+        .and_then(|nft| nft.collection_id)
+        .ok_or_else(|| ApiError::NotFound(format!("Nft {nft_id} not found")))?;
+    let depth = MarketDepth::get(&mut conn, COLLECTION_DEPTH_TYPE, &collection_id)
+        .await?
+        .map(|collection_depth| MarketDepth::from_collection_depth(&nft_id, collection_depth))
+        .ok_or_else(|| ApiError::NotFound(format!("No market depth for nft {nft_id}")))?;
+
+    Ok(Json(depth))
+}
+
+pub async fn get_collection_sale_fees(
+    State(state): State<Arc<ApiState>>,
+    Path(collection_id): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<SaleFee>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let sale_fees = SaleFee::get_by_collection(
+        &mut conn,
+        &standardize_address(&collection_id),
+        page_size,
+        Page::<SaleFee>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(sale_fees, page, page_size)))
+}
+
+pub async fn get_nft_actions(
+    State(state): State<Arc<ApiState>>,
+    Path(nft_id): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<Action>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let actions = Action::get_by_nft(
+        &mut conn,
+        &standardize_address(&nft_id),
+        params.exclude_flagged.unwrap_or(false),
+        page_size,
+        Page::<Action>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(actions, page, page_size)))
+}
+
+pub async fn get_wallet_nfts(
+    State(state): State<Arc<ApiState>>,
+    Path(address): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<Nft>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let nfts = Nft::get_by_owner(
+        &mut conn,
+        &standardize_address(&address),
+        page_size,
+        Page::<Nft>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(nfts, page, page_size)))
+}
+
+pub async fn get_wallet_actions(
+    State(state): State<Arc<ApiState>>,
+    Path(address): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<Action>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let actions = Action::get_by_wallet(
+        &mut conn,
+        &standardize_address(&address),
+        params.exclude_flagged.unwrap_or(false),
+        page_size,
+        Page::<Action>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(actions, page, page_size)))
+}
+
+pub async fn get_wallet_royalties(
+    State(state): State<Arc<ApiState>>,
+    Path(address): Path<String>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<Page<SaleFee>> {
+    let (page, page_size) = params.page(&state.config);
+    let mut conn = get_conn(&state).await?;
+
+    let sale_fees = SaleFee::get_by_royalty_payee(
+        &mut conn,
+        &standardize_address(&address),
+        page_size,
+        Page::<SaleFee>::offset(page, page_size),
+    )
+    .await?;
+
+    Ok(Json(Page::new(sale_fees, page, page_size)))
+}
@@ -0,0 +1,103 @@
+use crate::{
+    config::api_config::ApiConfig, postgres::postgres_utils::ArcDbPool, utils::event_bus::EventBus,
+};
+use anyhow::Context;
+use axum::{routing::get, Router};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::net::TcpListener;
+use tracing::info;
+
+pub mod events;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod handlers;
+pub mod pagination;
+
+pub struct ApiState {
+    pub config: ApiConfig,
+    pub db_pool: ArcDbPool,
+    pub event_bus: EventBus,
+}
+
+/// Read-only HTTP API over the indexed tables.
+pub struct ApiServer {
+    state: Arc<ApiState>,
+}
+
+impl ApiServer {
+    pub fn new(config: ApiConfig, db_pool: ArcDbPool, event_bus: EventBus) -> Self {
+        Self {
+            state: Arc::new(ApiState {
+                config,
+                db_pool,
+                event_bus,
+            }),
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        let router = Router::new()
+            .route("/collections/:collection_id", get(handlers::get_collection))
+            .route(
+                "/collections/:collection_id/nfts",
+                get(handlers::get_collection_nfts),
+            )
+            .route(
+                "/collections/:collection_id/listings",
+                get(handlers::get_collection_listings),
+            )
+            .route(
+                "/collections/:collection_id/bids",
+                get(handlers::get_collection_bids),
+            )
+            .route(
+                "/collections/:collection_id/depth",
+                get(handlers::get_collection_depth),
+            )
+            .route(
+                "/collections/:collection_id/fees",
+                get(handlers::get_collection_sale_fees),
+            )
+            .route("/events", get(events::stream_events))
+            .route("/nfts/:nft_id/bids", get(handlers::get_nft_bids))
+            .route("/nfts/:nft_id/depth", get(handlers::get_nft_depth))
+            .route("/nfts/:nft_id/actions", get(handlers::get_nft_actions))
+            .route("/wallets/:address/nfts", get(handlers::get_wallet_nfts))
+            .route(
+                "/wallets/:address/actions",
+                get(handlers::get_wallet_actions),
+            )
+            .route(
+                "/wallets/:address/royalties",
+                get(handlers::get_wallet_royalties),
+            )
+            .with_state(self.state.clone());
+
+        self.with_graphql(router)
+    }
+
+    #[cfg(feature = "graphql")]
+    fn with_graphql(&self, router: Router) -> Router {
+        router.route_service(
+            "/graphql",
+            async_graphql_axum::GraphQL::new(graphql::build_schema(self.state.clone())),
+        )
+    }
+
+    #[cfg(not(feature = "graphql"))]
+    fn with_graphql(&self, router: Router) -> Router {
+        router
+    }
+
+    pub async fn start(&self) -> anyhow::Result<()> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], self.state.config.port));
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind the api server to {addr}"))?;
+
+        info!("Api server is listening on {}", addr);
+        axum::serve(listener, self.router()).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use crate::config::api_config::ApiConfig;
+use serde::{Deserialize, Serialize};
+
+/// Query parameters shared by the list endpoints. Pages start at 1.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ListParams {
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+    /// Only used by the bid endpoints, e.g. `active`
+    pub status: Option<String>,
+    /// Only used by the action endpoints, leaves out the sales flagged as wash trades
+    pub exclude_flagged: Option<bool>,
+}
+
+impl ListParams {
+    /// Returns the page number and the page size clamped to the configured bounds.
+    pub fn page(&self, config: &ApiConfig) -> (i64, i64) {
+        let page = self.page.unwrap_or(1).max(1);
+        let page_size = self
+            .page_size
+            .unwrap_or(config.default_page_size)
+            .clamp(1, config.max_page_size.max(1));
+
+        (page, page_size)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+impl<T> Page<T> {
+    pub fn new(data: Vec<T>, page: i64, page_size: i64) -> Self {
+        Self {
+            data,
+            page,
+            page_size,
+        }
+    }
+
+    pub fn offset(page: i64, page_size: i64) -> i64 {
+        (page - 1).saturating_mul(page_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_is_clamped() {
+        let config = ApiConfig::default();
+
+        let params = ListParams::default();
+        assert_eq!(params.page(&config), (1, config.default_page_size));
+
+        let params = ListParams {
+            page: Some(0),
+            page_size: Some(10_000),
+            status: None,
+            exclude_flagged: None,
+        };
+        assert_eq!(params.page(&config), (1, config.max_page_size));
+
+        let params = ListParams {
+            page: Some(3),
+            page_size: Some(25),
+            status: None,
+            exclude_flagged: None,
+        };
+        let (page, page_size) = params.page(&config);
+        assert_eq!(Page::<()>::offset(page, page_size), 50);
+    }
+}
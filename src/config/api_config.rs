@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Configuration of the embedded read-only HTTP API. The server only runs when the binary is
+/// built with the `api` feature.
+///
+/// Example:
+/// ```yaml
+/// api_config:
+///   port: 8081
+///   default_page_size: 20
+///   max_page_size: 100
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub port: u16,
+    /// Page size used when the request doesn't specify one
+    pub default_page_size: i64,
+    /// Upper bound of the page size a request can ask for
+    pub max_page_size: i64,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            port: 8081,
+            default_page_size: 20,
+            max_page_size: 100,
+        }
+    }
+}
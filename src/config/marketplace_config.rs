@@ -12,7 +12,7 @@ use diesel::{
     sql_types::Text,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, io::Write};
+use std::{collections::HashMap, fmt, io::Write};
 use strum::{Display, EnumString};
 
 // event_type -> json_path, db_column
@@ -30,24 +30,71 @@ pub type ResourceRemappingConfig = HashMap<String, ResourceRemapping>;
 pub struct DbColumn {
     pub table: String,
     pub column: String,
+    /// Paths tried in order when the json path of the mapping matches nothing
+    #[serde(default)]
+    pub fallback_paths: Vec<HashableJsonPath>,
+    /// Applied in order to the extracted value before it's written to the column
+    #[serde(default)]
+    pub transforms: Vec<ValueTransform>,
+}
+
+/// Step turning the value extracted by a json path into the value of its column.
+///
+/// Paths of a transform are evaluated against the data of the same event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ValueTransform {
+    /// Takes the value out of a Move `Option`, i.e. the first element of its `vec`. A `none`
+    /// leaves the column empty
+    UnwrapOption,
+    /// Multiplies the value by the number at `path`, e.g. the unit price by the amount
+    Multiply { path: HashableJsonPath },
+    /// Divides the value by `by` rounding down, e.g. `1000` for milliseconds to seconds
+    Divide { by: u64 },
+    /// Appends the values at `paths` to the value, joined by `separator`
+    Concat {
+        paths: Vec<HashableJsonPath>,
+        #[serde(default = "default_concat_separator")]
+        separator: String,
+    },
+    /// Normalizes an address to its 0x prefixed 64 hex digit form
+    StandardizeAddress,
+}
+
+fn default_concat_separator() -> String {
+    "::".to_string()
 }
 
 /// Represents a marketplace and its configuration
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct NFTMarketplaceConfig {
     pub name: String,
+    #[serde(default)]
     pub contract_address: String,
     /// Maps event type strings to their corresponding MarketplaceEventType enum values.
     /// This mapping is used to standardize different marketplace event types across
     /// different NFT marketplaces into a standarzied event types for processing.
     /// For example, a "ListNFT" event from one marketplace might map to List
     /// while another marketplace's "CreateListing" event would also map to List.
+    /// Generic events whose meaning depends on a field are mapped by rules instead.
     #[serde(default)]
-    pub event_model_mapping: HashMap<String, MarketplaceEventType>,
+    pub event_model_mapping: HashMap<String, EventModelMapping>,
     #[serde(default)]
     pub events: EventRemappingConfig,
     #[serde(default)]
     pub resources: ResourceRemappingConfig,
+    /// How cancel and fill events of collection offers are matched back to the offer they
+    /// close when the events don't carry a collection offer id.
+    #[serde(default)]
+    pub collection_offer_matching: CollectionOfferMatching,
+    /// Fees the marketplace charges on sales, used for the fee breakdown of sales whose coin
+    /// deposits can't be attributed.
+    #[serde(default)]
+    pub fee_schedule: Option<FeeSchedule>,
+    /// Contracts of a marketplace that was upgraded to new addresses. When set, they replace
+    /// `contract_address`, `event_model_mapping` and `events`.
+    #[serde(default)]
+    pub deployments: Vec<ContractDeployment>,
 }
 
 impl NFTMarketplaceConfig {
@@ -57,6 +104,84 @@ impl NFTMarketplaceConfig {
         // This is acceptable since processor names live for the application lifetime
         Box::leak(self.name.clone().into_boxed_str())
     }
+
+    /// Splits the marketplace into its deployments, each with a config of its contract only.
+    /// A marketplace without `deployments` is a single deployment checkpointed by its name.
+    pub fn get_deployments(&self) -> Vec<MarketplaceDeployment> {
+        if self.deployments.is_empty() {
+            return vec![MarketplaceDeployment {
+                checkpoint_name: self.name.clone(),
+                starting_version: None,
+                ending_version: None,
+                config: self.clone(),
+            }];
+        }
+
+        self.deployments
+            .iter()
+            .map(|deployment| MarketplaceDeployment {
+                checkpoint_name: deployment.name.as_ref().map_or_else(
+                    || self.name.clone(),
+                    |name| format!("{}_{}", self.name, name),
+                ),
+                starting_version: deployment.starting_version,
+                ending_version: deployment.ending_version,
+                config: NFTMarketplaceConfig {
+                    contract_address: deployment.contract_address.clone(),
+                    event_model_mapping: deployment.event_model_mapping.clone(),
+                    events: deployment.events.clone(),
+                    deployments: vec![],
+                    ..self.clone()
+                },
+            })
+            .collect()
+    }
+}
+
+/// A contract of a marketplace with its own events and the versions it's indexed in.
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct ContractDeployment {
+    /// Checkpoint of the deployment is `{marketplace}_{name}`, or the name of the marketplace
+    /// when omitted so that an existing marketplace keeps its progress
+    #[serde(default)]
+    pub name: Option<String>,
+    pub contract_address: String,
+    #[serde(default)]
+    pub event_model_mapping: HashMap<String, EventModelMapping>,
+    #[serde(default)]
+    pub events: EventRemappingConfig,
+    /// First txn version the deployment is indexed from, e.g. when the contract was published
+    #[serde(default)]
+    pub starting_version: Option<u64>,
+    /// Last txn version the deployment is indexed to, e.g. once the contract is retired
+    #[serde(default)]
+    pub ending_version: Option<u64>,
+}
+
+/// A deployment of a marketplace, streamed and checkpointed on its own while its activities
+/// are attributed to the marketplace.
+#[derive(Clone, Debug)]
+pub struct MarketplaceDeployment {
+    /// Processor status the progress of the deployment is saved in
+    pub checkpoint_name: String,
+    pub starting_version: Option<u64>,
+    pub ending_version: Option<u64>,
+    /// The marketplace config with the contract and events of the deployment only
+    pub config: NFTMarketplaceConfig,
+}
+
+impl MarketplaceDeployment {
+    /// Whether both deployments are active at some version
+    pub fn overlaps(&self, other: &Self) -> bool {
+        let starts_before_end = |deployment: &Self, other: &Self| {
+            deployment
+                .starting_version
+                .zip(other.ending_version)
+                .map_or(true, |(starting, ending)| starting <= ending)
+        };
+
+        starts_before_end(self, other) && starts_before_end(other, self)
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -64,11 +189,98 @@ pub struct EventRemapping {
     pub event_fields: HashMap<String, Vec<DbColumn>>,
 }
 
+/// Standardized event type of the events of a struct.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum EventModelMapping {
+    /// Every event of the struct has this type
+    Fixed(MarketplaceEventType),
+    /// An event has the type of the first rule it matches, events matching no rule are ignored
+    Rules(Vec<EventMappingRule>),
+}
+
+impl fmt::Display for EventModelMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventModelMapping::Fixed(event_type) => write!(f, "{event_type}"),
+            EventModelMapping::Rules(rules) => {
+                let event_types = rules
+                    .iter()
+                    .map(|rule| rule.event_type.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "{}", event_types.join(" or "))
+            },
+        }
+    }
+}
+
+/// Maps the events matching all the predicates of `when` to `event_type`, e.g. the
+/// `ListingEvent` with `$.kind == 2` to `unlist`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct EventMappingRule {
+    /// Matches every event when empty
+    #[serde(default)]
+    pub when: Vec<EventPredicate>,
+    pub event_type: MarketplaceEventType,
+}
+
+/// Condition on the value at `path` of the event data. Numbers and strings are compared by
+/// their string form, so `2` equals the `"2"` of a Move u64. A predicate without any condition
+/// checks that the path has a value.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct EventPredicate {
+    pub path: HashableJsonPath,
+    #[serde(default)]
+    pub equals: Option<serde_json::Value>,
+    #[serde(default)]
+    pub not_equals: Option<serde_json::Value>,
+    /// The value equals one of these
+    #[serde(default, rename = "in")]
+    pub one_of: Option<Vec<serde_json::Value>>,
+    /// Whether the path has a value
+    #[serde(default)]
+    pub exists: Option<bool>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ResourceRemapping {
     pub resource_fields: HashMap<String, Vec<DbColumn>>,
 }
 
+/// Fees charged by a marketplace on every sale.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FeeSchedule {
+    /// Marketplace fee in basis points of the price, e.g. 250 for 2.5%
+    #[serde(default)]
+    pub marketplace_fee_bps: i64,
+    /// Wallet the marketplace fee is paid to, tells the fee apart from the other deposits of
+    /// a sale transaction
+    #[serde(default)]
+    pub fee_receiver: Option<String>,
+}
+
+/// Rule used to correlate the events of a collection offer without an on-chain id.
+///
+/// The offer itself is identified by the txn version and event index of its creation, while
+/// its cancel and fill events are matched to the oldest open offer with the same match key.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "match_by", rename_all = "snake_case")]
+pub enum CollectionOfferMatching {
+    /// Offers match when all these activity fields are equal, e.g. `[buyer, collection_addr]`
+    EventFields { fields: Vec<String> },
+    /// Offers match on the address of the write set resource of this type, e.g. the offer
+    /// object created by the contract
+    ResourceAddress { resource_type: String },
+}
+
+impl Default for CollectionOfferMatching {
+    fn default() -> Self {
+        Self::EventFields {
+            fields: vec!["buyer".to_string(), "collection_addr".to_string()],
+        }
+    }
+}
+
 #[derive(
     Debug,
     Clone,
@@ -150,7 +362,7 @@ impl TryFrom<&str> for EventType {
         Ok(EventType {
             address: standardize_address(parts[0]),
             module: parts[1].to_string(),
-            r#struct: parts[2..].join("::"), // Don't need to standardize generics because we won't support them
+            r#struct: parts[2..].join("::"), // Generics aren't standardized, they're matched as emitted
         })
     }
 }
@@ -175,4 +387,118 @@ impl EventType {
     pub fn get_struct(&self) -> &str {
         &self.r#struct
     }
+
+    /// Returns the event type without its generic type arguments, which is how the events of
+    /// generic structs are configured.
+    pub fn without_type_args(&self) -> Self {
+        let (r#struct, _) = split_type_args(&self.r#struct);
+        EventType {
+            address: self.address.clone(),
+            module: self.module.clone(),
+            r#struct: r#struct.to_string(),
+        }
+    }
+
+    /// Returns the top level generic type arguments of the event type, e.g. the coin type of
+    /// `Listing<0x1::aptos_coin::AptosCoin>`.
+    pub fn get_type_args(&self) -> Vec<String> {
+        split_type_args(&self.r#struct).1
+    }
+}
+
+/// Splits a struct name into its name and its top level generic type arguments.
+fn split_type_args(struct_name: &str) -> (&str, Vec<String>) {
+    let Some(start) = struct_name.find('<') else {
+        return (struct_name, vec![]);
+    };
+    let inner = &struct_name[start + 1..];
+    let inner = inner.strip_suffix('>').unwrap_or(inner);
+
+    let mut type_args = vec![];
+    let mut depth = 0;
+    let mut arg_start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                type_args.push(inner[arg_start..i].trim().to_string());
+                arg_start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    let last = inner[arg_start..].trim();
+    if !last.is_empty() {
+        type_args.push(last.to_string());
+    }
+
+    (&struct_name[..start], type_args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_type_args() {
+        let event_type = EventType::try_from(
+            "0x1::market::Listing<0x1::aptos_coin::AptosCoin, 0x2::pair::Pair<0x3::a::A, u64>>",
+        )
+        .unwrap();
+
+        assert_eq!(event_type.get_type_args(), vec![
+            "0x1::aptos_coin::AptosCoin".to_string(),
+            "0x2::pair::Pair<0x3::a::A, u64>".to_string(),
+        ]);
+        assert_eq!(
+            event_type.without_type_args(),
+            EventType::try_from("0x1::market::Listing").unwrap()
+        );
+
+        let event_type = EventType::try_from("0x1::market::Listing").unwrap();
+        assert!(event_type.get_type_args().is_empty());
+    }
+
+    #[test]
+    fn test_get_deployments() {
+        let config: NFTMarketplaceConfig = serde_yaml::from_str(
+            r#"
+name: tradeport
+fee_schedule:
+  marketplace_fee_bps: 150
+deployments:
+  - contract_address: "0x1"
+    ending_version: 100
+    event_model_mapping:
+      listings::ListEvent: list
+  - name: v2
+    contract_address: "0x2"
+    starting_version: 90
+"#,
+        )
+        .unwrap();
+
+        let deployments = config.get_deployments();
+        assert_eq!(deployments.len(), 2);
+
+        // The first deployment keeps the checkpoint of the marketplace
+        assert_eq!(deployments[0].checkpoint_name, "tradeport");
+        assert_eq!(deployments[0].ending_version, Some(100));
+        assert_eq!(deployments[0].config.contract_address, "0x1");
+        assert_eq!(deployments[0].config.event_model_mapping.len(), 1);
+
+        assert_eq!(deployments[1].checkpoint_name, "tradeport_v2");
+        assert_eq!(deployments[1].starting_version, Some(90));
+        assert_eq!(deployments[1].config.name, "tradeport");
+        assert!(deployments[1].config.event_model_mapping.is_empty());
+        assert_eq!(
+            deployments[1]
+                .config
+                .fee_schedule
+                .as_ref()
+                .map(|fees| fees.marketplace_fee_bps),
+            Some(150)
+        );
+    }
 }
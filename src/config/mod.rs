@@ -3,16 +3,25 @@
 
 use crate::{config::marketplace_config::NFTMarketplaceConfig, processor::Processor};
 use anyhow::Result;
+use api_config::ApiConfig;
 use aptos_indexer_processor_sdk::{
     aptos_indexer_transaction_stream::TransactionStreamConfig,
     postgres::subconfigs::postgres_config::PostgresConfig, server_framework::RunnableConfig,
     traits::processor_trait::ProcessorTrait,
 };
+use payment_token_config::PaymentTokenConfig;
 use processor_mode::ProcessorMode;
 use serde::{Deserialize, Serialize};
+use webhook_config::WebhookConfig;
+use worker_config::{AttributeWorkerConfig, WashTradeWorkerConfig, WebhookWorkerConfig};
 
+pub mod api_config;
 pub mod marketplace_config;
+pub mod payment_token_config;
 pub mod processor_mode;
+pub mod validation;
+pub mod webhook_config;
+pub mod worker_config;
 pub const QUERY_DEFAULT_RETRIES: u32 = 5;
 pub const QUERY_DEFAULT_RETRY_DELAY_MS: u64 = 500;
 
@@ -24,6 +33,20 @@ pub struct IndexerProcessorConfig {
     pub db_config: DbConfig,
     pub processor_mode: ProcessorMode,
     pub nft_marketplace_configs: Vec<NFTMarketplaceConfig>,
+    #[serde(default)]
+    pub attribute_worker_config: AttributeWorkerConfig,
+    /// Serves the indexed tables over HTTP when set
+    #[serde(default)]
+    pub api_config: Option<ApiConfig>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub webhook_worker_config: WebhookWorkerConfig,
+    #[serde(default)]
+    pub wash_trade_worker_config: WashTradeWorkerConfig,
+    /// Coins and fungible assets sales may be paid in, besides APT
+    #[serde(default)]
+    pub payment_tokens: Vec<PaymentTokenConfig>,
 }
 
 #[async_trait::async_trait]
@@ -0,0 +1,44 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+/// A coin or fungible asset sales may be paid in. Tokens from the config are written to the
+/// `payment_tokens` table on startup, APT is always known.
+///
+/// Example:
+/// ```yaml
+/// payment_tokens:
+///   - id: 0x1::aptos_coin::AptosCoin
+///     symbol: APT
+///     decimals: 8
+///     price_source:
+///       source: tapp
+///       index: 0x000000000000000000000000000000000000000000000000000000000000000a_usd
+///   - id: 0xbae207659db88bea0cbead6da0ed00aac12edcdda169e591cd41c94180b46f3b
+///     symbol: USDC
+///     decimals: 6
+///     price_source:
+///       source: fixed
+///       usd_price: 1
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PaymentTokenConfig {
+    /// Coin type or fungible asset metadata address
+    pub id: String,
+    #[serde(default)]
+    pub symbol: Option<String>,
+    pub decimals: i32,
+    /// Unset leaves the sales paid in this token without a usd price
+    #[serde(default)]
+    pub price_source: Option<PriceSourceConfig>,
+}
+
+/// Where the USD price of a payment token is polled from.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum PriceSourceConfig {
+    /// Index price served by the JSON-RPC endpoint at `tapp_url`
+    Tapp { index: String },
+    /// Constant price, e.g. 1 for a USD stablecoin
+    Fixed { usd_price: BigDecimal },
+}
@@ -0,0 +1,471 @@
+use crate::{
+    config::{
+        marketplace_config::{
+            CollectionOfferMatching, DbColumn, EventModelMapping, EventType, NFTMarketplaceConfig,
+            ValueTransform,
+        },
+        IndexerProcessorConfig,
+    },
+    models::marketplace::{MarketplaceField, MarketplaceModel, NftMarketplaceActivity},
+    steps::marketplace::{remapper_step::ProcessStep, remappers::TableType, HashableJsonPath},
+};
+use anyhow::Result;
+use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::Transaction;
+use std::{collections::HashSet, fmt, str::FromStr};
+use strum::{Display, IntoEnumIterator};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Severity {
+    /// Events or fields of the marketplace are lost or fail the processor
+    Error,
+    /// Part of the config has no effect
+    Warning,
+}
+
+/// A mistake in a marketplace config that serde doesn't catch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub marketplace: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}] {}",
+            self.severity, self.marketplace, self.message
+        )
+    }
+}
+
+/// Collects the issues of one marketplace config
+struct Issues<'a> {
+    marketplace: &'a str,
+    issues: Vec<ConfigIssue>,
+}
+
+impl Issues<'_> {
+    fn push(&mut self, severity: Severity, message: String) {
+        self.issues.push(ConfigIssue {
+            marketplace: self.marketplace.to_string(),
+            severity,
+            message,
+        });
+    }
+
+    fn error(&mut self, message: String) {
+        self.push(Severity::Error, message);
+    }
+
+    fn warning(&mut self, message: String) {
+        self.push(Severity::Warning, message);
+    }
+}
+
+/// Checks every marketplace config of the processor config.
+pub fn validate_config(config: &IndexerProcessorConfig) -> Vec<ConfigIssue> {
+    let mut issues = vec![];
+    let mut names = HashSet::new();
+    for marketplace in &config.nft_marketplace_configs {
+        if !names.insert(marketplace.name.as_str()) {
+            issues.push(ConfigIssue {
+                marketplace: marketplace.name.clone(),
+                severity: Severity::Error,
+                message: "name is used by several marketplaces, which then share their \
+                          processor status"
+                    .to_string(),
+            });
+        }
+        issues.extend(validate_marketplace_config(marketplace));
+    }
+
+    issues
+}
+
+/// Checks that every event of the config is mapped to a marketplace event type and every json
+/// path is mapped to a table and column the remappers know.
+pub fn validate_marketplace_config(config: &NFTMarketplaceConfig) -> Vec<ConfigIssue> {
+    let mut issues = Issues {
+        marketplace: &config.name,
+        issues: vec![],
+    };
+
+    if config.name.is_empty() {
+        issues.error("name is empty".to_string());
+    }
+
+    if !config.deployments.is_empty()
+        && (!config.contract_address.is_empty()
+            || !config.events.is_empty()
+            || !config.event_model_mapping.is_empty())
+    {
+        issues.warning(
+            "contract_address, event_model_mapping and events are ignored, the marketplace is \
+             configured by its deployments"
+                .to_string(),
+        );
+    }
+
+    let mut checkpoint_names = HashSet::new();
+    let deployments = config.get_deployments();
+    for (index, deployment) in deployments.iter().enumerate() {
+        // Issues of a deployment are reported under its checkpoint name
+        let mut deployment_issues = Issues {
+            marketplace: &deployment.checkpoint_name,
+            issues: vec![],
+        };
+        if !checkpoint_names.insert(deployment.checkpoint_name.clone()) {
+            deployment_issues.error(
+                "checkpoint is used by several deployments, give them distinct names".to_string(),
+            );
+        }
+        if deployment.config.contract_address.is_empty() {
+            deployment_issues.error("contract_address is empty".to_string());
+        }
+        if let (Some(starting_version), Some(ending_version)) =
+            (deployment.starting_version, deployment.ending_version)
+        {
+            if starting_version > ending_version {
+                deployment_issues.error(format!(
+                    "starting_version {starting_version} is after ending_version \
+                     {ending_version}, nothing is indexed"
+                ));
+            }
+        }
+        // An upgraded contract keeps its address, its events are remapped by the deployment
+        // live at their version
+        for other in &deployments[..index] {
+            if other.config.contract_address == deployment.config.contract_address
+                && other.overlaps(deployment)
+            {
+                deployment_issues.error(format!(
+                    "versions overlap with {}, which has the same contract_address, give them \
+                     distinct version ranges",
+                    other.checkpoint_name
+                ));
+            }
+        }
+        check_events(&mut deployment_issues, &deployment.config);
+        issues.issues.extend(deployment_issues.issues);
+    }
+
+    for (resource_type, resource_remapping) in &config.resources {
+        for (json_path, db_columns) in &resource_remapping.resource_fields {
+            let location = format!("resource {resource_type} path {json_path}");
+            if let Err(e) = HashableJsonPath::new(json_path) {
+                issues.error(format!("{location}: invalid json path: {e}"));
+            }
+            // Resource fields are merged into the activities by column only
+            for db_column in db_columns {
+                if let Err(e) = MarketplaceField::from_str(&db_column.column) {
+                    issues.error(format!(
+                        "{location}: unknown column {}: {e}",
+                        db_column.column
+                    ));
+                }
+                if !db_column.fallback_paths.is_empty() || !db_column.transforms.is_empty() {
+                    issues.warning(format!(
+                        "{location}: fallback_paths and transforms of {} are ignored, they only \
+                         apply to event_fields",
+                        db_column.column
+                    ));
+                }
+            }
+        }
+    }
+
+    if let CollectionOfferMatching::EventFields { fields } = &config.collection_offer_matching {
+        for field in fields {
+            if let Err(e) = MarketplaceField::from_str(field) {
+                issues.error(format!(
+                    "collection_offer_matching: unknown field {field}: {e}"
+                ));
+            }
+        }
+    }
+
+    issues.issues
+}
+
+/// Checks the events of the contract of a deployment.
+fn check_events(issues: &mut Issues, config: &NFTMarketplaceConfig) {
+    for (event_key, event_remapping) in &config.events {
+        let event_type = format!("{}::{}", config.contract_address, event_key);
+        if let Err(e) = EventType::try_from(event_type.as_str()) {
+            issues.error(format!("event {event_key}: {e}"));
+        }
+        if !config.event_model_mapping.contains_key(event_key) {
+            issues.error(format!(
+                "event {event_key} is missing from event_model_mapping, its events are ignored"
+            ));
+        }
+
+        for (json_path, db_columns) in &event_remapping.event_fields {
+            let location = format!("event {event_key} path {json_path}");
+            if let Err(e) = HashableJsonPath::new(json_path) {
+                issues.error(format!("{location}: invalid json path: {e}"));
+            }
+            for db_column in db_columns {
+                check_event_column(issues, &location, db_column);
+            }
+        }
+    }
+
+    for (event_key, event_type) in &config.event_model_mapping {
+        if !config.events.contains_key(event_key) {
+            issues.warning(format!(
+                "event {event_key} is mapped to {event_type} but has no event_fields, its events \
+                 are ignored"
+            ));
+        }
+        if let EventModelMapping::Rules(rules) = event_type {
+            if rules.is_empty() {
+                issues.warning(format!(
+                    "event {event_key} has no event_model_mapping rules, its events are ignored"
+                ));
+            }
+            // Rules are tried in order, a rule without predicates matches every event
+            if let Some(position) = rules.iter().position(|rule| rule.when.is_empty()) {
+                if position + 1 < rules.len() {
+                    issues.warning(format!(
+                        "event {event_key}: the event_model_mapping rules after rule {} are never \
+                         reached, it has no predicates",
+                        position + 1
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn check_event_column(issues: &mut Issues, location: &str, db_column: &DbColumn) {
+    match TableType::from_str(&db_column.table) {
+        Some(TableType::Activities) => {
+            if let Err(e) = MarketplaceField::from_str(&db_column.column) {
+                issues.error(format!(
+                    "{location}: unknown column {}.{}: {e}",
+                    db_column.table, db_column.column
+                ));
+            }
+        },
+        Some(_) => issues.warning(format!(
+            "{location}: {}.{} is ignored, only the columns of the activities are remapped",
+            db_column.table, db_column.column
+        )),
+        None => issues.error(format!("{location}: unknown table {}", db_column.table)),
+    }
+
+    for transform in &db_column.transforms {
+        if let ValueTransform::Divide { by: 0 } = transform {
+            issues.error(format!(
+                "{location}: {} is divided by zero",
+                db_column.column
+            ));
+        }
+    }
+}
+
+/// Remaps a fixture transaction with a marketplace config and describes what each of its
+/// mapped events produces: the fields of its activity, or why it was dead-lettered.
+pub fn remap_fixture(
+    config: &NFTMarketplaceConfig,
+    transaction: &Transaction,
+) -> Result<Vec<String>> {
+    let process_step = ProcessStep::new(config.clone())?;
+    let (activities, dead_letters) = process_step.remap_transaction(transaction)?;
+
+    let mut lines = activities
+        .iter()
+        .map(|activity| {
+            (
+                activity.txn_version,
+                activity.index,
+                describe_activity(activity),
+            )
+        })
+        .chain(dead_letters.iter().map(|dead_letter| {
+            let mut line = format!(
+                "{} dead-lettered: {}",
+                dead_letter.event_type, dead_letter.error
+            );
+            if !dead_letter.json_path.is_empty() {
+                line.push_str(&format!(", failed paths: {}", dead_letter.json_path));
+            }
+            (dead_letter.txn_version, dead_letter.event_index, line)
+        }))
+        .collect::<Vec<_>>();
+    lines.sort_by_key(|(version, index, _)| (*version, *index));
+
+    Ok(lines
+        .into_iter()
+        .map(|(version, index, line)| format!("[{}] {version}/{index} {line}", config.name))
+        .collect())
+}
+
+fn describe_activity(activity: &NftMarketplaceActivity) -> String {
+    let fields = MarketplaceField::iter()
+        .filter_map(|field| {
+            activity
+                .get_field(field.clone())
+                .map(|value| format!("{field}={value}"))
+        })
+        .collect::<Vec<_>>();
+    let mut description = format!(
+        "{} -> {}: {}",
+        activity.raw_event_type,
+        activity.standard_event_type,
+        fields.join(" ")
+    );
+    for field_error in &activity.field_errors {
+        if field_error.value.is_empty() {
+            description.push_str(&format!("\n  {}: {}", field_error.field, field_error.error));
+        } else {
+            description.push_str(&format!(
+                "\n  {} {:?} couldn't be parsed: {}",
+                field_error.field, field_error.value, field_error.error
+            ));
+        }
+    }
+
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marketplace_config(config: serde_json::Value) -> NFTMarketplaceConfig {
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn test_validate_marketplace_config() {
+        let config = marketplace_config(serde_json::json!({
+            "name": "market",
+            "contract_address": "0x1",
+            "event_model_mapping": {
+                "market::ListEvent": "list",
+                "market::BuyEvent": "buy",
+                "market::OrderEvent": [
+                    { "event_type": "list" },
+                    { "when": [{ "path": "$.kind", "equals": 2 }], "event_type": "unlist" }
+                ]
+            },
+            "events": {
+                "market::ListEvent": {
+                    "event_fields": {
+                        "$.price": [
+                            { "table": "nft_marketplace_activities", "column": "price" },
+                            { "table": "current_nft_marketplace_listings", "column": "price" }
+                        ],
+                        "$.token": [
+                            { "table": "nft_marketplace_activities", "column": "token_data_id" }
+                        ],
+                        "$.expiration_ms": [
+                            {
+                                "table": "nft_marketplace_activities",
+                                "column": "expiration_time",
+                                "transforms": [{ "type": "divide", "by": 0 }]
+                            }
+                        ]
+                    }
+                },
+                "market::DelistEvent": {
+                    "event_fields": {
+                        "$.seller": [{ "table": "listings", "column": "seller" }]
+                    }
+                }
+            }
+        }));
+
+        let mut issues = validate_marketplace_config(&config)
+            .into_iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        issues.sort();
+        assert_eq!(
+            issues,
+            vec![
+            "error [market] event market::DelistEvent is missing from event_model_mapping, its \
+             events are ignored",
+            "error [market] event market::DelistEvent path $.seller: unknown table listings",
+            "error [market] event market::ListEvent path $.expiration_ms: expiration_time is \
+             divided by zero",
+            "error [market] event market::ListEvent path $.token: unknown column \
+             nft_marketplace_activities.token_data_id: Matching variant not found",
+            "warning [market] event market::BuyEvent is mapped to buy but has no event_fields, \
+             its events are ignored",
+            "warning [market] event market::ListEvent path $.price: \
+             current_nft_marketplace_listings.price is ignored, only the columns of the \
+             activities are remapped",
+            "warning [market] event market::OrderEvent is mapped to list or unlist but has no \
+             event_fields, its events are ignored",
+            "warning [market] event market::OrderEvent: the event_model_mapping rules after rule \
+             1 are never reached, it has no predicates",
+        ]
+        );
+    }
+
+    #[test]
+    fn test_validate_deployments() {
+        let config = marketplace_config(serde_json::json!({
+            "name": "market",
+            "deployments": [
+                {
+                    "contract_address": "0x1",
+                    "event_model_mapping": { "market::ListEvent": "list" }
+                },
+                {
+                    "name": "v2",
+                    "contract_address": "0x2",
+                    "starting_version": 10,
+                    "ending_version": 5
+                },
+                { "name": "v2", "contract_address": "" }
+            ]
+        }));
+
+        let mut issues = validate_marketplace_config(&config)
+            .into_iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        issues.sort();
+        assert_eq!(
+            issues,
+            vec![
+            "error [market_v2] checkpoint is used by several deployments, give them distinct names",
+            "error [market_v2] contract_address is empty",
+            "error [market_v2] starting_version 10 is after ending_version 5, nothing is indexed",
+            "warning [market] event market::ListEvent is mapped to list but has no event_fields, \
+             its events are ignored",
+        ]
+        );
+    }
+
+    #[test]
+    fn test_validate_upgraded_deployments() {
+        let config = marketplace_config(serde_json::json!({
+            "name": "market",
+            "deployments": [
+                { "contract_address": "0x1", "ending_version": 99 },
+                { "name": "v2", "contract_address": "0x1", "starting_version": 100 },
+                { "name": "v3", "contract_address": "0x1", "starting_version": 200 }
+            ]
+        }));
+
+        let issues = validate_marketplace_config(&config)
+            .into_iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                "error [market_v3] versions overlap with market_v2, which has the same \
+                 contract_address, give them distinct version ranges"
+            ]
+        );
+    }
+}
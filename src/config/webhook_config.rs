@@ -0,0 +1,40 @@
+use crate::config::marketplace_config::MarketplaceEventType;
+use serde::{Deserialize, Serialize};
+
+/// An endpoint notified of the stored actions. Webhooks from the config are written to the
+/// `webhooks` table on startup; more can be registered directly in that table.
+///
+/// Example:
+/// ```yaml
+/// webhooks:
+///   - id: sales-bot
+///     url: https://example.com/hooks/nft
+///     secret: change-me
+///     marketplaces: ["tradeport"]
+///     event_types: ["buy", "accept_bid", "accept_collection_bid"]
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub id: String,
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature of the payloads
+    pub secret: String,
+    /// Unset matches every collection
+    #[serde(default)]
+    pub collection_ids: Option<Vec<String>>,
+    /// Unset matches every marketplace
+    #[serde(default)]
+    pub marketplaces: Option<Vec<String>>,
+    /// Unset matches every event type
+    #[serde(default)]
+    pub event_types: Option<Vec<MarketplaceEventType>>,
+    #[serde(default = "WebhookConfig::default_active")]
+    pub active: bool,
+}
+
+impl WebhookConfig {
+    const fn default_active() -> bool {
+        true
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+/// Configuration of the worker that fetches the off-chain metadata and attributes of nfts.
+///
+/// Example:
+/// ```yaml
+/// attribute_worker_config:
+///   batch_size: 100
+///   concurrency: 10
+///   request_timeout_secs: 10
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct AttributeWorkerConfig {
+    /// Number of nfts picked up per polling round
+    pub batch_size: i64,
+    /// Maximum number of metadata requests in flight at once
+    pub concurrency: usize,
+    pub request_timeout_secs: u64,
+    /// Number of retries of a single request, with exponential backoff between them
+    pub request_retries: u32,
+    pub retry_backoff_ms: u64,
+    /// Number of failed rounds after which an nft is no longer retried
+    pub max_attempts: i32,
+    /// Delay before an nft whose fetch failed is picked up again
+    pub retry_after_secs: i64,
+    /// Age after which fetched metadata is considered stale and fetched again
+    pub stale_after_secs: i64,
+    pub poll_interval_secs: u64,
+}
+
+impl Default for AttributeWorkerConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            concurrency: 10,
+            request_timeout_secs: 10,
+            request_retries: 3,
+            retry_backoff_ms: 500,
+            max_attempts: 5,
+            retry_after_secs: 600,
+            stale_after_secs: 7 * 24 * 60 * 60,
+            poll_interval_secs: 30,
+        }
+    }
+}
+
+/// Configuration of the worker that delivers the webhook outbox.
+///
+/// Example:
+/// ```yaml
+/// webhook_worker_config:
+///   batch_size: 100
+///   max_attempts: 10
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct WebhookWorkerConfig {
+    /// Number of due deliveries picked up per polling round
+    pub batch_size: i64,
+    /// Maximum number of requests in flight at once
+    pub concurrency: usize,
+    pub request_timeout_secs: u64,
+    /// Number of failed attempts after which a delivery is dead-lettered
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled on every further attempt
+    pub retry_backoff_secs: i64,
+    pub max_retry_backoff_secs: i64,
+    pub poll_interval_secs: u64,
+}
+
+impl Default for WebhookWorkerConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            concurrency: 10,
+            request_timeout_secs: 10,
+            max_attempts: 10,
+            retry_backoff_secs: 10,
+            max_retry_backoff_secs: 60 * 60,
+            poll_interval_secs: 5,
+        }
+    }
+}
+
+/// Configuration of the worker that flags likely wash trades among the stored sales.
+///
+/// Example:
+/// ```yaml
+/// wash_trade_worker_config:
+///   round_trip_window_secs: 2592000
+///   funding_lookback_txns: 10
+///   price_outlier_multiplier: 10
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct WashTradeWorkerConfig {
+    /// A sale is flagged if the same wallets traded the other way around within this window
+    pub round_trip_window_secs: i64,
+    /// A sale is flagged if the buyer or the seller received a direct coin transfer from the
+    /// other within their last this many received transfers. 0 disables the check, and the
+    /// indexing of direct coin transfers by the token processor
+    pub funding_lookback_txns: i64,
+    /// A sale is flagged if its price is above this multiple of the median of the recent
+    /// sales of the collection
+    pub price_outlier_multiplier: i64,
+    /// Number of recent sales of the collection the median is computed over
+    pub median_window: i64,
+    /// Minimum number of recent sales for the median to be used
+    pub min_median_sales: i64,
+    /// Number of sales checked per polling round
+    pub batch_size: i64,
+    pub poll_interval_secs: u64,
+}
+
+impl Default for WashTradeWorkerConfig {
+    fn default() -> Self {
+        Self {
+            round_trip_window_secs: 30 * 24 * 60 * 60,
+            funding_lookback_txns: 10,
+            price_outlier_multiplier: 10,
+            median_window: 50,
+            min_median_sales: 5,
+            batch_size: 100,
+            poll_interval_secs: 30,
+        }
+    }
+}
@@ -7,6 +7,9 @@ pub mod schema;
 
 pub mod steps;
 
+#[cfg(feature = "api")]
+pub mod api;
+
 pub mod config;
 pub mod models;
 pub mod postgres;
@@ -1,9 +1,21 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
-use anyhow::Result;
-use aptos_indexer_processor_sdk::server_framework::ServerArgs;
-use clap::Parser;
-use nft_aggregator::config::IndexerProcessorConfig;
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::server_framework::{
+    load, setup_logging, GenericConfig, ServerArgs,
+};
+use clap::{Parser, Subcommand};
+use nft_aggregator::{
+    config::{
+        marketplace_config::NFTMarketplaceConfig,
+        validation::{remap_fixture, validate_config, Severity},
+        IndexerProcessorConfig,
+    },
+    processor::Processor,
+    steps::marketplace::dry_run::dry_run,
+    utils::fixtures::load_fixtures,
+};
+use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
 #[global_allocator]
@@ -11,6 +23,101 @@ static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 const RUNTIME_WORKER_MULTIPLIER: usize = 2;
 
+#[derive(Parser)]
+struct Args {
+    /// Config of the processor, required unless dry-running
+    #[clap(short, long, value_parser)]
+    config_path: Option<PathBuf>,
+    /// Runs the processor when omitted
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    fn load_config(&self) -> Result<IndexerProcessorConfig> {
+        let config_path = self
+            .config_path
+            .as_ref()
+            .context("--config-path is required")?;
+        Ok(load::<GenericConfig<IndexerProcessorConfig>>(config_path)?.server_config)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Remaps the dead-lettered events with the current marketplace configs and stores the
+    /// activities of the events that now remap
+    ReplayDeadLetters {
+        /// Only replay the events of this marketplace
+        #[clap(long)]
+        marketplace: Option<String>,
+    },
+    /// Checks the marketplace configs without running the processor, and prints what the
+    /// events of the transaction fixtures produce
+    ValidateConfig {
+        /// JSON or protobuf files of a transaction, or directories of them
+        #[clap(long)]
+        fixtures: Vec<PathBuf>,
+    },
+    /// Prints the actions, bids and listings a marketplace config produces for transaction
+    /// files, without touching the database
+    DryRun {
+        /// YAML file of a single marketplace config
+        #[clap(long)]
+        marketplace_config: PathBuf,
+        /// JSON or protobuf files of a transaction, or directories of them
+        #[clap(required = true)]
+        transactions: Vec<PathBuf>,
+    },
+}
+
+/// Prints the issues of the config and the remapping of the fixtures, failing when the config
+/// has errors.
+fn validate(config: &IndexerProcessorConfig, fixtures: &[PathBuf]) -> Result<()> {
+    let issues = validate_config(config);
+    for issue in &issues {
+        println!("{issue}");
+    }
+
+    for (path, transaction) in load_fixtures(fixtures)? {
+        println!("{}", path.display());
+        for marketplace in &config.nft_marketplace_configs {
+            match remap_fixture(marketplace, &transaction) {
+                Ok(lines) => lines.iter().for_each(|line| println!("  {line}")),
+                Err(e) => println!("  [{}] failed: {e:#}", marketplace.name),
+            }
+        }
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("Found {errors} errors in the marketplace configs");
+    }
+    println!("No errors found");
+
+    Ok(())
+}
+
+/// Prints the rows a marketplace config produces for the transactions as JSON.
+async fn print_dry_run(marketplace_config: &Path, transactions: &[PathBuf]) -> Result<()> {
+    let config: NFTMarketplaceConfig = serde_yaml::from_slice(
+        &std::fs::read(marketplace_config)
+            .with_context(|| format!("Failed to read {}", marketplace_config.display()))?,
+    )?;
+    let transactions = load_fixtures(transactions)?
+        .into_iter()
+        .map(|(_, transaction)| transaction)
+        .collect();
+
+    let output = dry_run(&config, transactions).await?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let num_cpus = num_cpus::get();
     let worker_threads = (num_cpus * RUNTIME_WORKER_MULTIPLIER).max(16);
@@ -23,8 +130,31 @@ fn main() -> Result<()> {
         .build()
         .unwrap()
         .block_on(async {
-            let args = ServerArgs::parse();
-            args.run::<IndexerProcessorConfig>(tokio::runtime::Handle::current())
-                .await
+            let args = Args::parse();
+            match &args.command {
+                None => {
+                    let server_args = ServerArgs {
+                        config_path: args
+                            .config_path
+                            .clone()
+                            .context("--config-path is required")?,
+                    };
+                    server_args
+                        .run::<IndexerProcessorConfig>(tokio::runtime::Handle::current())
+                        .await
+                },
+                Some(Command::ReplayDeadLetters { marketplace }) => {
+                    setup_logging();
+                    let processor = Processor::new(args.load_config()?).await?;
+                    processor.replay_dead_letters(marketplace.as_deref()).await
+                },
+                Some(Command::ValidateConfig { fixtures }) => {
+                    validate(&args.load_config()?, fixtures)
+                },
+                Some(Command::DryRun {
+                    marketplace_config,
+                    transactions,
+                }) => print_dry_run(marketplace_config, transactions).await,
+            }
         })
 }
@@ -0,0 +1,419 @@
+use crate::{
+    config::marketplace_config::MarketplaceEventType,
+    models::db::{action::Action, bid::Bid, listing::Listing},
+    schema::change_events,
+};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use std::{io::Write, str::FromStr};
+use strum::{Display, EnumString};
+
+/// Maximum number of changes inserted per statement
+const CHANGE_EVENT_INSERT_CHUNK_SIZE: usize = 1000;
+
+/// Kind of change published after a batch of actions is stored, or after the workers expire or
+/// invalidate orders.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Display,
+    EnumString,
+    Eq,
+    Hash,
+    PartialEq,
+    Serialize,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum ChangeEventType {
+    ListingCreated,
+    ListingCancelled,
+    ListingFilled,
+    ListingExpired,
+    ListingInvalidated,
+    BidPlaced,
+    BidCancelled,
+    BidAccepted,
+    BidExpired,
+    Mint,
+    Burn,
+    Transfer,
+}
+
+impl ChangeEventType {
+    pub fn from_marketplace_event_type(event_type: &MarketplaceEventType) -> Option<Self> {
+        match event_type {
+            MarketplaceEventType::List => Some(ChangeEventType::ListingCreated),
+            MarketplaceEventType::Unlist => Some(ChangeEventType::ListingCancelled),
+            MarketplaceEventType::Buy => Some(ChangeEventType::ListingFilled),
+            MarketplaceEventType::SoloBid | MarketplaceEventType::CollectionBid => {
+                Some(ChangeEventType::BidPlaced)
+            },
+            MarketplaceEventType::UnlistBid | MarketplaceEventType::CancelCollectionBid => {
+                Some(ChangeEventType::BidCancelled)
+            },
+            MarketplaceEventType::AcceptBid | MarketplaceEventType::AcceptCollectionBid => {
+                Some(ChangeEventType::BidAccepted)
+            },
+            MarketplaceEventType::Mint => Some(ChangeEventType::Mint),
+            MarketplaceEventType::Burn => Some(ChangeEventType::Burn),
+            MarketplaceEventType::Transfer => Some(ChangeEventType::Transfer),
+            MarketplaceEventType::Deposit | MarketplaceEventType::Unknown => None,
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for ChangeEventType {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for ChangeEventType {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let s = std::str::from_utf8(bytes.as_bytes())?;
+        s.parse::<ChangeEventType>()
+            .map_err(|_| "Unrecognized ChangeEventType".into())
+    }
+}
+
+/// A change published to the subscribers of real-time changes, either a stored action or a
+/// status set by the workers. Stored in the `change_events` outbox.
+#[derive(Clone, Debug, Deserialize, Insertable, PartialEq, Queryable, Selectable, Serialize)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = change_events)]
+pub struct ChangeEvent {
+    pub event_type: ChangeEventType,
+    /// `None` for changes made by the workers
+    pub tx_index: Option<i64>,
+    pub tx_id: Option<String>,
+    pub nft_id: Option<String>,
+    pub collection_id: Option<String>,
+    pub sender: Option<String>,
+    pub receiver: Option<String>,
+    pub price: Option<BigDecimal>,
+    pub market_name: Option<String>,
+    pub market_contract_id: Option<String>,
+    pub block_time: Option<NaiveDateTime>,
+}
+
+/// A change with its id in the outbox. Ids follow the publish order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredChangeEvent {
+    pub id: i64,
+    pub event: ChangeEvent,
+}
+
+impl ChangeEvent {
+    pub fn from_action(action: &Action) -> Option<Self> {
+        let tx_type = MarketplaceEventType::from_str(action.tx_type.as_deref()?).ok()?;
+        let event_type = ChangeEventType::from_marketplace_event_type(&tx_type)?;
+
+        Some(Self {
+            event_type,
+            tx_index: Some(action.tx_index),
+            tx_id: Some(action.tx_id.clone()),
+            nft_id: action.nft_id.clone(),
+            collection_id: action.collection_id.clone(),
+            sender: action.sender.clone(),
+            receiver: action.receiver.clone(),
+            price: action.price.clone(),
+            market_name: action.market_name.clone(),
+            market_contract_id: action.market_contract_id.clone(),
+            block_time: action.block_time,
+        })
+    }
+
+    /// A bid closed by the expiry worker, sent by its bidder.
+    pub fn from_bid(bid: &Bid, event_type: ChangeEventType) -> Self {
+        Self {
+            event_type,
+            tx_index: None,
+            tx_id: None,
+            nft_id: bid.nft_id.clone(),
+            collection_id: bid.collection_id.clone(),
+            sender: bid.bidder.clone(),
+            receiver: None,
+            price: bid.price.clone(),
+            market_name: bid.market_name.clone(),
+            market_contract_id: bid.market_contract_id.clone(),
+            block_time: None,
+        }
+    }
+
+    /// A listing closed by the expiry worker or invalidated, sent by its seller.
+    pub fn from_listing(listing: &Listing, event_type: ChangeEventType) -> Self {
+        Self {
+            event_type,
+            tx_index: None,
+            tx_id: None,
+            nft_id: listing.nft_id.clone(),
+            collection_id: listing.collection_id.clone(),
+            sender: listing.seller.clone(),
+            receiver: None,
+            price: listing.price.clone(),
+            market_name: listing.market_name.clone(),
+            market_contract_id: listing.market_contract_id.clone(),
+            block_time: None,
+        }
+    }
+
+    /// Stores the changes in the outbox and returns the stored ones with their ids, in publish
+    /// order. Changes of a transaction that were already stored are skipped.
+    pub async fn insert_all(
+        conn: &mut DbPoolConnection<'_>,
+        events: &[Self],
+    ) -> QueryResult<Vec<StoredChangeEvent>> {
+        let mut stored = Vec::with_capacity(events.len());
+        for chunk in events.chunks(CHANGE_EVENT_INSERT_CHUNK_SIZE) {
+            let mut changes = diesel::insert_into(change_events::table)
+                .values(chunk)
+                .on_conflict((
+                    change_events::tx_index,
+                    change_events::tx_id,
+                    change_events::event_type,
+                ))
+                .do_nothing()
+                .returning((change_events::id, Self::as_returning()))
+                .get_results::<(i64, Self)>(conn)
+                .await?;
+            // Ids are assigned in the order of the values
+            changes.sort_by_key(|(id, _)| *id);
+            stored.extend(
+                changes
+                    .into_iter()
+                    .map(|(id, event)| StoredChangeEvent { id, event }),
+            );
+        }
+
+        Ok(stored)
+    }
+
+    /// Returns the id the changes of the transactions after `tx_index` are replayed from, i.e.
+    /// the id before the first change of such a transaction, or the last id if there is none
+    /// yet.
+    pub async fn get_resume_id(conn: &mut DbPoolConnection<'_>, tx_index: i64) -> QueryResult<i64> {
+        let first_id = change_events::table
+            .filter(change_events::tx_index.gt(tx_index))
+            .select(diesel::dsl::min(change_events::id))
+            .first::<Option<i64>>(conn)
+            .await?;
+        if let Some(first_id) = first_id {
+            return Ok(first_id - 1);
+        }
+
+        let last_id = change_events::table
+            .select(diesel::dsl::max(change_events::id))
+            .first::<Option<i64>>(conn)
+            .await?;
+
+        Ok(last_id.unwrap_or_default())
+    }
+
+    /// Returns the stored changes matching `filter` after the change `after_id`, oldest first.
+    pub async fn get_since(
+        conn: &mut DbPoolConnection<'_>,
+        filter: &ChangeEventFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> QueryResult<Vec<StoredChangeEvent>> {
+        let mut query = change_events::table
+            .filter(change_events::id.gt(after_id))
+            .select((change_events::id, Self::as_select()))
+            .into_boxed();
+
+        if let Some(collection_id) = &filter.collection_id {
+            query = query.filter(change_events::collection_id.eq(collection_id));
+        }
+        if let Some(nft_id) = &filter.nft_id {
+            query = query.filter(change_events::nft_id.eq(nft_id));
+        }
+        if let Some(wallet) = &filter.wallet {
+            query = query.filter(
+                change_events::sender
+                    .eq(wallet)
+                    .or(change_events::receiver.eq(wallet)),
+            );
+        }
+        if let Some(event_types) = &filter.event_types {
+            query = query.filter(change_events::event_type.eq_any(event_types));
+        }
+
+        let changes = query
+            .order(change_events::id.asc())
+            .limit(limit)
+            .load::<(i64, Self)>(conn)
+            .await?;
+
+        Ok(changes
+            .into_iter()
+            .map(|(id, event)| StoredChangeEvent { id, event })
+            .collect())
+    }
+
+    /// Deletes the changes stored before `created_before`.
+    pub async fn delete_before(
+        conn: &mut DbPoolConnection<'_>,
+        created_before: NaiveDateTime,
+    ) -> QueryResult<usize> {
+        diesel::delete(change_events::table)
+            .filter(change_events::created_at.lt(created_before))
+            .execute(conn)
+            .await
+    }
+}
+
+/// Selects the changes a subscriber receives. Unset fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeEventFilter {
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    /// Matches the sender or the receiver
+    pub wallet: Option<String>,
+    pub event_types: Option<Vec<ChangeEventType>>,
+}
+
+impl ChangeEventFilter {
+    pub fn matches(&self, event: &ChangeEvent) -> bool {
+        let matches_field = |expected: &Option<String>, actual: &Option<String>| {
+            expected.is_none() || expected == actual
+        };
+
+        matches_field(&self.collection_id, &event.collection_id)
+            && matches_field(&self.nft_id, &event.nft_id)
+            && self.wallet.as_ref().map_or(true, |wallet| {
+                event.sender.as_ref() == Some(wallet) || event.receiver.as_ref() == Some(wallet)
+            })
+            && self
+                .event_types
+                .as_ref()
+                .map_or(true, |event_types| event_types.contains(&event.event_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MIGRATIONS;
+    use aptos_indexer_processor_sdk::postgres::utils::database::{new_db_pool, run_migrations};
+
+    #[test]
+    fn test_change_event_from_action() {
+        let action = Action {
+            tx_type: Some(MarketplaceEventType::AcceptCollectionBid.to_string()),
+            tx_index: 1_200_000_003,
+            tx_id: "0xabc".to_string(),
+            nft_id: Some("0x1".to_string()),
+            collection_id: Some("0xc".to_string()),
+            sender: Some("0xa".to_string()),
+            receiver: Some("0xb".to_string()),
+            ..Default::default()
+        };
+
+        let event = ChangeEvent::from_action(&action).unwrap();
+        assert_eq!(event.event_type, ChangeEventType::BidAccepted);
+        assert_eq!(event.tx_index, Some(1_200_000_003));
+
+        let deposit = Action {
+            tx_type: Some(MarketplaceEventType::Deposit.to_string()),
+            ..action.clone()
+        };
+        assert!(ChangeEvent::from_action(&deposit).is_none());
+
+        let filter = ChangeEventFilter {
+            collection_id: Some("0xc".to_string()),
+            wallet: Some("0xb".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.matches(&event));
+
+        let filter = ChangeEventFilter {
+            event_types: Some(vec![ChangeEventType::BidPlaced]),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&event));
+    }
+
+    #[test]
+    fn test_change_event_from_listing() {
+        let listing = Listing {
+            nft_id: Some("0x1".to_string()),
+            collection_id: Some("0xc".to_string()),
+            seller: Some("0xa".to_string()),
+            ..Default::default()
+        };
+
+        let event = ChangeEvent::from_listing(&listing, ChangeEventType::ListingInvalidated);
+        assert_eq!(event.tx_index, None);
+        assert!(ChangeEventFilter {
+            wallet: Some("0xa".to_string()),
+            event_types: Some(vec![ChangeEventType::ListingInvalidated]),
+            ..Default::default()
+        }
+        .matches(&event));
+    }
+
+    /// Runs against the database of `DATABASE_URL`, e.g.
+    /// `DATABASE_URL=postgresql://localhost:5432/nft cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_insert_all_skips_published_changes() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let db_pool = new_db_pool(&url, Some(2)).await.unwrap();
+        run_migrations(url, db_pool.clone(), MIGRATIONS).await;
+        let mut conn = db_pool.get().await.unwrap();
+
+        let tx_index = i64::MAX - 1;
+        let sale = Action {
+            tx_type: Some(MarketplaceEventType::Buy.to_string()),
+            tx_index,
+            tx_id: "0x7e57".to_string(),
+            ..Default::default()
+        };
+        let events = vec![
+            ChangeEvent::from_action(&sale).unwrap(),
+            ChangeEvent::from_listing(&Listing::default(), ChangeEventType::ListingInvalidated),
+        ];
+
+        let stored = ChangeEvent::insert_all(&mut conn, &events).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(
+            ChangeEvent::get_resume_id(&mut conn, tx_index - 1)
+                .await
+                .unwrap(),
+            stored[0].id - 1
+        );
+
+        // The batch is stored again, only the change without a transaction is new
+        let stored_again = ChangeEvent::insert_all(&mut conn, &events).await.unwrap();
+        assert_eq!(stored_again.len(), 1);
+        assert_eq!(stored_again[0].event.tx_index, None);
+
+        let ids = stored
+            .iter()
+            .chain(stored_again.iter())
+            .map(|change| change.id)
+            .collect::<Vec<_>>();
+        diesel::delete(change_events::table.filter(change_events::id.eq_any(ids)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+}
@@ -1,44 +1,321 @@
 use crate::{
     config::marketplace_config::MarketplaceEventType,
     models::{db::nft::Nft, EventModel},
-    schema::actions,
+    schema::{actions, trade_flags},
     utils::{
         object_utils::ObjectAggregatedData,
         token_utils::{TokenEvent, V2TokenEvent},
     },
 };
 use ahash::AHashMap;
-use aptos_indexer_processor_sdk::utils::convert::standardize_address;
+use aptos_indexer_processor_sdk::{
+    postgres::utils::database::DbPoolConnection, utils::convert::standardize_address,
+};
 use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
-use diesel::prelude::*;
+use diesel::{
+    pg::Pg,
+    prelude::*,
+    sql_types::{BigInt, Bool, Text},
+};
+use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
 pub const ACTIONS_TABLE_NAME: &str = "actions";
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
+pub struct ActionFilter {
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    /// Matches the sender or the receiver
+    pub wallet: Option<String>,
+    /// e.g. `buy`, `list`
+    pub tx_type: Option<String>,
+    /// Name of the marketplace, e.g. `tradeport`
+    pub marketplace: Option<String>,
+    pub min_price: Option<BigDecimal>,
+    pub max_price: Option<BigDecimal>,
+    /// Leaves out the sales flagged as wash trades
+    pub exclude_flagged: Option<bool>,
+}
+
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 #[derive(
-    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
 )]
 #[diesel(primary_key(tx_index, tx_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = actions)]
 pub struct Action {
+    #[diesel(select_expression = actions::tx_type.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<actions::tx_type>)]
     pub tx_type: Option<String>,
     pub tx_index: i64,
     pub tx_id: String,
     pub sender: Option<String>,
     pub receiver: Option<String>,
-    pub price: Option<i64>,
+    pub price: Option<BigDecimal>,
     pub nft_id: Option<String>,
     pub collection_id: Option<String>,
+    #[diesel(select_expression = actions::block_time.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<actions::block_time>)]
     pub block_time: Option<NaiveDateTime>,
+    #[diesel(select_expression = actions::block_height.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<actions::block_height>)]
     pub block_height: Option<i64>,
     pub market_name: Option<String>,
     pub market_contract_id: Option<String>,
     pub usd_price: Option<BigDecimal>,
+    /// Coin type or fungible asset metadata address the price is paid in
+    pub payment_token: Option<String>,
+}
+
+/// Primary key of an action returned by raw queries
+#[derive(QueryableByName)]
+struct ActionKey {
+    #[diesel(sql_type = BigInt)]
+    tx_index: i64,
+    #[diesel(sql_type = Text)]
+    tx_id: String,
 }
 
 impl Action {
+    /// Returns true if the action is a sale, i.e. a listing was bought or a bid was accepted.
+    pub fn is_sale(&self) -> bool {
+        is_sale_type(self.tx_type.as_deref())
+    }
+
+    /// Fills `usd_price` for sale actions that were written before the price history covering
+    /// their block time existed. Returns the keys of the repaired rows.
+    pub async fn backfill_usd_prices(
+        conn: &mut DbPoolConnection<'_>,
+        batch_size: i64,
+    ) -> diesel::QueryResult<Vec<(i64, String)>> {
+        diesel::sql_query(
+            r#"
+            UPDATE actions a
+            SET usd_price = ROUND(a.price::NUMERIC / POWER(10::NUMERIC, p.decimals) * p.price, 2)
+            FROM (
+                SELECT
+                    s.tx_index,
+                    s.tx_id,
+                    t.decimals,
+                    (
+                        SELECT price FROM prices
+                        WHERE payment_token = s.payment_token AND created_at <= s.block_time
+                        ORDER BY created_at DESC
+                        LIMIT 1
+                    ) AS price
+                FROM actions s
+                JOIN payment_tokens t ON t.id = s.payment_token
+                WHERE s.tx_type IN ('buy', 'accept_bid', 'accept_collection_bid')
+                    AND s.price > 0
+                    AND s.usd_price IS NULL
+                    AND s.block_time >= (
+                        SELECT MIN(created_at) FROM prices WHERE payment_token = s.payment_token
+                    )
+                LIMIT $1
+            ) p
+            WHERE a.tx_index = p.tx_index AND a.tx_id = p.tx_id AND p.price IS NOT NULL
+            RETURNING a.tx_index, a.tx_id
+            "#,
+        )
+        .bind::<BigInt, _>(batch_size)
+        .load::<ActionKey>(conn)
+        .await
+        .map(|keys| {
+            keys.into_iter()
+                .map(|key| (key.tx_index, key.tx_id))
+                .collect()
+        })
+    }
+
+    /// Returns true if the action changed the owner of the nft, i.e. it's a transfer or burn.
+    pub fn is_ownership_change(&self) -> bool {
+        self.tx_type.as_deref().map_or(false, |tx_type| {
+            ownership_change_types().iter().any(|t| t == tx_type)
+        })
+    }
+
+    /// Returns the latest transfer or burn of each of the nfts.
+    pub async fn get_last_ownership_changes(
+        conn: &mut DbPoolConnection<'_>,
+        nft_ids: &[String],
+    ) -> diesel::QueryResult<Vec<Self>> {
+        actions::table
+            .filter(actions::nft_id.eq_any(nft_ids))
+            .filter(actions::tx_type.eq_any(ownership_change_types()))
+            .distinct_on(actions::nft_id)
+            .select(Self::as_select())
+            .order((actions::nft_id.asc(), actions::tx_index.desc()))
+            .load(conn)
+            .await
+    }
+
+    /// Returns the sales with a tx_index in `(after_tx_index, up_to_tx_index]`, oldest first.
+    pub async fn get_sales_between(
+        conn: &mut DbPoolConnection<'_>,
+        after_tx_index: i64,
+        up_to_tx_index: i64,
+        limit: i64,
+    ) -> diesel::QueryResult<Vec<Self>> {
+        actions::table
+            .filter(actions::tx_type.eq_any(sale_types()))
+            .filter(actions::tx_index.gt(after_tx_index))
+            .filter(actions::tx_index.le(up_to_tx_index))
+            .select(Self::as_select())
+            .order((actions::tx_index.asc(), actions::tx_id.asc()))
+            .limit(limit)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the latest sale from `seller` to `buyer` between `since` and `before_tx_index`.
+    pub async fn get_last_sale_between(
+        conn: &mut DbPoolConnection<'_>,
+        seller: &str,
+        buyer: &str,
+        since: NaiveDateTime,
+        before_tx_index: i64,
+    ) -> diesel::QueryResult<Option<Self>> {
+        actions::table
+            .filter(actions::tx_type.eq_any(sale_types()))
+            .filter(actions::sender.eq(seller))
+            .filter(actions::receiver.eq(buyer))
+            .filter(actions::tx_index.lt(before_tx_index))
+            .filter(actions::block_time.ge(since))
+            .select(Self::as_select())
+            .order(actions::tx_index.desc())
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Returns the prices of the last `limit` sales of a collection paid in `payment_token`
+    /// before `before_tx_index`.
+    pub async fn get_recent_sale_prices(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+        payment_token: Option<&str>,
+        before_tx_index: i64,
+        limit: i64,
+    ) -> diesel::QueryResult<Vec<BigDecimal>> {
+        actions::table
+            .filter(actions::tx_type.eq_any(sale_types()))
+            .filter(actions::collection_id.eq(collection_id))
+            .filter(actions::payment_token.is_not_distinct_from(payment_token))
+            .filter(actions::tx_index.lt(before_tx_index))
+            .filter(actions::price.is_not_null())
+            .select(actions::price.assume_not_null())
+            .order(actions::tx_index.desc())
+            .limit(limit)
+            .load::<BigDecimal>(conn)
+            .await
+    }
+
+    /// Returns the history of an nft, latest first.
+    pub async fn get_by_nft(
+        conn: &mut DbPoolConnection<'_>,
+        nft_id: &str,
+        exclude_flagged: bool,
+        limit: i64,
+        offset: i64,
+    ) -> diesel::QueryResult<Vec<Self>> {
+        let mut query = actions::table
+            .filter(actions::nft_id.eq(nft_id))
+            .select(Self::as_select())
+            .into_boxed();
+
+        if exclude_flagged {
+            query = query.filter(is_not_flagged());
+        }
+
+        query
+            .order((actions::tx_index.desc(), actions::tx_id.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the actions a wallet took part in as sender or receiver, latest first.
+    pub async fn get_by_wallet(
+        conn: &mut DbPoolConnection<'_>,
+        wallet: &str,
+        exclude_flagged: bool,
+        limit: i64,
+        offset: i64,
+    ) -> diesel::QueryResult<Vec<Self>> {
+        let mut query = actions::table
+            .filter(actions::sender.eq(wallet).or(actions::receiver.eq(wallet)))
+            .select(Self::as_select())
+            .into_boxed();
+
+        if exclude_flagged {
+            query = query.filter(is_not_flagged());
+        }
+
+        query
+            .order((actions::tx_index.desc(), actions::tx_id.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the actions matching `filter`, latest first.
+    pub async fn get_filtered(
+        conn: &mut DbPoolConnection<'_>,
+        filter: &ActionFilter,
+        limit: i64,
+        offset: i64,
+    ) -> diesel::QueryResult<Vec<Self>> {
+        let mut query = actions::table.select(Self::as_select()).into_boxed();
+
+        if let Some(collection_id) = &filter.collection_id {
+            query = query.filter(actions::collection_id.eq(collection_id));
+        }
+        if let Some(nft_id) = &filter.nft_id {
+            query = query.filter(actions::nft_id.eq(nft_id));
+        }
+        if let Some(wallet) = &filter.wallet {
+            query = query.filter(actions::sender.eq(wallet).or(actions::receiver.eq(wallet)));
+        }
+        if let Some(tx_type) = &filter.tx_type {
+            query = query.filter(actions::tx_type.eq(tx_type));
+        }
+        if let Some(marketplace) = &filter.marketplace {
+            query = query.filter(actions::market_name.eq(marketplace));
+        }
+        if let Some(min_price) = filter.min_price {
+            query = query.filter(actions::price.ge(min_price));
+        }
+        if let Some(max_price) = filter.max_price {
+            query = query.filter(actions::price.le(max_price));
+        }
+        if filter.exclude_flagged.unwrap_or(false) {
+            query = query.filter(is_not_flagged());
+        }
+
+        query
+            .order((actions::tx_index.desc(), actions::tx_id.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+
     pub fn get_action_from_token_event_v1(
         event: &EventModel,
         txn_id: &str,
@@ -197,6 +474,34 @@ impl Action {
     }
 }
 
+fn sale_types() -> [String; 3] {
+    [
+        MarketplaceEventType::Buy.to_string(),
+        MarketplaceEventType::AcceptBid.to_string(),
+        MarketplaceEventType::AcceptCollectionBid.to_string(),
+    ]
+}
+
+pub fn is_sale_type(tx_type: Option<&str>) -> bool {
+    tx_type.map_or(false, |tx_type| sale_types().iter().any(|t| t == tx_type))
+}
+
+fn ownership_change_types() -> [String; 2] {
+    [
+        MarketplaceEventType::Transfer.to_string(),
+        MarketplaceEventType::Burn.to_string(),
+    ]
+}
+
+/// Matches the actions without a row in `trade_flags`.
+fn is_not_flagged() -> Box<dyn BoxableExpression<actions::table, Pg, SqlType = Bool>> {
+    Box::new(diesel::dsl::not(diesel::dsl::exists(
+        trade_flags::table
+            .filter(trade_flags::tx_index.eq(actions::tx_index))
+            .filter(trade_flags::tx_id.eq(actions::tx_id)),
+    )))
+}
+
 impl From<Action> for Nft {
     fn from(value: Action) -> Self {
         Self {
@@ -207,3 +512,99 @@ impl From<Action> for Nft {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::db::price::Price,
+        schema::{payment_tokens, prices},
+        MIGRATIONS,
+    };
+    use aptos_indexer_processor_sdk::postgres::utils::database::{new_db_pool, run_migrations};
+    use diesel::upsert::excluded;
+    use std::str::FromStr;
+
+    /// Runs against the database of `DATABASE_URL`, e.g.
+    /// `DATABASE_URL=postgresql://localhost:5432/nft cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_backfill_usd_prices_with_sub_cent_price() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let db_pool = new_db_pool(&url, Some(2)).await.unwrap();
+        run_migrations(url, db_pool.clone(), MIGRATIONS).await;
+        let mut conn = db_pool.get().await.unwrap();
+
+        let payment_token = "0x7e57::meme::Meme";
+        let created_at = NaiveDateTime::from_str("2025-08-13T00:00:00").unwrap();
+        diesel::insert_into(payment_tokens::table)
+            .values((
+                payment_tokens::id.eq(payment_token),
+                payment_tokens::decimals.eq(8),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        // Worth less than half a cent, which NUMERIC(20, 2) stored as 0.00
+        diesel::insert_into(prices::table)
+            .values(Price {
+                price: BigDecimal::from_str("0.0004").unwrap(),
+                created_at,
+                payment_token: payment_token.to_string(),
+            })
+            .on_conflict((prices::payment_token, prices::created_at))
+            .do_update()
+            .set(prices::price.eq(excluded(prices::price)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        // 10,000 whole tokens
+        let sale = Action {
+            tx_type: Some(MarketplaceEventType::Buy.to_string()),
+            tx_index: 1,
+            tx_id: "0x7e57".to_string(),
+            price: Some(BigDecimal::from(1_000_000_000_000_i64)),
+            block_time: Some(created_at + chrono::Duration::hours(1)),
+            block_height: Some(1),
+            payment_token: Some(payment_token.to_string()),
+            ..Default::default()
+        };
+        diesel::insert_into(actions::table)
+            .values(sale.clone())
+            .on_conflict((actions::tx_index, actions::tx_id))
+            .do_update()
+            .set(actions::usd_price.eq(None::<BigDecimal>))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        while !Action::backfill_usd_prices(&mut conn, 100)
+            .await
+            .unwrap()
+            .is_empty()
+        {}
+
+        let usd_price = actions::table
+            .find((sale.tx_index, &sale.tx_id))
+            .select(actions::usd_price)
+            .first::<Option<BigDecimal>>(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(usd_price, Some(BigDecimal::from_str("4.00").unwrap()));
+
+        diesel::delete(actions::table.find((sale.tx_index, &sale.tx_id)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        diesel::delete(prices::table.filter(prices::payment_token.eq(payment_token)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        diesel::delete(payment_tokens::table.find(payment_token))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    models::marketplace::{FieldError, NftMarketplaceActivity},
+    schema::activity_errors,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+pub const ACTIVITY_ERRORS_TABLE_NAME: &str = "activity_errors";
+
+/// A value of a marketplace event that couldn't be parsed into its activity field, e.g. a
+/// price that isn't an unsigned integer, or a field whose json paths matched nothing, with an
+/// empty `raw_value`. The field of the activity is left empty instead.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(tx_index, tx_id, field))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = activity_errors)]
+pub struct ActivityError {
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub field: String,
+    pub market_contract_id: Option<String>,
+    pub market_name: Option<String>,
+    pub event_type: String,
+    pub raw_event_type: String,
+    pub raw_value: String,
+    pub error: String,
+    pub block_time: NaiveDateTime,
+}
+
+impl ActivityError {
+    pub fn new(activity: &NftMarketplaceActivity, field_error: &FieldError) -> Self {
+        Self {
+            tx_index: activity.get_tx_index(),
+            tx_id: activity.txn_id.clone(),
+            field: field_error.field.clone(),
+            market_contract_id: activity.contract_address.clone(),
+            market_name: activity.marketplace.clone(),
+            event_type: activity.standard_event_type.to_string(),
+            raw_event_type: activity.raw_event_type.clone(),
+            raw_value: field_error.value.clone(),
+            error: field_error.error.clone(),
+            block_time: activity.block_timestamp,
+        }
+    }
+}
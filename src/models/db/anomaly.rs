@@ -0,0 +1,65 @@
+use crate::{models::marketplace::NftMarketplaceActivity, schema::anomalies};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+pub const ANOMALIES_TABLE_NAME: &str = "anomalies";
+
+pub const BID_ORDER_TYPE: &str = "bid";
+pub const LISTING_ORDER_TYPE: &str = "listing";
+
+/// A marketplace event that couldn't be applied to its bid or listing, e.g. a fill of an
+/// already cancelled bid. These usually point at a mis-mapped marketplace config.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(tx_index, tx_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = anomalies)]
+pub struct Anomaly {
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub order_type: String,
+    pub market_contract_id: String,
+    pub market_name: Option<String>,
+    pub order_id: Option<String>,
+    pub event_type: String,
+    pub raw_event_type: String,
+    pub from_status: Option<String>,
+    pub reason: String,
+    pub block_time: NaiveDateTime,
+}
+
+impl Anomaly {
+    pub fn new(
+        activity: &NftMarketplaceActivity,
+        order_type: &str,
+        order_id: Option<String>,
+        from_status: Option<String>,
+        reason: String,
+    ) -> Self {
+        Self {
+            tx_index: activity.get_tx_index(),
+            tx_id: activity.txn_id.clone(),
+            order_type: order_type.to_string(),
+            market_contract_id: activity.contract_address.clone().unwrap_or_default(),
+            market_name: activity.marketplace.clone(),
+            order_id,
+            event_type: activity.standard_event_type.to_string(),
+            raw_event_type: activity.raw_event_type.clone(),
+            from_status,
+            reason,
+            block_time: activity.block_timestamp,
+        }
+    }
+}
@@ -1,19 +1,103 @@
 use crate::schema::attributes;
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
 use bigdecimal::BigDecimal;
 use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
+/// Maximum number of attributes stored per insert query
+const REPLACE_CHUNK_SIZE: usize = 200;
+
+/// Matches the nfts having the trait `attr_type` with `value`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
+pub struct TraitFilter {
+    pub attr_type: String,
+    pub value: String,
+}
+
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 #[derive(
-    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
 )]
 #[diesel(primary_key(collection_id, nft_id, attr_type, value))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = attributes)]
 pub struct Attribute {
+    #[diesel(select_expression = attributes::collection_id.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<attributes::collection_id>)]
     pub collection_id: Option<String>,
+    #[diesel(select_expression = attributes::nft_id.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<attributes::nft_id>)]
     pub nft_id: Option<String>,
+    #[diesel(select_expression = attributes::attr_type.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<attributes::attr_type>)]
     pub attr_type: Option<String>,
+    #[diesel(select_expression = attributes::value.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<attributes::value>)]
     pub value: Option<String>,
     pub score: Option<BigDecimal>,
     pub rarity: Option<BigDecimal>,
 }
+
+impl Attribute {
+    /// Replaces the attributes of the given nfts with `new_attributes` in one transaction, so
+    /// that traits dropped from refetched metadata don't linger.
+    pub async fn replace_for_nfts(
+        conn: &mut DbPoolConnection<'_>,
+        nft_ids: &[String],
+        new_attributes: &[Attribute],
+    ) -> diesel::QueryResult<()> {
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                diesel::delete(attributes::table.filter(attributes::nft_id.eq_any(nft_ids)))
+                    .execute(conn)
+                    .await?;
+                for chunk in new_attributes.chunks(REPLACE_CHUNK_SIZE) {
+                    diesel::insert_into(attributes::table)
+                        .values(chunk)
+                        .on_conflict_do_nothing()
+                        .execute(conn)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Returns the (nft_id, attr_type, value) traits of every nft of the collection.
+    pub async fn get_traits_by_collection(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+    ) -> diesel::QueryResult<Vec<(String, String, String)>> {
+        attributes::table
+            .filter(attributes::collection_id.eq(collection_id))
+            .select((attributes::nft_id, attributes::attr_type, attributes::value))
+            .load::<(String, String, String)>(conn)
+            .await
+    }
+
+    pub async fn get_by_nft_ids(
+        conn: &mut DbPoolConnection<'_>,
+        nft_ids: &[String],
+    ) -> diesel::QueryResult<Vec<Self>> {
+        attributes::table
+            .filter(attributes::nft_id.eq_any(nft_ids))
+            .select(Self::as_select())
+            .order((attributes::attr_type.asc(), attributes::value.asc()))
+            .load(conn)
+            .await
+    }
+}
@@ -1,31 +1,214 @@
-use crate::schema::bids;
+use crate::{models::order_status::BidStatus, schema::bids};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
 pub const BIDS_TABLE_NAME: &str = "bids";
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
+pub struct BidFilter {
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    pub bidder: Option<String>,
+    /// Name of the marketplace, e.g. `tradeport`
+    pub marketplace: Option<String>,
+    /// e.g. `active`
+    pub status: Option<String>,
+    /// `solo` or `collection`
+    pub bid_type: Option<String>,
+    pub min_price: Option<BigDecimal>,
+    pub max_price: Option<BigDecimal>,
+}
+
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 #[derive(
-    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
 )]
 #[diesel(primary_key(market_contract_id, nonce))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = bids)]
 pub struct Bid {
+    #[diesel(select_expression = bids::bidder.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<bids::bidder>)]
     pub bidder: Option<String>,
     pub accepted_tx_id: Option<String>,
     pub canceled_tx_id: Option<String>,
     pub collection_id: Option<String>,
     pub created_tx_id: Option<String>,
     pub expires_at: Option<NaiveDateTime>,
+    #[diesel(select_expression = bids::market_contract_id.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<bids::market_contract_id>)]
     pub market_contract_id: Option<String>,
     pub market_name: Option<String>,
+    #[diesel(select_expression = bids::nonce.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<bids::nonce>)]
     pub nonce: Option<String>,
     pub nft_id: Option<String>,
-    pub price: Option<i64>,
+    pub price: Option<BigDecimal>,
     pub price_str: Option<String>,
     pub receiver: Option<String>,
     pub remaining_count: Option<i64>,
-    pub status: Option<String>,
+    pub status: Option<BidStatus>,
     pub bid_type: Option<String>,
+    /// Correlates the cancel and fill events of collection offers without an on-chain id
+    pub match_key: Option<String>,
+    /// Coin type or fungible asset metadata address the price is paid in
+    pub payment_token: Option<String>,
+}
+
+impl Bid {
+    /// Marks the active bids of a marketplace that expired at or before `chain_time` as expired,
+    /// and returns them.
+    pub async fn expire(
+        conn: &mut DbPoolConnection<'_>,
+        market_name: &str,
+        chain_time: NaiveDateTime,
+    ) -> QueryResult<Vec<Self>> {
+        diesel::update(bids::table)
+            .filter(bids::market_name.eq(market_name))
+            .filter(bids::status.eq(BidStatus::Active))
+            .filter(bids::expires_at.le(chain_time))
+            .set(bids::status.eq(BidStatus::Expired))
+            .returning(Self::as_returning())
+            .get_results(conn)
+            .await
+    }
+
+    /// Returns the open collection offers of a marketplace with one of the `match_keys`, oldest
+    /// first.
+    pub async fn get_open_collection_offers(
+        conn: &mut DbPoolConnection<'_>,
+        market_contract_id: &str,
+        match_keys: &[String],
+    ) -> QueryResult<Vec<Self>> {
+        bids::table
+            .filter(bids::market_contract_id.eq(market_contract_id))
+            .filter(bids::match_key.eq_any(match_keys))
+            .filter(bids::status.eq(BidStatus::Active))
+            .select(Self::as_select())
+            .order(bids::nonce.asc())
+            .load(conn)
+            .await
+    }
+
+    /// Returns the bids of a marketplace with one of the `nonces`.
+    pub async fn get_by_nonces(
+        conn: &mut DbPoolConnection<'_>,
+        market_contract_id: &str,
+        nonces: &[String],
+    ) -> QueryResult<Vec<Self>> {
+        bids::table
+            .filter(bids::market_contract_id.eq(market_contract_id))
+            .filter(bids::nonce.eq_any(nonces))
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Returns the bids placed on an nft, highest first, optionally only those with `status`.
+    pub async fn get_by_nft(
+        conn: &mut DbPoolConnection<'_>,
+        nft_id: &str,
+        status: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<Vec<Self>> {
+        let mut query = bids::table
+            .filter(bids::nft_id.eq(nft_id))
+            .select(Self::as_select())
+            .into_boxed();
+
+        if let Some(status) = status {
+            query = query.filter(bids::status.eq(status));
+        }
+
+        query
+            .order((bids::price.desc().nulls_last(), bids::nonce.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the solo and collection bids of a collection, highest first, optionally only
+    /// those with `status`.
+    pub async fn get_by_collection(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+        status: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<Vec<Self>> {
+        let mut query = bids::table
+            .filter(bids::collection_id.eq(collection_id))
+            .select(Self::as_select())
+            .into_boxed();
+
+        if let Some(status) = status {
+            query = query.filter(bids::status.eq(status));
+        }
+
+        query
+            .order((bids::price.desc().nulls_last(), bids::nonce.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the bids matching `filter`, highest first.
+    pub async fn get_filtered(
+        conn: &mut DbPoolConnection<'_>,
+        filter: &BidFilter,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<Vec<Self>> {
+        let mut query = bids::table.select(Self::as_select()).into_boxed();
+
+        if let Some(collection_id) = &filter.collection_id {
+            query = query.filter(bids::collection_id.eq(collection_id));
+        }
+        if let Some(nft_id) = &filter.nft_id {
+            query = query.filter(bids::nft_id.eq(nft_id));
+        }
+        if let Some(bidder) = &filter.bidder {
+            query = query.filter(bids::bidder.eq(bidder));
+        }
+        if let Some(marketplace) = &filter.marketplace {
+            query = query.filter(bids::market_name.eq(marketplace));
+        }
+        if let Some(status) = &filter.status {
+            query = query.filter(bids::status.eq(status));
+        }
+        if let Some(bid_type) = &filter.bid_type {
+            query = query.filter(bids::bid_type.eq(bid_type));
+        }
+        if let Some(min_price) = filter.min_price {
+            query = query.filter(bids::price.ge(min_price));
+        }
+        if let Some(max_price) = filter.max_price {
+            query = query.filter(bids::price.le(max_price));
+        }
+
+        query
+            .order((bids::price.desc().nulls_last(), bids::nonce.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
 }
@@ -11,17 +11,30 @@ use ahash::AHashMap;
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
     aptos_protos::transaction::v1::{WriteResource, WriteTableItem},
+    postgres::utils::database::DbPoolConnection,
     utils::convert::standardize_address,
 };
 use bigdecimal::ToPrimitive;
 use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 #[derive(
-    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
 )]
 #[diesel(primary_key(id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = collections)]
 pub struct Collection {
     pub id: String,
@@ -37,6 +50,29 @@ pub struct Collection {
 }
 
 impl Collection {
+    pub async fn get_by_id(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+    ) -> QueryResult<Option<Self>> {
+        collections::table
+            .filter(collections::id.eq(collection_id))
+            .select(Self::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    pub async fn get_by_ids(
+        conn: &mut DbPoolConnection<'_>,
+        collection_ids: &[String],
+    ) -> QueryResult<Vec<Self>> {
+        collections::table
+            .filter(collections::id.eq_any(collection_ids))
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
     pub fn get_from_write_table_item(
         table_item: &WriteTableItem,
         txn_version: i64,
@@ -0,0 +1,483 @@
+use crate::schema::collection_stats;
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    sql_types::{Array, BigInt, Text},
+};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+/// Aggregated market data of a collection. Volumes are in octas. The all-time totals and owner
+/// counts are running totals updated from the changes of each batch, the listing stats are
+/// recomputed for the collections a batch touched and the rolling windows on a schedule.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(collection_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = collection_stats)]
+pub struct CollectionStats {
+    pub collection_id: String,
+    pub floor_price: Option<BigDecimal>,
+    pub listed_count: Option<i64>,
+    pub owner_count: Option<i64>,
+    pub supply: Option<i64>,
+    pub listed_percentage: Option<BigDecimal>,
+    pub sales_count: Option<i64>,
+    pub volume: Option<BigDecimal>,
+    pub volume_usd: Option<BigDecimal>,
+    pub volume_24h: Option<BigDecimal>,
+    pub volume_24h_usd: Option<BigDecimal>,
+    pub volume_7d: Option<BigDecimal>,
+    pub volume_7d_usd: Option<BigDecimal>,
+    pub volume_30d: Option<BigDecimal>,
+    pub volume_30d_usd: Option<BigDecimal>,
+    /// When the rolling windows were last recomputed
+    pub updated_at: Option<NaiveDateTime>,
+    /// Nfts held in the collection, the supply when the collection has none
+    pub nft_count: Option<i64>,
+}
+
+/// Chain time the rolling windows end at, i.e. the block time of the furthest indexed
+/// transaction, so that they stay correct while the processors catch up
+const CHAIN_TIME: &str =
+    "(SELECT COALESCE(MAX(last_transaction_timestamp) AT TIME ZONE 'UTC', NOW()) \
+     FROM processor_status)";
+
+impl CollectionStats {
+    /// Applies the sales of the given actions to the running totals of their collections. Each
+    /// action stores what it adds to the totals, so only the difference with what it adds now
+    /// is applied, which covers new sales, sales flagged as wash trades and late usd prices, and
+    /// makes it safe to apply the same sale again. The rolling windows get the difference too
+    /// when the sale is within them, and are recomputed on a schedule by `refresh_windows`.
+    /// Sales flagged as wash trades don't count. The native volumes only count APT sales, the
+    /// usd volumes count every payment token.
+    pub async fn count_sales(
+        conn: &mut DbPoolConnection<'_>,
+        keys: &[(i64, String)],
+    ) -> diesel::QueryResult<usize> {
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let (tx_indexes, tx_ids): (Vec<i64>, Vec<String>) = keys.iter().cloned().unzip();
+
+        diesel::sql_query(format!(
+            r#"
+            WITH sales AS (
+                SELECT
+                    a.tx_index,
+                    a.tx_id,
+                    a.collection_id,
+                    a.block_time,
+                    a.stats_counted,
+                    a.stats_volume,
+                    a.stats_volume_usd,
+                    NOT EXISTS (
+                        SELECT 1 FROM trade_flags f
+                        WHERE f.tx_index = a.tx_index AND f.tx_id = a.tx_id
+                    ) AS counted,
+                    CASE WHEN a.payment_token = '0x1::aptos_coin::AptosCoin'
+                        THEN COALESCE(a.price, 0)
+                        ELSE 0
+                    END AS volume,
+                    COALESCE(a.usd_price, 0) AS volume_usd
+                FROM actions a
+                JOIN UNNEST($1::BIGINT[], $2::VARCHAR[]) AS k(tx_index, tx_id)
+                    ON a.tx_index = k.tx_index AND a.tx_id = k.tx_id
+                WHERE a.tx_type IN ('buy', 'accept_bid', 'accept_collection_bid')
+                    AND a.collection_id IS NOT NULL
+                ORDER BY a.tx_index, a.tx_id
+                FOR UPDATE OF a
+            ),
+            contributions AS (
+                SELECT
+                    tx_index,
+                    tx_id,
+                    collection_id,
+                    block_time,
+                    stats_counted,
+                    stats_volume,
+                    stats_volume_usd,
+                    counted,
+                    CASE WHEN counted THEN volume ELSE 0 END AS volume,
+                    CASE WHEN counted THEN volume_usd ELSE 0 END AS volume_usd
+                FROM sales
+            ),
+            changed AS (
+                UPDATE actions a
+                SET
+                    stats_counted = c.counted,
+                    stats_volume = c.volume,
+                    stats_volume_usd = c.volume_usd
+                FROM contributions c
+                WHERE a.tx_index = c.tx_index AND a.tx_id = c.tx_id
+                    AND (
+                        a.stats_counted <> c.counted
+                        OR a.stats_volume <> c.volume
+                        OR a.stats_volume_usd <> c.volume_usd
+                    )
+                RETURNING
+                    c.collection_id,
+                    c.block_time,
+                    c.counted::INT - c.stats_counted::INT AS sales_delta,
+                    c.volume - c.stats_volume AS volume_delta,
+                    c.volume_usd - c.stats_volume_usd AS volume_usd_delta
+            ),
+            chain AS (
+                SELECT {CHAIN_TIME} AS now
+            )
+            INSERT INTO collection_stats (
+                collection_id, sales_count, volume, volume_usd, volume_24h, volume_24h_usd,
+                volume_7d, volume_7d_usd, volume_30d, volume_30d_usd
+            )
+            SELECT
+                d.collection_id,
+                SUM(d.sales_delta),
+                SUM(d.volume_delta),
+                SUM(d.volume_usd_delta),
+                COALESCE(SUM(d.volume_delta) FILTER (WHERE d.block_time >= chain.now - INTERVAL '24 hours'), 0),
+                COALESCE(SUM(d.volume_usd_delta) FILTER (WHERE d.block_time >= chain.now - INTERVAL '24 hours'), 0),
+                COALESCE(SUM(d.volume_delta) FILTER (WHERE d.block_time >= chain.now - INTERVAL '7 days'), 0),
+                COALESCE(SUM(d.volume_usd_delta) FILTER (WHERE d.block_time >= chain.now - INTERVAL '7 days'), 0),
+                COALESCE(SUM(d.volume_delta) FILTER (WHERE d.block_time >= chain.now - INTERVAL '30 days'), 0),
+                COALESCE(SUM(d.volume_usd_delta) FILTER (WHERE d.block_time >= chain.now - INTERVAL '30 days'), 0)
+            FROM changed d
+            CROSS JOIN chain
+            GROUP BY d.collection_id
+            ORDER BY d.collection_id
+            ON CONFLICT (collection_id) DO UPDATE SET
+                sales_count = COALESCE(collection_stats.sales_count, 0) + EXCLUDED.sales_count,
+                volume = COALESCE(collection_stats.volume, 0) + EXCLUDED.volume,
+                volume_usd = COALESCE(collection_stats.volume_usd, 0) + EXCLUDED.volume_usd,
+                volume_24h = COALESCE(collection_stats.volume_24h, 0) + EXCLUDED.volume_24h,
+                volume_24h_usd = COALESCE(collection_stats.volume_24h_usd, 0) + EXCLUDED.volume_24h_usd,
+                volume_7d = COALESCE(collection_stats.volume_7d, 0) + EXCLUDED.volume_7d,
+                volume_7d_usd = COALESCE(collection_stats.volume_7d_usd, 0) + EXCLUDED.volume_7d_usd,
+                volume_30d = COALESCE(collection_stats.volume_30d, 0) + EXCLUDED.volume_30d,
+                volume_30d_usd = COALESCE(collection_stats.volume_30d_usd, 0) + EXCLUDED.volume_30d_usd
+            "#
+        ))
+        .bind::<Array<BigInt>, _>(tx_indexes)
+        .bind::<Array<Text>, _>(tx_ids)
+        .execute(conn)
+        .await
+    }
+
+    /// Applies the nfts held by the given `(collection_id, owner)` pairs to the owner and nft
+    /// counts of their collections. The holdings of each pair are recounted from `nfts` and only
+    /// the difference with the stored holdings is applied, so the pairs must include both the
+    /// previous and the new owners of the nfts that changed hands or were burned.
+    pub async fn count_owners(
+        conn: &mut DbPoolConnection<'_>,
+        pairs: &[(String, String)],
+    ) -> diesel::QueryResult<usize> {
+        if pairs.is_empty() {
+            return Ok(0);
+        }
+        let (collection_ids, owners): (Vec<String>, Vec<String>) = pairs.iter().cloned().unzip();
+
+        diesel::sql_query(
+            r#"
+            WITH pairs AS (
+                SELECT DISTINCT collection_id, owner
+                FROM UNNEST($1::VARCHAR[], $2::VARCHAR[]) AS p(collection_id, owner)
+            ),
+            holdings AS (
+                SELECT
+                    p.collection_id,
+                    p.owner,
+                    n.nft_count,
+                    COALESCE(o.nft_count, 0) AS previous_nft_count
+                FROM pairs p
+                CROSS JOIN LATERAL (
+                    SELECT COUNT(*) AS nft_count
+                    FROM nfts
+                    WHERE collection_id = p.collection_id AND owner = p.owner
+                        AND burned IS NOT TRUE
+                ) n
+                LEFT JOIN collection_owners o
+                    ON o.collection_id = p.collection_id AND o.owner = p.owner
+                WHERE n.nft_count <> COALESCE(o.nft_count, 0)
+            ),
+            upserted AS (
+                INSERT INTO collection_owners (collection_id, owner, nft_count)
+                SELECT collection_id, owner, nft_count FROM holdings WHERE nft_count > 0
+                ON CONFLICT (collection_id, owner) DO UPDATE SET nft_count = EXCLUDED.nft_count
+            ),
+            deleted AS (
+                DELETE FROM collection_owners o
+                USING holdings h
+                WHERE o.collection_id = h.collection_id AND o.owner = h.owner
+                    AND h.nft_count = 0
+            )
+            INSERT INTO collection_stats (collection_id, owner_count, nft_count)
+            SELECT
+                collection_id,
+                SUM((nft_count > 0)::INT - (previous_nft_count > 0)::INT),
+                SUM(nft_count - previous_nft_count)::BIGINT
+            FROM holdings
+            GROUP BY collection_id
+            ORDER BY collection_id
+            ON CONFLICT (collection_id) DO UPDATE SET
+                owner_count = COALESCE(collection_stats.owner_count, 0) + EXCLUDED.owner_count,
+                nft_count = COALESCE(collection_stats.nft_count, 0) + EXCLUDED.nft_count
+            "#,
+        )
+        .bind::<Array<Text>, _>(collection_ids)
+        .bind::<Array<Text>, _>(owners)
+        .execute(conn)
+        .await
+    }
+
+    /// Recomputes the listing stats of the given collections from their active listings. The
+    /// floor price only counts APT listings. Collections without a supply fall back to the
+    /// number of nfts held, kept by `count_owners`.
+    pub async fn refresh(
+        conn: &mut DbPoolConnection<'_>,
+        collection_ids: &[String],
+    ) -> diesel::QueryResult<usize> {
+        if collection_ids.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::sql_query(
+            r#"
+            INSERT INTO collection_stats (
+                collection_id, floor_price, listed_count, supply, listed_percentage
+            )
+            SELECT
+                c.id,
+                l.floor_price,
+                l.listed_count,
+                s.supply,
+                CASE WHEN s.supply > 0
+                    THEN ROUND(l.listed_count::NUMERIC * 100 / s.supply, 2)
+                    ELSE 0
+                END
+            FROM UNNEST($1::VARCHAR[]) AS c(id)
+            LEFT JOIN collections col ON col.id = c.id
+            LEFT JOIN collection_stats cs ON cs.collection_id = c.id
+            CROSS JOIN LATERAL (
+                SELECT
+                    MIN(price) FILTER (WHERE payment_token = '0x1::aptos_coin::AptosCoin') AS floor_price,
+                    COUNT(*) AS listed_count
+                FROM listings
+                WHERE collection_id = c.id AND listed = true
+            ) l
+            CROSS JOIN LATERAL (
+                SELECT COALESCE(NULLIF(col.supply, 0), cs.nft_count, 0) AS supply
+            ) s
+            ORDER BY c.id
+            ON CONFLICT (collection_id) DO UPDATE SET
+                floor_price = EXCLUDED.floor_price,
+                listed_count = EXCLUDED.listed_count,
+                supply = EXCLUDED.supply,
+                listed_percentage = EXCLUDED.listed_percentage
+            "#,
+        )
+        .bind::<Array<Text>, _>(collection_ids)
+        .execute(conn)
+        .await
+    }
+
+    /// Recomputes the 24h/7d/30d volumes of the given collections, which shrink as their sales
+    /// age out of the windows. The windows end at the chain time, not the wall clock.
+    pub async fn refresh_windows(
+        conn: &mut DbPoolConnection<'_>,
+        collection_ids: &[String],
+    ) -> diesel::QueryResult<usize> {
+        if collection_ids.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::sql_query(format!(
+            r#"
+            WITH chain AS (
+                SELECT {CHAIN_TIME} AS now
+            )
+            UPDATE collection_stats cs
+            SET
+                volume_24h = COALESCE(a.volume_24h, 0),
+                volume_24h_usd = COALESCE(a.volume_24h_usd, 0),
+                volume_7d = COALESCE(a.volume_7d, 0),
+                volume_7d_usd = COALESCE(a.volume_7d_usd, 0),
+                volume_30d = COALESCE(a.volume_30d, 0),
+                volume_30d_usd = COALESCE(a.volume_30d_usd, 0),
+                updated_at = NOW()
+            FROM UNNEST($1::VARCHAR[]) AS c(id)
+            CROSS JOIN chain
+            CROSS JOIN LATERAL (
+                SELECT
+                    SUM(stats_volume) FILTER (WHERE block_time >= chain.now - INTERVAL '24 hours') AS volume_24h,
+                    SUM(stats_volume_usd) FILTER (WHERE block_time >= chain.now - INTERVAL '24 hours') AS volume_24h_usd,
+                    SUM(stats_volume) FILTER (WHERE block_time >= chain.now - INTERVAL '7 days') AS volume_7d,
+                    SUM(stats_volume_usd) FILTER (WHERE block_time >= chain.now - INTERVAL '7 days') AS volume_7d_usd,
+                    SUM(stats_volume) AS volume_30d,
+                    SUM(stats_volume_usd) AS volume_30d_usd
+                FROM actions
+                WHERE collection_id = c.id
+                    AND tx_type IN ('buy', 'accept_bid', 'accept_collection_bid')
+                    AND block_time >= chain.now - INTERVAL '30 days'
+                    AND stats_counted
+            ) a
+            WHERE cs.collection_id = c.id
+            "#
+        ))
+        .bind::<Array<Text>, _>(collection_ids)
+        .execute(conn)
+        .await
+    }
+
+    /// Returns the collections with recent volume whose rolling windows were not refreshed
+    /// since `updated_before`.
+    pub async fn get_stale_collection_ids(
+        conn: &mut DbPoolConnection<'_>,
+        updated_before: NaiveDateTime,
+        limit: i64,
+    ) -> diesel::QueryResult<Vec<String>> {
+        collection_stats::table
+            .filter(
+                collection_stats::volume_30d
+                    .gt(BigDecimal::from(0))
+                    .or(collection_stats::volume_30d_usd.gt(BigDecimal::from(0))),
+            )
+            .filter(collection_stats::updated_at.lt(updated_before))
+            .select(collection_stats::collection_id)
+            .limit(limit)
+            .load::<String>(conn)
+            .await
+    }
+
+    pub async fn get_by_collection_id(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+    ) -> diesel::QueryResult<Option<Self>> {
+        collection_stats::table
+            .filter(collection_stats::collection_id.eq(collection_id))
+            .select(Self::as_select())
+            .first::<Self>(conn)
+            .await
+            .optional()
+    }
+}
+
+/// Refreshes the listing stats of the collections touched by a committed batch.
+pub async fn refresh_collection_stats(
+    conn: &mut DbPoolConnection<'_>,
+    collection_ids: impl IntoIterator<Item = String>,
+) -> diesel::QueryResult<usize> {
+    let mut collection_ids = collection_ids.into_iter().collect::<Vec<_>>();
+    collection_ids.sort();
+    collection_ids.dedup();
+
+    CollectionStats::refresh(conn, &collection_ids).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::marketplace_config::MarketplaceEventType,
+        models::db::{action::Action, trade_flag::TradeFlagReason},
+        schema::{actions, trade_flags},
+        MIGRATIONS,
+    };
+    use aptos_indexer_processor_sdk::postgres::utils::database::{new_db_pool, run_migrations};
+    use std::str::FromStr;
+
+    /// Runs against the database of `DATABASE_URL`, e.g.
+    /// `DATABASE_URL=postgresql://localhost:5432/nft cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_count_sales_applies_each_sale_once() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let db_pool = new_db_pool(&url, Some(2)).await.unwrap();
+        run_migrations(url, db_pool.clone(), MIGRATIONS).await;
+        let mut conn = db_pool.get().await.unwrap();
+
+        let collection_id = "0x7e57c011ec710";
+        let block_time = NaiveDateTime::from_str("2025-08-15T00:00:00").unwrap();
+        let sale = Action {
+            tx_type: Some(MarketplaceEventType::Buy.to_string()),
+            tx_index: 1,
+            tx_id: "0x7e57c011ec710".to_string(),
+            price: Some(BigDecimal::from(100_000_000)),
+            collection_id: Some(collection_id.to_string()),
+            block_time: Some(block_time),
+            block_height: Some(1),
+            usd_price: Some(BigDecimal::from(5)),
+            payment_token: Some("0x1::aptos_coin::AptosCoin".to_string()),
+            ..Default::default()
+        };
+        diesel::insert_into(actions::table)
+            .values(sale.clone())
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let keys = [(sale.tx_index, sale.tx_id.clone())];
+
+        // A sale stored twice, e.g. after a restart, only counts once
+        CollectionStats::count_sales(&mut conn, &keys)
+            .await
+            .unwrap();
+        CollectionStats::count_sales(&mut conn, &keys)
+            .await
+            .unwrap();
+        let stats = CollectionStats::get_by_collection_id(&mut conn, collection_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stats.sales_count, Some(1));
+        assert_eq!(stats.volume, Some(BigDecimal::from(100_000_000)));
+        assert_eq!(stats.volume_usd, Some(BigDecimal::from(5)));
+
+        // Flagged as a wash trade
+        diesel::insert_into(trade_flags::table)
+            .values((
+                trade_flags::tx_index.eq(sale.tx_index),
+                trade_flags::tx_id.eq(&sale.tx_id),
+                trade_flags::reason.eq(TradeFlagReason::RoundTrip.to_string()),
+                trade_flags::block_time.eq(block_time),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        CollectionStats::count_sales(&mut conn, &keys)
+            .await
+            .unwrap();
+        let stats = CollectionStats::get_by_collection_id(&mut conn, collection_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stats.sales_count, Some(0));
+        assert_eq!(stats.volume, Some(BigDecimal::from(0)));
+        assert_eq!(stats.volume_usd, Some(BigDecimal::from(0)));
+
+        diesel::delete(trade_flags::table.filter(trade_flags::tx_id.eq(&sale.tx_id)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        diesel::delete(actions::table.find((sale.tx_index, &sale.tx_id)))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        diesel::delete(collection_stats::table.find(collection_id))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+}
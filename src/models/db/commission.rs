@@ -11,27 +11,88 @@ use ahash::AHashMap;
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
     aptos_protos::transaction::v1::{WriteResource, WriteTableItem},
+    postgres::utils::database::DbPoolConnection,
     utils::convert::standardize_address,
 };
 use bigdecimal::BigDecimal;
 use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 #[derive(
-    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
 )]
 #[diesel(primary_key(id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = commissions)]
 pub struct Commission {
+    #[diesel(select_expression = commissions::id.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<commissions::id>)]
     pub id: Option<Uuid>,
     pub royalty: Option<BigDecimal>,
     pub nft_id: Option<String>,
     pub collection_id: Option<String>,
+    /// Wallet the royalty is paid to
+    pub payee_address: Option<String>,
 }
 
 impl Commission {
+    /// Returns the royalty of an nft, falling back to the royalty of its collection.
+    pub async fn get_by_nft(
+        conn: &mut DbPoolConnection<'_>,
+        nft_id: &str,
+        collection_id: Option<&str>,
+    ) -> QueryResult<Option<Self>> {
+        let by_nft = commissions::table
+            .filter(commissions::nft_id.eq(nft_id))
+            .select(Self::as_select())
+            .first(conn)
+            .await
+            .optional()?;
+
+        match (by_nft, collection_id) {
+            (Some(commission), _) => Ok(Some(commission)),
+            (None, Some(collection_id)) => commissions::table
+                .filter(commissions::collection_id.eq(collection_id))
+                .filter(commissions::nft_id.is_null())
+                .select(Self::as_select())
+                .first(conn)
+                .await
+                .optional(),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Returns the royalties of the nfts and of the collections, the ones of a collection are
+    /// the rows without an nft.
+    pub async fn get_by_nfts(
+        conn: &mut DbPoolConnection<'_>,
+        nft_ids: &[String],
+        collection_ids: &[String],
+    ) -> QueryResult<Vec<Self>> {
+        commissions::table
+            .filter(
+                commissions::nft_id.eq_any(nft_ids).or(commissions::nft_id
+                    .is_null()
+                    .and(commissions::collection_id.eq_any(collection_ids))),
+            )
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
     pub fn get_from_write_table_item(
         write_table_item: &WriteTableItem,
         transaction_version: i64,
@@ -69,6 +130,7 @@ impl Commission {
                     )),
                     collection_id: Some(token_data_id.get_collection_addr()),
                     nft_id: Some(token_data_id.to_addr()),
+                    payee_address: Some(token_data.royalty.get_payee_address()),
                 };
 
                 return Ok(Some(commission));
@@ -90,6 +152,7 @@ impl Commission {
                         royalty: Some(calc_royalty(&royalty.denominator, &royalty.numerator)),
                         nft_id: None,
                         collection_id: Some(address),
+                        payee_address: Some(standardize_address(&royalty.payee_address)),
                     };
 
                     return Ok(Some(commission));
@@ -105,6 +168,7 @@ impl Commission {
                         royalty: Some(calc_royalty(&royalty.denominator, &royalty.numerator)),
                         nft_id: Some(address),
                         collection_id: Some(inner.get_collection_address()),
+                        payee_address: Some(standardize_address(&royalty.payee_address)),
                     };
 
                     return Ok(Some(commission));
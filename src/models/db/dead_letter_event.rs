@@ -0,0 +1,156 @@
+use crate::{models::EventModel, schema::dead_letter_events};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::{transaction::TxnData, write_set_change::Change, Transaction},
+    postgres::utils::database::DbPoolConnection,
+};
+use chrono::NaiveDateTime;
+use diesel::{
+    pg::{upsert::excluded, Pg},
+    prelude::*,
+    query_builder::QueryFragment,
+};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+pub const DEAD_LETTER_EVENTS_TABLE_NAME: &str = "dead_letter_events";
+
+/// A marketplace event that couldn't be remapped into an activity because json paths of the
+/// marketplace config matched nothing, or matched a value that couldn't be parsed, and left the
+/// activity without its nft or collection. The event is kept as is so it can be replayed once
+/// the config is fixed.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(txn_version, event_index, market_name))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = dead_letter_events)]
+pub struct DeadLetterEvent {
+    pub txn_version: i64,
+    pub event_index: i64,
+    pub market_name: String,
+    pub tx_id: String,
+    pub market_contract_id: String,
+    pub event_type: String,
+    /// The json paths that matched nothing or a value that couldn't be parsed, comma separated
+    pub json_path: String,
+    pub error: String,
+    /// The [`EventModel`] of the event
+    pub event: serde_json::Value,
+    pub block_time: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    /// The [`Transaction`] of the event with only its events and write set resources, to
+    /// replay the event with them. Missing on events dead-lettered before it was kept
+    pub transaction: Option<serde_json::Value>,
+}
+
+impl DeadLetterEvent {
+    pub fn new(
+        tx_id: &str,
+        event: &EventModel,
+        market_name: &str,
+        json_paths: &[String],
+        error: String,
+        created_at: NaiveDateTime,
+    ) -> Self {
+        Self {
+            txn_version: event.transaction_version,
+            event_index: event.event_index,
+            market_name: market_name.to_string(),
+            tx_id: tx_id.to_string(),
+            market_contract_id: event.account_address.clone(),
+            event_type: event.type_str.clone(),
+            json_path: json_paths.join(", "),
+            error,
+            event: serde_json::to_value(event).unwrap_or_default(),
+            block_time: event.block_timestamp,
+            created_at,
+            transaction: None,
+        }
+    }
+
+    /// Keeps the transaction of the event, without its payload, signature and the write set
+    /// changes other than resources.
+    pub fn set_transaction(&mut self, transaction: &Transaction) {
+        let mut transaction = transaction.clone();
+        if let Some(info) = transaction.info.as_mut() {
+            info.changes
+                .retain(|change| matches!(change.change, Some(Change::WriteResource(_))));
+        }
+        if let Some(TxnData::User(user_txn)) = transaction.txn_data.as_mut() {
+            if let Some(request) = user_txn.request.as_mut() {
+                request.payload = None;
+                request.signature = None;
+            }
+        }
+
+        self.transaction = serde_json::to_value(&transaction).ok();
+    }
+
+    /// Returns the event that was dead-lettered.
+    pub fn get_event(&self) -> anyhow::Result<EventModel> {
+        Ok(serde_json::from_value(self.event.clone())?)
+    }
+
+    /// Returns the transaction of the event that was dead-lettered, if it was kept.
+    pub fn get_transaction(&self) -> anyhow::Result<Option<Transaction>> {
+        self.transaction
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Returns the dead-lettered events of a marketplace in the order they were emitted.
+    pub async fn get_by_marketplace(
+        conn: &mut DbPoolConnection<'_>,
+        market_name: &str,
+    ) -> QueryResult<Vec<Self>> {
+        dead_letter_events::table
+            .filter(dead_letter_events::market_name.eq(market_name))
+            .select(Self::as_select())
+            .order((
+                dead_letter_events::txn_version.asc(),
+                dead_letter_events::event_index.asc(),
+            ))
+            .load(conn)
+            .await
+    }
+
+    pub async fn delete(&self, conn: &mut DbPoolConnection<'_>) -> QueryResult<usize> {
+        diesel::delete(dead_letter_events::table.find((
+            self.txn_version,
+            self.event_index,
+            &self.market_name,
+        )))
+        .execute(conn)
+        .await
+    }
+}
+
+/// Events dead-lettered again keep the paths and error of their latest remapping.
+pub fn insert_dead_letter_events(
+    items_to_insert: Vec<DeadLetterEvent>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::dead_letter_events::dsl::*;
+
+    diesel::insert_into(dead_letter_events)
+        .values(items_to_insert)
+        .on_conflict((txn_version, event_index, market_name))
+        .do_update()
+        .set((
+            json_path.eq(excluded(json_path)),
+            error.eq(excluded(error)),
+            event.eq(excluded(event)),
+            transaction.eq(excluded(transaction)),
+        ))
+}
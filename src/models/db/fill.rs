@@ -0,0 +1,116 @@
+use crate::{
+    models::marketplace::{BidModel, NftMarketplaceActivity},
+    schema::{bid_fills, listing_fills},
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+pub const BID_FILLS_TABLE_NAME: &str = "bid_fills";
+pub const LISTING_FILLS_TABLE_NAME: &str = "listing_fills";
+
+/// A single fill of a bid, keyed by the action of the accept event.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(tx_index, tx_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = bid_fills)]
+pub struct BidFill {
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub market_contract_id: String,
+    pub nonce: String,
+    pub bid_type: Option<String>,
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    pub bidder: Option<String>,
+    pub seller: Option<String>,
+    pub amount: i64,
+    pub price: Option<BigDecimal>,
+    /// Tokens left on the bid after this fill, `None` when the bid amount is unknown
+    pub remaining_count: Option<i64>,
+    pub block_time: NaiveDateTime,
+}
+
+impl BidFill {
+    pub fn new(activity: &NftMarketplaceActivity, remaining_count: Option<i64>) -> Option<Self> {
+        Some(Self {
+            tx_index: activity.get_tx_index(),
+            tx_id: activity.txn_id.clone(),
+            market_contract_id: activity.contract_address.clone()?,
+            nonce: activity.offer_id.clone()?,
+            bid_type: activity.get_bid_type(),
+            collection_id: activity.collection_addr.clone(),
+            nft_id: activity.token_addr.clone(),
+            bidder: activity.buyer.clone(),
+            seller: activity.seller.clone(),
+            amount: activity.get_filled_amount(),
+            price: activity.price.clone(),
+            remaining_count,
+            block_time: activity.block_timestamp,
+        })
+    }
+}
+
+/// A single fill of a listing, keyed by the action of the buy event.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(tx_index, tx_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = listing_fills)]
+pub struct ListingFill {
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub market_contract_id: String,
+    pub nft_id: String,
+    pub collection_id: Option<String>,
+    pub nonce: Option<String>,
+    pub seller: Option<String>,
+    pub buyer: Option<String>,
+    pub amount: i64,
+    pub price: Option<BigDecimal>,
+    /// Tokens left on the listing after this fill, `None` when the listed amount is unknown
+    pub remaining_count: Option<i64>,
+    pub block_time: NaiveDateTime,
+}
+
+impl ListingFill {
+    pub fn new(activity: &NftMarketplaceActivity, remaining_count: Option<i64>) -> Option<Self> {
+        Some(Self {
+            tx_index: activity.get_tx_index(),
+            tx_id: activity.txn_id.clone(),
+            market_contract_id: activity.contract_address.clone()?,
+            nft_id: activity.token_addr.clone()?,
+            collection_id: activity.collection_addr.clone(),
+            nonce: activity.listing_id.clone(),
+            seller: activity.seller.clone(),
+            buyer: activity.buyer.clone(),
+            amount: activity.get_filled_amount(),
+            price: activity.price.clone(),
+            remaining_count,
+            block_time: activity.block_timestamp,
+        })
+    }
+}
@@ -1,27 +1,329 @@
-use crate::schema::listings;
+use crate::{
+    config::marketplace_config::MarketplaceEventType,
+    models::{db::action::Action, order_status::ListingStatus},
+    schema::listings,
+};
+use ahash::AHashMap;
+use aptos_indexer_processor_sdk::{
+    postgres::utils::database::DbPoolConnection, utils::convert::standardize_address,
+};
+use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
 pub const LISTINGS_TABLE_NAME: &str = "listings";
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
+pub struct ListingFilter {
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    pub seller: Option<String>,
+    /// Name of the marketplace, e.g. `tradeport`
+    pub marketplace: Option<String>,
+    pub min_price: Option<BigDecimal>,
+    pub max_price: Option<BigDecimal>,
+    /// Only the active listings when unset
+    pub listed: Option<bool>,
+}
+
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
 #[derive(
-    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
 )]
 #[diesel(primary_key(market_contract_id, nft_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = listings)]
 pub struct Listing {
     pub block_height: Option<i64>,
+    #[diesel(select_expression = listings::block_time.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<listings::block_time>)]
     pub block_time: Option<NaiveDateTime>,
+    #[diesel(select_expression = listings::market_contract_id.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<listings::market_contract_id>)]
     pub market_contract_id: Option<String>,
     pub listed: Option<bool>,
     pub market_name: Option<String>,
     pub collection_id: Option<String>,
+    #[diesel(select_expression = listings::nft_id.nullable())]
+    #[diesel(select_expression_type = diesel::dsl::Nullable<listings::nft_id>)]
     pub nft_id: Option<String>,
     pub nonce: Option<String>,
-    pub price: Option<i64>,
+    pub price: Option<BigDecimal>,
     pub price_str: Option<String>,
     pub seller: Option<String>,
     pub tx_index: Option<i64>,
+    /// Number of tokens still listed, `None` when the listed amount is unknown
+    pub remaining_count: Option<i64>,
+    pub status: Option<ListingStatus>,
+    pub expires_at: Option<NaiveDateTime>,
+    /// Coin type or fungible asset metadata address the price is paid in
+    pub payment_token: Option<String>,
+}
+
+impl Listing {
+    /// Returns true if the nft was transferred or burned in a transaction after it was listed
+    /// and the seller no longer holds it, so the listing can't be filled anymore.
+    ///
+    /// Marketplaces that escrow listed nfts transfer them in the listing transaction, which is
+    /// never considered. Listings of more than one token are left alone, as a transfer doesn't
+    /// tell how many tokens the seller still holds.
+    pub fn is_stale(&self, ownership_change: &Action) -> bool {
+        let Some(tx_index) = self.tx_index else {
+            return false;
+        };
+        // tx_index is txn_version * 100_000 + event_index
+        let is_later_txn = ownership_change.tx_index / 100_000 > tx_index / 100_000;
+        let is_multi_token = self
+            .remaining_count
+            .map_or(false, |remaining| remaining > 1);
+        if !is_later_txn || is_multi_token {
+            return false;
+        }
+
+        let tx_type = ownership_change
+            .tx_type
+            .as_deref()
+            .and_then(|tx_type| tx_type.parse::<MarketplaceEventType>().ok());
+        match tx_type {
+            Some(MarketplaceEventType::Burn) => true,
+            Some(MarketplaceEventType::Transfer) => {
+                ownership_change
+                    .receiver
+                    .as_deref()
+                    .map(standardize_address)
+                    != self.seller.as_deref().map(standardize_address)
+            },
+            _ => false,
+        }
+    }
+
+    /// Marks a listing invalid unless it was listed again in the meantime.
+    pub async fn invalidate(conn: &mut DbPoolConnection<'_>, listing: &Self) -> QueryResult<usize> {
+        let (Some(market_contract_id), Some(nft_id), Some(tx_index)) = (
+            &listing.market_contract_id,
+            &listing.nft_id,
+            listing.tx_index,
+        ) else {
+            return Ok(0);
+        };
+
+        diesel::update(listings::table)
+            .filter(listings::market_contract_id.eq(market_contract_id))
+            .filter(listings::nft_id.eq(nft_id))
+            .filter(listings::tx_index.eq(tx_index))
+            .filter(listings::listed.eq(true))
+            .set((
+                listings::status.eq(ListingStatus::Invalid),
+                listings::listed.eq(false),
+            ))
+            .execute(conn)
+            .await
+    }
+
+    /// Marks the listings of a marketplace that expired at or before `chain_time` as expired,
+    /// and returns them.
+    pub async fn expire(
+        conn: &mut DbPoolConnection<'_>,
+        market_name: &str,
+        chain_time: NaiveDateTime,
+    ) -> QueryResult<Vec<Self>> {
+        diesel::update(listings::table)
+            .filter(listings::market_name.eq(market_name))
+            .filter(listings::status.eq(ListingStatus::Listed))
+            .filter(listings::expires_at.le(chain_time))
+            .set((
+                listings::status.eq(ListingStatus::Expired),
+                listings::listed.eq(false),
+            ))
+            .returning(Self::as_returning())
+            .get_results(conn)
+            .await
+    }
+
+    /// Returns the listings of a marketplace for the `nft_ids`.
+    pub async fn get_by_nft_ids(
+        conn: &mut DbPoolConnection<'_>,
+        market_contract_id: &str,
+        nft_ids: &[String],
+    ) -> QueryResult<Vec<Self>> {
+        listings::table
+            .filter(listings::market_contract_id.eq(market_contract_id))
+            .filter(listings::nft_id.eq_any(nft_ids))
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Returns the active listings of a collection, cheapest first.
+    pub async fn get_active_by_collection(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<Vec<Self>> {
+        listings::table
+            .filter(listings::collection_id.eq(collection_id))
+            .filter(listings::listed.eq(true))
+            .order((listings::price.asc().nulls_last(), listings::nft_id.asc()))
+            .select(Self::as_select())
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the active listings of the nfts, cheapest first.
+    pub async fn get_active_by_nft_ids(
+        conn: &mut DbPoolConnection<'_>,
+        nft_ids: &[String],
+    ) -> QueryResult<Vec<Self>> {
+        listings::table
+            .filter(listings::nft_id.eq_any(nft_ids))
+            .filter(listings::listed.eq(true))
+            .order((listings::price.asc().nulls_last(), listings::nft_id.asc()))
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Returns the listings matching `filter`, cheapest first.
+    pub async fn get_filtered(
+        conn: &mut DbPoolConnection<'_>,
+        filter: &ListingFilter,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<Vec<Self>> {
+        let mut query = listings::table
+            .filter(listings::listed.eq(filter.listed.unwrap_or(true)))
+            .select(Self::as_select())
+            .into_boxed();
+
+        if let Some(collection_id) = &filter.collection_id {
+            query = query.filter(listings::collection_id.eq(collection_id));
+        }
+        if let Some(nft_id) = &filter.nft_id {
+            query = query.filter(listings::nft_id.eq(nft_id));
+        }
+        if let Some(seller) = &filter.seller {
+            query = query.filter(listings::seller.eq(seller));
+        }
+        if let Some(marketplace) = &filter.marketplace {
+            query = query.filter(listings::market_name.eq(marketplace));
+        }
+        if let Some(min_price) = filter.min_price {
+            query = query.filter(listings::price.ge(min_price));
+        }
+        if let Some(max_price) = filter.max_price {
+            query = query.filter(listings::price.le(max_price));
+        }
+
+        query
+            .order((listings::price.asc().nulls_last(), listings::nft_id.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+}
+
+/// Invalidates the active listings of the nfts whose seller no longer holds them, and returns
+/// them. Called by both pipelines, as either can run ahead of the other: the token pipeline
+/// after storing transfers and burns, and the marketplace pipeline after storing new listings.
+pub async fn invalidate_stale_listings(
+    conn: &mut DbPoolConnection<'_>,
+    nft_ids: impl IntoIterator<Item = String>,
+) -> QueryResult<Vec<Listing>> {
+    let mut nft_ids = nft_ids.into_iter().collect::<Vec<_>>();
+    nft_ids.sort();
+    nft_ids.dedup();
+    if nft_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let listings = Listing::get_active_by_nft_ids(conn, &nft_ids).await?;
+    if listings.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let listed_nft_ids = listings
+        .iter()
+        .filter_map(|listing| listing.nft_id.clone())
+        .collect::<Vec<_>>();
+    let ownership_changes = Action::get_last_ownership_changes(conn, &listed_nft_ids)
+        .await?
+        .into_iter()
+        .filter_map(|action| Some((action.nft_id.clone()?, action)))
+        .collect::<AHashMap<_, _>>();
+
+    let mut invalidated = vec![];
+    for listing in listings {
+        let is_stale = listing
+            .nft_id
+            .as_ref()
+            .and_then(|nft_id| ownership_changes.get(nft_id))
+            .map_or(false, |ownership_change| listing.is_stale(ownership_change));
+        if is_stale && Listing::invalidate(conn, &listing).await? > 0 {
+            invalidated.push(listing);
+        }
+    }
+
+    Ok(invalidated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing() -> Listing {
+        Listing {
+            seller: Some("0x1".to_string()),
+            tx_index: Some(10 * 100_000 + 2),
+            listed: Some(true),
+            ..Default::default()
+        }
+    }
+
+    fn ownership_change(tx_type: MarketplaceEventType, tx_index: i64, receiver: &str) -> Action {
+        Action {
+            tx_type: Some(tx_type.to_string()),
+            tx_index,
+            receiver: Some(receiver.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_stale() {
+        use MarketplaceEventType::*;
+
+        assert!(listing().is_stale(&ownership_change(Transfer, 11 * 100_000, "0x2")));
+        assert!(listing().is_stale(&ownership_change(Burn, 11 * 100_000, "")));
+
+        // Back with the seller
+        assert!(!listing().is_stale(&ownership_change(Transfer, 11 * 100_000, "0x01")));
+        // Escrowed in the listing transaction
+        assert!(!listing().is_stale(&ownership_change(Transfer, 10 * 100_000 + 1, "0x2")));
+        assert!(!listing().is_stale(&ownership_change(Transfer, 9 * 100_000, "0x2")));
+        // Sales are followed by a transfer
+        assert!(!listing().is_stale(&ownership_change(Buy, 11 * 100_000, "0x2")));
+
+        let multi_token = Listing {
+            remaining_count: Some(3),
+            ..listing()
+        };
+        assert!(!multi_token.is_stale(&ownership_change(Transfer, 11 * 100_000, "0x2")));
+    }
 }
@@ -0,0 +1,350 @@
+use crate::{
+    models::db::{bid::Bid, listing::Listing},
+    schema::market_depth,
+};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    sql_types::{Array, Text},
+};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+pub const NFT_DEPTH_TYPE: &str = "nft";
+pub const COLLECTION_DEPTH_TYPE: &str = "collection";
+
+const COLLECTION_BID_TYPE: &str = "collection";
+
+/// Lowest active ask and highest active bid across all marketplaces, with the marketplace
+/// and order holding them, of an nft or a collection.
+///
+/// The best bid of an nft merges its solo bids with the collection bids of its collection,
+/// while the best bid of a collection only considers collection bids. Prices are compared in
+/// APT, orders paid in other tokens are left out.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(depth_type, id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = market_depth)]
+pub struct MarketDepth {
+    /// `nft` or `collection`
+    pub depth_type: String,
+    /// Nft id or collection id
+    pub id: String,
+    pub collection_id: Option<String>,
+    pub best_ask: Option<BigDecimal>,
+    pub best_ask_market_name: Option<String>,
+    pub best_ask_market_contract_id: Option<String>,
+    pub best_ask_nft_id: Option<String>,
+    pub best_ask_nonce: Option<String>,
+    pub best_bid: Option<BigDecimal>,
+    pub best_bid_market_name: Option<String>,
+    pub best_bid_market_contract_id: Option<String>,
+    pub best_bid_nonce: Option<String>,
+    /// `solo` or `collection`
+    pub best_bid_type: Option<String>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl MarketDepth {
+    /// Market depth of an nft that has no listing or solo bid, and so no row of its own: only
+    /// the best collection bid applies.
+    pub fn from_collection_depth(nft_id: &str, collection_depth: Self) -> Self {
+        Self {
+            depth_type: NFT_DEPTH_TYPE.to_string(),
+            id: nft_id.to_string(),
+            collection_id: collection_depth.collection_id,
+            best_bid: collection_depth.best_bid,
+            best_bid_market_name: collection_depth.best_bid_market_name,
+            best_bid_market_contract_id: collection_depth.best_bid_market_contract_id,
+            best_bid_nonce: collection_depth.best_bid_nonce,
+            best_bid_type: collection_depth.best_bid_type,
+            updated_at: collection_depth.updated_at,
+            ..Default::default()
+        }
+    }
+
+    /// Recomputes the market depth of the given nfts from their active listings, their active
+    /// solo bids and the active collection bids of their collection.
+    pub async fn refresh_nfts(
+        conn: &mut DbPoolConnection<'_>,
+        nft_ids: &[String],
+    ) -> diesel::QueryResult<usize> {
+        if nft_ids.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::sql_query(
+            r#"
+            INSERT INTO market_depth (
+                depth_type, id, collection_id, best_ask, best_ask_market_name,
+                best_ask_market_contract_id, best_ask_nft_id, best_ask_nonce, best_bid,
+                best_bid_market_name, best_bid_market_contract_id, best_bid_nonce, best_bid_type,
+                updated_at
+            )
+            SELECT
+                'nft',
+                n.id,
+                c.collection_id,
+                a.price,
+                a.market_name,
+                a.market_contract_id,
+                a.nft_id,
+                a.nonce,
+                b.price,
+                b.market_name,
+                b.market_contract_id,
+                b.nonce,
+                b.bid_type,
+                NOW()
+            FROM UNNEST($1::VARCHAR[]) AS n(id)
+            LEFT JOIN nfts nft ON nft.id = n.id
+            LEFT JOIN LATERAL (
+                SELECT price, market_name, market_contract_id, nft_id, nonce, collection_id
+                FROM listings
+                WHERE nft_id = n.id AND listed = true AND price IS NOT NULL
+                    AND payment_token = '0x1::aptos_coin::AptosCoin'
+                ORDER BY price ASC, tx_index ASC
+                LIMIT 1
+            ) a ON true
+            CROSS JOIN LATERAL (
+                SELECT COALESCE(nft.collection_id, a.collection_id) AS collection_id
+            ) c
+            LEFT JOIN LATERAL (
+                SELECT * FROM (
+                    (
+                        SELECT price, market_name, market_contract_id, nonce, bid_type
+                        FROM bids
+                        WHERE nft_id = n.id AND status = 'active' AND price IS NOT NULL
+                            AND payment_token = '0x1::aptos_coin::AptosCoin'
+                        ORDER BY price DESC, nonce ASC
+                        LIMIT 1
+                    )
+                    UNION ALL
+                    (
+                        SELECT price, market_name, market_contract_id, nonce, bid_type
+                        FROM bids
+                        WHERE collection_id = c.collection_id
+                            AND bid_type = 'collection'
+                            AND status = 'active'
+                            AND price IS NOT NULL
+                            AND payment_token = '0x1::aptos_coin::AptosCoin'
+                        ORDER BY price DESC, nonce ASC
+                        LIMIT 1
+                    )
+                ) merged
+                ORDER BY price DESC, nonce ASC
+                LIMIT 1
+            ) b ON true
+            ON CONFLICT (depth_type, id) DO UPDATE SET
+                collection_id = EXCLUDED.collection_id,
+                best_ask = EXCLUDED.best_ask,
+                best_ask_market_name = EXCLUDED.best_ask_market_name,
+                best_ask_market_contract_id = EXCLUDED.best_ask_market_contract_id,
+                best_ask_nft_id = EXCLUDED.best_ask_nft_id,
+                best_ask_nonce = EXCLUDED.best_ask_nonce,
+                best_bid = EXCLUDED.best_bid,
+                best_bid_market_name = EXCLUDED.best_bid_market_name,
+                best_bid_market_contract_id = EXCLUDED.best_bid_market_contract_id,
+                best_bid_nonce = EXCLUDED.best_bid_nonce,
+                best_bid_type = EXCLUDED.best_bid_type,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind::<Array<Text>, _>(nft_ids)
+        .execute(conn)
+        .await
+    }
+
+    /// Recomputes the market depth of the given collections from their active listings and
+    /// active collection bids.
+    pub async fn refresh_collections(
+        conn: &mut DbPoolConnection<'_>,
+        collection_ids: &[String],
+    ) -> diesel::QueryResult<usize> {
+        if collection_ids.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::sql_query(
+            r#"
+            INSERT INTO market_depth (
+                depth_type, id, collection_id, best_ask, best_ask_market_name,
+                best_ask_market_contract_id, best_ask_nft_id, best_ask_nonce, best_bid,
+                best_bid_market_name, best_bid_market_contract_id, best_bid_nonce, best_bid_type,
+                updated_at
+            )
+            SELECT
+                'collection',
+                c.id,
+                c.id,
+                a.price,
+                a.market_name,
+                a.market_contract_id,
+                a.nft_id,
+                a.nonce,
+                b.price,
+                b.market_name,
+                b.market_contract_id,
+                b.nonce,
+                b.bid_type,
+                NOW()
+            FROM UNNEST($1::VARCHAR[]) AS c(id)
+            LEFT JOIN LATERAL (
+                SELECT price, market_name, market_contract_id, nft_id, nonce
+                FROM listings
+                WHERE collection_id = c.id AND listed = true AND price IS NOT NULL
+                    AND payment_token = '0x1::aptos_coin::AptosCoin'
+                ORDER BY price ASC, tx_index ASC
+                LIMIT 1
+            ) a ON true
+            LEFT JOIN LATERAL (
+                SELECT price, market_name, market_contract_id, nonce, bid_type
+                FROM bids
+                WHERE collection_id = c.id
+                    AND bid_type = 'collection'
+                    AND status = 'active'
+                    AND price IS NOT NULL
+                    AND payment_token = '0x1::aptos_coin::AptosCoin'
+                ORDER BY price DESC, nonce ASC
+                LIMIT 1
+            ) b ON true
+            ON CONFLICT (depth_type, id) DO UPDATE SET
+                best_ask = EXCLUDED.best_ask,
+                best_ask_market_name = EXCLUDED.best_ask_market_name,
+                best_ask_market_contract_id = EXCLUDED.best_ask_market_contract_id,
+                best_ask_nft_id = EXCLUDED.best_ask_nft_id,
+                best_ask_nonce = EXCLUDED.best_ask_nonce,
+                best_bid = EXCLUDED.best_bid,
+                best_bid_market_name = EXCLUDED.best_bid_market_name,
+                best_bid_market_contract_id = EXCLUDED.best_bid_market_contract_id,
+                best_bid_nonce = EXCLUDED.best_bid_nonce,
+                best_bid_type = EXCLUDED.best_bid_type,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind::<Array<Text>, _>(collection_ids)
+        .execute(conn)
+        .await
+    }
+
+    /// Returns the nfts of the collections that have a market depth row.
+    pub async fn get_nft_ids_by_collections(
+        conn: &mut DbPoolConnection<'_>,
+        collection_ids: &[String],
+    ) -> diesel::QueryResult<Vec<String>> {
+        market_depth::table
+            .filter(market_depth::depth_type.eq(NFT_DEPTH_TYPE))
+            .filter(market_depth::collection_id.eq_any(collection_ids))
+            .select(market_depth::id)
+            .load::<String>(conn)
+            .await
+    }
+
+    pub async fn get(
+        conn: &mut DbPoolConnection<'_>,
+        depth_type: &str,
+        id: &str,
+    ) -> diesel::QueryResult<Option<Self>> {
+        market_depth::table
+            .filter(market_depth::depth_type.eq(depth_type))
+            .filter(market_depth::id.eq(id))
+            .select(Self::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+}
+
+/// Nfts and collections whose market depth changed in a batch.
+#[derive(Clone, Debug, Default)]
+pub struct MarketDepthChanges {
+    nft_ids: BTreeSet<String>,
+    collection_ids: BTreeSet<String>,
+    /// Collections whose collection bids changed, which changes the best bid of all their nfts
+    offer_collection_ids: BTreeSet<String>,
+}
+
+impl MarketDepthChanges {
+    pub fn add_listing(&mut self, listing: &Listing) {
+        self.nft_ids.extend(listing.nft_id.clone());
+        self.collection_ids.extend(listing.collection_id.clone());
+    }
+
+    pub fn add_bid(&mut self, bid: &Bid) {
+        self.nft_ids.extend(bid.nft_id.clone());
+        self.collection_ids.extend(bid.collection_id.clone());
+        if bid.bid_type.as_deref() == Some(COLLECTION_BID_TYPE) {
+            self.offer_collection_ids.extend(bid.collection_id.clone());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nft_ids.is_empty() && self.collection_ids.is_empty()
+    }
+
+    /// Recomputes the changed collections, then the changed nfts along with the nfts of the
+    /// collections whose collection bids changed.
+    pub async fn refresh(self, conn: &mut DbPoolConnection<'_>) -> diesel::QueryResult<usize> {
+        let collection_ids = self.collection_ids.into_iter().collect::<Vec<_>>();
+        let offer_collection_ids = self.offer_collection_ids.into_iter().collect::<Vec<_>>();
+
+        let mut nft_ids = self.nft_ids;
+        if !offer_collection_ids.is_empty() {
+            nft_ids.extend(
+                MarketDepth::get_nft_ids_by_collections(conn, &offer_collection_ids).await?,
+            );
+        }
+        let nft_ids = nft_ids.into_iter().collect::<Vec<_>>();
+
+        let collection_count = MarketDepth::refresh_collections(conn, &collection_ids).await?;
+        let nft_count = MarketDepth::refresh_nfts(conn, &nft_ids).await?;
+
+        Ok(collection_count + nft_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection_bids_refresh_the_nfts_of_their_collection() {
+        let mut changes = MarketDepthChanges::default();
+        changes.add_listing(&Listing {
+            nft_id: Some("0xnft".to_string()),
+            collection_id: Some("0xcollection".to_string()),
+            ..Default::default()
+        });
+        changes.add_bid(&Bid {
+            nft_id: Some("0xnft".to_string()),
+            collection_id: Some("0xcollection".to_string()),
+            bid_type: Some("solo".to_string()),
+            ..Default::default()
+        });
+        assert!(changes.offer_collection_ids.is_empty());
+
+        changes.add_bid(&Bid {
+            collection_id: Some("0xother".to_string()),
+            bid_type: Some(COLLECTION_BID_TYPE.to_string()),
+            ..Default::default()
+        });
+        assert_eq!(changes.nft_ids.len(), 1);
+        assert_eq!(changes.collection_ids.len(), 2);
+        assert!(changes.offer_collection_ids.contains("0xother"));
+    }
+}
@@ -1,8 +1,21 @@
 pub mod action;
+pub mod activity_error;
+pub mod anomaly;
 pub mod attributes;
 pub mod bid;
 pub mod collection;
+pub mod collection_stats;
 pub mod commission;
+pub mod dead_letter_event;
+pub mod fill;
 pub mod listing;
+pub mod market_depth;
 pub mod nft;
+pub mod payment_token;
 pub mod price;
+pub mod rarity;
+pub mod sale_fee;
+pub mod trade_flag;
+pub mod wallet_transfer;
+pub mod webhook;
+pub mod worker_checkpoint;
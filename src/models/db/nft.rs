@@ -1,13 +1,13 @@
 use crate::{
     models::{
-        db::attributes::Attribute,
+        db::attributes::{Attribute, TraitFilter},
         nft_metadata::NFTMetadata,
         resources::{
             token::{Token as TokenResourceData, TokenWriteSet},
             FromWriteResource, TYPE_TOKEN_STORE_V1,
         },
     },
-    schema::nfts,
+    schema::{attributes, listings, nfts},
     utils::{object_utils::ObjectAggregatedData, token_utils::TableMetadataForToken},
 };
 use ahash::{AHashMap, HashMap};
@@ -17,12 +17,34 @@ use aptos_indexer_processor_sdk::{
     postgres::utils::database::DbPoolConnection,
     utils::convert::standardize_address,
 };
+use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
+/// The metadata of the nft was fetched and stored
+pub const METADATA_STATUS_FETCHED: &str = "fetched";
+/// The last attempt to fetch the metadata of the nft failed
+pub const METADATA_STATUS_FAILED: &str = "failed";
+/// The uri of the nft doesn't serve a json document, e.g. it points to the media itself. It's
+/// not fetched again until the uri changes.
+pub const METADATA_STATUS_UNSUPPORTED: &str = "unsupported";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
+pub struct NftFilter {
+    pub collection_id: Option<String>,
+    pub owner: Option<String>,
+    /// Every trait has to match
+    pub traits: Option<Vec<TraitFilter>>,
+    /// The price range and marketplace match the active listings of the nft
+    pub min_price: Option<BigDecimal>,
+    pub max_price: Option<BigDecimal>,
+    pub marketplace: Option<String>,
+}
+
 #[derive(
     Clone,
     Debug,
@@ -35,6 +57,11 @@ use serde::{Deserialize, Serialize};
     Queryable,
     Selectable,
 )]
+#[cfg_attr(
+    feature = "graphql",
+    derive(async_graphql::SimpleObject),
+    graphql(complex)
+)]
 #[diesel(primary_key(id))]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(table_name = nfts)]
@@ -44,6 +71,7 @@ pub struct Nft {
     pub owner: Option<String>,
     pub collection_id: Option<String>,
     pub burned: Option<bool>,
+    #[cfg_attr(feature = "graphql", graphql(skip))]
     pub properties: Option<serde_json::Value>,
     pub description: Option<String>,
     pub background_color: Option<String>,
@@ -55,6 +83,11 @@ pub struct Nft {
     pub image_url: Option<String>,
     pub version: Option<String>,
     pub created_at: Option<NaiveDateTime>,
+    pub uri: Option<String>,
+    pub metadata_status: Option<String>,
+    pub metadata_attempts: Option<i32>,
+    pub metadata_error: Option<String>,
+    pub metadata_updated_at: Option<NaiveDateTime>,
 }
 
 impl Nft {
@@ -69,6 +102,7 @@ impl Nft {
                 id: token_addr.clone(),
                 collection_id: Some(inner.get_collection_address()),
                 name: Some(inner.name),
+                uri: Some(inner.uri.clone()),
                 image_url: Some(inner.uri),
                 description: Some(inner.description),
                 version: Some("v2".to_string()),
@@ -138,6 +172,7 @@ impl Nft {
                         owner: owner_address,
                         collection_id: Some(token_data_id_struct.get_collection_addr()),
                         name: Some(token_data.name),
+                        uri: Some(token_data.uri.clone()),
                         image_url: Some(token_data.uri),
                         properties: Some(token_data.default_properties),
                         description: Some(token_data.description),
@@ -214,26 +249,182 @@ impl Nft {
         None
     }
 
-    pub async fn get_nfts(
+    /// Returns the nfts whose metadata has never been fetched, failed to be fetched and is due
+    /// for a retry, or was fetched before `stale_before`.
+    pub async fn get_nfts_pending_metadata(
         conn: &mut DbPoolConnection<'_>,
-        offset: i64,
+        retry_before: NaiveDateTime,
+        stale_before: NaiveDateTime,
+        max_attempts: i32,
         limit: i64,
     ) -> diesel::QueryResult<Vec<Nft>> {
+        let never_fetched = nfts::metadata_status.is_null();
+        let retry_failed = nfts::metadata_status
+            .eq(METADATA_STATUS_FAILED)
+            .and(nfts::metadata_attempts.lt(max_attempts))
+            .and(nfts::metadata_updated_at.lt(retry_before));
+        let refresh_stale = nfts::metadata_status
+            .eq(METADATA_STATUS_FETCHED)
+            .and(nfts::metadata_updated_at.lt(stale_before));
+
         nfts::dsl::nfts
-            .filter(nfts::image_url.ilike("%.json"))
+            .filter(nfts::uri.is_not_null())
+            .filter(nfts::burned.is_distinct_from(true))
+            .filter(never_fetched.or(retry_failed).or(refresh_stale))
+            .select(Nft::as_select())
+            .order((nfts::metadata_attempts.asc(), nfts::created_at.asc()))
+            .limit(limit)
+            .load::<Nft>(conn)
+            .await
+    }
+
+    /// Records a failed metadata fetch so the nft is retried later with backoff.
+    pub async fn mark_metadata_failed(
+        conn: &mut DbPoolConnection<'_>,
+        nft_id: &str,
+        error: &str,
+        failed_at: NaiveDateTime,
+    ) -> diesel::QueryResult<usize> {
+        diesel::update(nfts::table.filter(nfts::id.eq(nft_id)))
+            .set((
+                nfts::metadata_status.eq(METADATA_STATUS_FAILED),
+                nfts::metadata_attempts.eq(nfts::metadata_attempts + 1),
+                nfts::metadata_error.eq(error),
+                nfts::metadata_updated_at.eq(failed_at),
+            ))
+            .execute(conn)
+            .await
+    }
+
+    pub async fn mark_metadata_unsupported(
+        conn: &mut DbPoolConnection<'_>,
+        nft_id: &str,
+        error: &str,
+        checked_at: NaiveDateTime,
+    ) -> diesel::QueryResult<usize> {
+        diesel::update(nfts::table.filter(nfts::id.eq(nft_id)))
+            .set((
+                nfts::metadata_status.eq(METADATA_STATUS_UNSUPPORTED),
+                nfts::metadata_error.eq(error),
+                nfts::metadata_updated_at.eq(checked_at),
+            ))
+            .execute(conn)
+            .await
+    }
+
+    /// Returns the nfts currently held by `owner`.
+    pub async fn get_by_owner(
+        conn: &mut DbPoolConnection<'_>,
+        owner: &str,
+        limit: i64,
+        offset: i64,
+    ) -> diesel::QueryResult<Vec<Nft>> {
+        nfts::table
+            .filter(nfts::owner.eq(owner))
+            .filter(nfts::burned.is_distinct_from(true))
             .select(Nft::as_select())
-            .order(nfts::created_at.asc())
+            .order(nfts::id.asc())
             .limit(limit)
             .offset(offset)
             .load::<Nft>(conn)
             .await
     }
 
-    pub async fn count_nfts(conn: &mut DbPoolConnection<'_>) -> diesel::QueryResult<i64> {
-        nfts::dsl::nfts
-            .filter(nfts::image_url.ilike("%.json"))
-            .count()
-            .get_result(conn)
+    /// Returns the `(collection_id, owner)` pairs of the given nfts that have both.
+    pub async fn get_collection_owners(
+        conn: &mut DbPoolConnection<'_>,
+        nft_ids: &[String],
+    ) -> diesel::QueryResult<Vec<(String, String)>> {
+        nfts::table
+            .filter(nfts::id.eq_any(nft_ids))
+            .filter(nfts::collection_id.is_not_null())
+            .filter(nfts::owner.is_not_null())
+            .select((
+                nfts::collection_id.assume_not_null(),
+                nfts::owner.assume_not_null(),
+            ))
+            .load::<(String, String)>(conn)
+            .await
+    }
+
+    /// Returns the nfts of a collection that are not burned.
+    pub async fn get_by_collection(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> diesel::QueryResult<Vec<Nft>> {
+        nfts::table
+            .filter(nfts::collection_id.eq(collection_id))
+            .filter(nfts::burned.is_distinct_from(true))
+            .select(Nft::as_select())
+            .order(nfts::id.asc())
+            .limit(limit)
+            .offset(offset)
+            .load::<Nft>(conn)
+            .await
+    }
+
+    pub async fn get_by_id(
+        conn: &mut DbPoolConnection<'_>,
+        nft_id: &str,
+    ) -> diesel::QueryResult<Option<Nft>> {
+        nfts::table
+            .filter(nfts::id.eq(nft_id))
+            .select(Nft::as_select())
+            .first::<Nft>(conn)
+            .await
+            .optional()
+    }
+
+    /// Returns the nfts that are not burned and match `filter`.
+    pub async fn get_filtered(
+        conn: &mut DbPoolConnection<'_>,
+        filter: &NftFilter,
+        limit: i64,
+        offset: i64,
+    ) -> diesel::QueryResult<Vec<Nft>> {
+        let mut query = nfts::table
+            .filter(nfts::burned.is_distinct_from(true))
+            .select(Nft::as_select())
+            .into_boxed();
+
+        if let Some(collection_id) = &filter.collection_id {
+            query = query.filter(nfts::collection_id.eq(collection_id));
+        }
+        if let Some(owner) = &filter.owner {
+            query = query.filter(nfts::owner.eq(owner));
+        }
+        for trait_filter in filter.traits.iter().flatten() {
+            let nft_ids = attributes::table
+                .filter(attributes::attr_type.eq(trait_filter.attr_type.clone()))
+                .filter(attributes::value.eq(trait_filter.value.clone()))
+                .select(attributes::nft_id);
+            query = query.filter(nfts::id.eq_any(nft_ids));
+        }
+        if filter.min_price.is_some() || filter.max_price.is_some() || filter.marketplace.is_some()
+        {
+            let mut listed = listings::table
+                .filter(listings::listed.eq(true))
+                .select(listings::nft_id)
+                .into_boxed();
+            if let Some(min_price) = filter.min_price {
+                listed = listed.filter(listings::price.ge(min_price));
+            }
+            if let Some(max_price) = filter.max_price {
+                listed = listed.filter(listings::price.le(max_price));
+            }
+            if let Some(marketplace) = &filter.marketplace {
+                listed = listed.filter(listings::market_name.eq(marketplace.clone()));
+            }
+            query = query.filter(nfts::id.eq_any(listed));
+        }
+
+        query
+            .order(nfts::id.asc())
+            .limit(limit)
+            .offset(offset)
+            .load::<Nft>(conn)
             .await
     }
 }
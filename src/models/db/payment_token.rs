@@ -0,0 +1,82 @@
+use crate::{config::payment_token_config::PaymentTokenConfig, schema::payment_tokens};
+use aptos_indexer_processor_sdk::{
+    postgres::utils::database::DbPoolConnection, utils::convert::standardize_address,
+};
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+/// Coin type of APT, which sales are paid in unless the event says otherwise
+pub const APT_COIN_TYPE: &str = "0x1::aptos_coin::AptosCoin";
+
+/// A coin or fungible asset that prices are denominated in. Prices are stored in its smallest
+/// unit, `decimals` converts them to whole tokens.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = payment_tokens)]
+pub struct PaymentToken {
+    /// Coin type or fungible asset metadata address
+    pub id: String,
+    pub symbol: Option<String>,
+    pub decimals: i32,
+}
+
+impl PaymentToken {
+    pub fn from_config(config: &PaymentTokenConfig) -> Self {
+        Self {
+            id: normalize_payment_token(&config.id),
+            symbol: config.symbol.clone(),
+            decimals: config.decimals,
+        }
+    }
+
+    pub async fn get_by_id(conn: &mut DbPoolConnection<'_>, id: &str) -> QueryResult<Option<Self>> {
+        payment_tokens::table
+            .find(id)
+            .select(Self::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Inserts the tokens, replacing the symbol and decimals of the existing ones.
+    pub async fn upsert_all(conn: &mut DbPoolConnection<'_>, items: &[Self]) -> QueryResult<usize> {
+        if items.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::insert_into(payment_tokens::table)
+            .values(items)
+            .on_conflict(payment_tokens::id)
+            .do_update()
+            .set((
+                payment_tokens::symbol.eq(excluded(payment_tokens::symbol)),
+                payment_tokens::decimals.eq(excluded(payment_tokens::decimals)),
+            ))
+            .execute(conn)
+            .await
+    }
+}
+
+/// Coin types are kept as they appear on chain, fungible asset metadata addresses are
+/// standardized.
+pub fn normalize_payment_token(payment_token: &str) -> String {
+    if payment_token.contains("::") {
+        payment_token.to_string()
+    } else {
+        standardize_address(payment_token)
+    }
+}
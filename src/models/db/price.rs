@@ -1,16 +1,77 @@
 use crate::schema::prices;
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
 use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
+/// A USD price candle of one whole payment token.
 #[derive(
     Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
 )]
-#[diesel(primary_key(created_at))]
+#[diesel(primary_key(payment_token, created_at))]
 #[diesel(table_name = prices)]
 pub struct Price {
     pub price: BigDecimal,
     pub created_at: NaiveDateTime,
+    pub payment_token: String,
+}
+
+impl Price {
+    /// Returns the USD price of `payment_token` from the closest candle at or before
+    /// `block_time`.
+    pub async fn get_price_at(
+        conn: &mut DbPoolConnection<'_>,
+        payment_token: &str,
+        block_time: NaiveDateTime,
+    ) -> diesel::QueryResult<Option<BigDecimal>> {
+        prices::table
+            .filter(prices::payment_token.eq(payment_token))
+            .filter(prices::created_at.le(block_time))
+            .order(prices::created_at.desc())
+            .select(prices::price)
+            .first::<BigDecimal>(conn)
+            .await
+            .optional()
+    }
+}
+
+/// Converts an amount in the smallest unit of a token with `decimals` decimals to USD using
+/// the USD price of one whole token, rounded to cents.
+pub fn calc_usd_price(amount: &BigDecimal, decimals: i32, usd_price: &BigDecimal) -> BigDecimal {
+    let whole = amount * BigDecimal::new(1.into(), decimals as i64);
+    (whole * usd_price).round(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_calc_usd_price() {
+        let apt_usd_price = BigDecimal::from_str("4.5").unwrap();
+        assert_eq!(
+            calc_usd_price(&BigDecimal::from(250_000_000), 8, &apt_usd_price),
+            BigDecimal::from_str("11.25").unwrap()
+        );
+
+        let usdc_usd_price = BigDecimal::from(1);
+        assert_eq!(
+            calc_usd_price(&BigDecimal::from(1_999_999), 6, &usdc_usd_price),
+            BigDecimal::from_str("2.00").unwrap()
+        );
+
+        // Amounts beyond the range of an i64
+        assert_eq!(
+            calc_usd_price(
+                &BigDecimal::from_str("100000000000000000000").unwrap(),
+                18,
+                &usdc_usd_price
+            ),
+            BigDecimal::from_str("100.00").unwrap()
+        );
+    }
 }
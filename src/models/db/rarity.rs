@@ -0,0 +1,150 @@
+use crate::schema::{collection_rarities, collections, nft_rarities};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{
+    pg::{upsert::excluded, Pg},
+    prelude::*,
+    query_builder::QueryFragment,
+};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+)]
+#[diesel(primary_key(nft_id))]
+#[diesel(table_name = nft_rarities)]
+pub struct NftRarity {
+    pub nft_id: String,
+    pub collection_id: String,
+    pub trait_count: Option<i64>,
+    pub rarity_score: Option<BigDecimal>,
+    pub statistical_rarity: Option<BigDecimal>,
+    pub rarity_rank: Option<i64>,
+    pub statistical_rank: Option<i64>,
+}
+
+/// Tracks when the rarity of a collection has to be recomputed. A collection is marked
+/// whenever its attributes or supply change, and is pending until computed after that.
+#[derive(
+    Clone, Debug, Default, Deserialize, FieldCount, Identifiable, Insertable, Serialize, Queryable,
+)]
+#[diesel(primary_key(collection_id))]
+#[diesel(table_name = collection_rarities)]
+pub struct CollectionRarity {
+    pub collection_id: String,
+    pub supply: Option<i64>,
+    pub marked_at: Option<NaiveDateTime>,
+    pub computed_at: Option<NaiveDateTime>,
+}
+
+impl CollectionRarity {
+    /// Returns the collections marked before `marked_before` that were not computed since.
+    pub async fn get_pending_collection_ids(
+        conn: &mut DbPoolConnection<'_>,
+        marked_before: NaiveDateTime,
+        limit: i64,
+    ) -> diesel::QueryResult<Vec<String>> {
+        collection_rarities::table
+            .filter(collection_rarities::marked_at.lt(marked_before))
+            .filter(
+                collection_rarities::computed_at
+                    .is_null()
+                    .or(collection_rarities::computed_at
+                        .lt(collection_rarities::marked_at.nullable())),
+            )
+            .order(collection_rarities::marked_at.asc())
+            .select(collection_rarities::collection_id)
+            .limit(limit)
+            .load::<String>(conn)
+            .await
+    }
+
+    pub async fn get_supply(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+    ) -> diesel::QueryResult<Option<i64>> {
+        collections::table
+            .filter(collections::id.eq(collection_id))
+            .select(collections::supply)
+            .first::<Option<i64>>(conn)
+            .await
+            .optional()
+            .map(Option::flatten)
+    }
+
+    pub async fn set_computed(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+        computed_at: NaiveDateTime,
+    ) -> diesel::QueryResult<usize> {
+        diesel::update(
+            collection_rarities::table
+                .filter(collection_rarities::collection_id.eq(collection_id)),
+        )
+        .set(collection_rarities::computed_at.eq(computed_at))
+        .execute(conn)
+        .await
+    }
+}
+
+impl CollectionRarity {
+    pub fn new(collection_id: String, supply: Option<i64>, marked_at: NaiveDateTime) -> Self {
+        Self {
+            collection_id,
+            supply,
+            marked_at: Some(marked_at),
+            computed_at: None,
+        }
+    }
+}
+
+/// Marks the collections whose supply changed since they were last marked.
+pub fn insert_supply_changes(
+    items_to_insert: Vec<CollectionRarity>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::collection_rarities::dsl::*;
+
+    let query = diesel::insert_into(collection_rarities)
+        .values(items_to_insert)
+        .on_conflict(collection_id)
+        .do_update()
+        .set((supply.eq(excluded(supply)), marked_at.eq(excluded(marked_at))));
+
+    // Called through FilterDsl since QueryDsl::filter doesn't apply to upserts
+    diesel::query_dsl::methods::FilterDsl::filter(query, supply.is_distinct_from(excluded(supply)))
+}
+
+/// Marks the collections whose attributes changed.
+pub fn insert_attribute_changes(
+    items_to_insert: Vec<CollectionRarity>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::collection_rarities::dsl::*;
+
+    diesel::insert_into(collection_rarities)
+        .values(items_to_insert)
+        .on_conflict(collection_id)
+        .do_update()
+        .set(marked_at.eq(excluded(marked_at)))
+}
+
+pub fn insert_nft_rarities(
+    items_to_insert: Vec<NftRarity>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::nft_rarities::dsl::*;
+
+    diesel::insert_into(nft_rarities)
+        .values(items_to_insert)
+        .on_conflict(nft_id)
+        .do_update()
+        .set((
+            collection_id.eq(excluded(collection_id)),
+            trait_count.eq(excluded(trait_count)),
+            rarity_score.eq(excluded(rarity_score)),
+            statistical_rarity.eq(excluded(statistical_rarity)),
+            rarity_rank.eq(excluded(rarity_rank)),
+            statistical_rank.eq(excluded(statistical_rank)),
+        ))
+}
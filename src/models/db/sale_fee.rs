@@ -0,0 +1,294 @@
+use crate::{
+    config::marketplace_config::FeeSchedule,
+    models::{db::commission::Commission, marketplace::NftMarketplaceActivity},
+    schema::sale_fees,
+};
+use aptos_indexer_processor_sdk::{
+    postgres::utils::database::DbPoolConnection, utils::convert::standardize_address,
+};
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use chrono::NaiveDateTime;
+use diesel::{pg::Pg, prelude::*, query_builder::QueryFragment};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+pub const SALE_FEES_TABLE_NAME: &str = "sale_fees";
+
+/// Where the amounts of a [`SaleFee`] come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum SaleFeeSource {
+    /// The coins deposited to the seller, the royalty payee and the fee receiver by the sale
+    /// transaction
+    Events,
+    /// The fee schedule of the marketplace config and the royalty of the nft
+    FeeSchedule,
+    /// Only the royalty of the nft is known
+    Royalty,
+}
+
+/// Breakdown of the price of a sale into royalty, marketplace fee and seller proceeds, keyed
+/// by the action of the sale.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(tx_index, tx_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = sale_fees)]
+pub struct SaleFee {
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub market_name: Option<String>,
+    pub market_contract_id: Option<String>,
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    pub seller: Option<String>,
+    pub price: BigDecimal,
+    pub royalty_amount: Option<BigDecimal>,
+    pub royalty_payee: Option<String>,
+    pub marketplace_fee: Option<BigDecimal>,
+    pub seller_proceeds: Option<BigDecimal>,
+    pub source: String,
+    pub block_time: NaiveDateTime,
+}
+
+impl SaleFee {
+    /// Breaks down the price of a sale activity.
+    ///
+    /// The coin deposits of the sale transaction are used when the seller received any of them.
+    /// The royalty is then what the royalty payee received, and the marketplace fee what the fee
+    /// receiver received, or the rest of the price when the fee receiver isn't configured.
+    /// Otherwise the fee schedule and the royalty rate of the nft are applied to the price.
+    /// Returns `None` when the price of the sale is unknown.
+    pub fn new(
+        activity: &NftMarketplaceActivity,
+        commission: Option<&Commission>,
+        fee_schedule: Option<&FeeSchedule>,
+    ) -> Option<Self> {
+        let price = activity.price.clone()?;
+        let seller = activity.seller.as_deref().map(standardize_address);
+        let royalty_payee = commission.and_then(|commission| commission.payee_address.clone());
+        let royalty_rate_amount = commission
+            .and_then(|commission| commission.royalty.as_ref())
+            .map(|royalty| percent_of(&price, royalty));
+
+        let seller_deposit = seller
+            .as_ref()
+            .and_then(|seller| activity.payments.get(seller).cloned());
+        let (royalty_amount, marketplace_fee, seller_proceeds, source) =
+            match (seller_deposit, fee_schedule) {
+                (Some(seller_proceeds), _) => {
+                    // A creator selling their own nft can't be told apart from the royalty
+                    let royalty_amount = royalty_payee
+                        .as_ref()
+                        .filter(|payee| Some(*payee) != seller.as_ref())
+                        .map(|payee| activity.payments.get(payee).cloned().unwrap_or_default());
+                    let marketplace_fee = match fee_schedule
+                        .and_then(|fee_schedule| fee_schedule.fee_receiver.as_deref())
+                    {
+                        Some(fee_receiver) => Some(
+                            activity
+                                .payments
+                                .get(&standardize_address(fee_receiver))
+                                .cloned()
+                                .unwrap_or_default(),
+                        ),
+                        None => Some(
+                            &price - &seller_proceeds - royalty_amount.clone().unwrap_or_default(),
+                        )
+                        .filter(|fee| *fee >= BigDecimal::zero()),
+                    };
+
+                    (
+                        royalty_amount,
+                        marketplace_fee,
+                        Some(seller_proceeds),
+                        SaleFeeSource::Events,
+                    )
+                },
+                (None, Some(fee_schedule)) => {
+                    let marketplace_fee = (&price
+                        * BigDecimal::from(fee_schedule.marketplace_fee_bps)
+                        / BigDecimal::from(10_000))
+                    .with_scale_round(0, RoundingMode::Down);
+                    let seller_proceeds =
+                        &price - &marketplace_fee - royalty_rate_amount.clone().unwrap_or_default();
+
+                    (
+                        royalty_rate_amount,
+                        Some(marketplace_fee),
+                        Some(seller_proceeds),
+                        SaleFeeSource::FeeSchedule,
+                    )
+                },
+                (None, None) => (royalty_rate_amount, None, None, SaleFeeSource::Royalty),
+            };
+
+        Some(Self {
+            tx_index: activity.get_tx_index(),
+            tx_id: activity.txn_id.clone(),
+            market_name: activity.marketplace.clone(),
+            market_contract_id: activity.contract_address.clone(),
+            collection_id: activity.collection_addr.clone(),
+            nft_id: activity.token_addr.clone(),
+            seller,
+            price,
+            royalty_amount,
+            royalty_payee,
+            marketplace_fee,
+            seller_proceeds,
+            source: source.to_string(),
+            block_time: activity.block_timestamp,
+        })
+    }
+
+    /// Returns the breakdowns of the sales that paid royalty to `payee`, latest first.
+    pub async fn get_by_royalty_payee(
+        conn: &mut DbPoolConnection<'_>,
+        payee: &str,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<Vec<Self>> {
+        sale_fees::table
+            .filter(sale_fees::royalty_payee.eq(payee))
+            .select(Self::as_select())
+            .order((sale_fees::tx_index.desc(), sale_fees::tx_id.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+
+    /// Returns the breakdowns of the sales of a collection, latest first.
+    pub async fn get_by_collection(
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> QueryResult<Vec<Self>> {
+        sale_fees::table
+            .filter(sale_fees::collection_id.eq(collection_id))
+            .select(Self::as_select())
+            .order((sale_fees::tx_index.desc(), sale_fees::tx_id.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load(conn)
+            .await
+    }
+}
+
+/// Returns `percent`% of `amount`, rounded down.
+fn percent_of(amount: &BigDecimal, percent: &BigDecimal) -> BigDecimal {
+    (amount * percent / BigDecimal::from(100)).with_scale_round(0, RoundingMode::Down)
+}
+
+pub fn insert_sale_fees(
+    items_to_insert: Vec<SaleFee>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::sale_fees::dsl::*;
+
+    diesel::insert_into(sale_fees)
+        .values(items_to_insert)
+        .on_conflict((tx_index, tx_id))
+        .do_nothing()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::marketplace_config::MarketplaceEventType;
+    use std::{collections::HashMap, str::FromStr};
+
+    const SELLER: &str = "0x1";
+    const PAYEE: &str = "0x2";
+    const FEE_RECEIVER: &str = "0x3";
+
+    fn sale(payments: &[(&str, i64)]) -> NftMarketplaceActivity {
+        NftMarketplaceActivity {
+            standard_event_type: MarketplaceEventType::Buy,
+            price: Some(BigDecimal::from(1_000)),
+            seller: Some(SELLER.to_string()),
+            payments: payments
+                .iter()
+                .map(|(wallet, amount)| (standardize_address(wallet), BigDecimal::from(*amount)))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    fn commission() -> Commission {
+        Commission {
+            royalty: Some(BigDecimal::from_str("5").unwrap()),
+            payee_address: Some(standardize_address(PAYEE)),
+            ..Default::default()
+        }
+    }
+
+    fn fee_schedule(fee_receiver: Option<&str>) -> FeeSchedule {
+        FeeSchedule {
+            marketplace_fee_bps: 250,
+            fee_receiver: fee_receiver.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_breakdown_from_events() {
+        let activity = sale(&[(SELLER, 925), (PAYEE, 50), (FEE_RECEIVER, 25)]);
+
+        let fee = SaleFee::new(
+            &activity,
+            Some(&commission()),
+            Some(&fee_schedule(Some(FEE_RECEIVER))),
+        )
+        .unwrap();
+        assert_eq!(fee.source, "events");
+        assert_eq!(fee.royalty_amount, Some(BigDecimal::from(50)));
+        assert_eq!(fee.marketplace_fee, Some(BigDecimal::from(25)));
+        assert_eq!(fee.seller_proceeds, Some(BigDecimal::from(925)));
+
+        // Without a fee receiver the fee is what's left of the price
+        let fee = SaleFee::new(&activity, Some(&commission()), None).unwrap();
+        assert_eq!(fee.marketplace_fee, Some(BigDecimal::from(25)));
+
+        // A royalty that wasn't paid is recorded as zero
+        let activity = sale(&[(SELLER, 975), (FEE_RECEIVER, 25)]);
+        let fee = SaleFee::new(&activity, Some(&commission()), None).unwrap();
+        assert_eq!(fee.royalty_amount, Some(BigDecimal::from(0)));
+        assert_eq!(fee.royalty_payee, Some(standardize_address(PAYEE)));
+    }
+
+    #[test]
+    fn test_breakdown_from_fee_schedule() {
+        let fee = SaleFee::new(&sale(&[]), Some(&commission()), Some(&fee_schedule(None))).unwrap();
+        assert_eq!(fee.source, "fee_schedule");
+        assert_eq!(fee.royalty_amount, Some(BigDecimal::from(50)));
+        assert_eq!(fee.marketplace_fee, Some(BigDecimal::from(25)));
+        assert_eq!(fee.seller_proceeds, Some(BigDecimal::from(925)));
+
+        // Deposits that didn't reach the seller can't be attributed
+        let fee = SaleFee::new(&sale(&[(PAYEE, 50)]), None, Some(&fee_schedule(None))).unwrap();
+        assert_eq!(fee.source, "fee_schedule");
+        assert_eq!(fee.royalty_amount, None);
+        assert_eq!(fee.seller_proceeds, Some(BigDecimal::from(975)));
+    }
+
+    #[test]
+    fn test_breakdown_from_royalty() {
+        let fee = SaleFee::new(&sale(&[]), Some(&commission()), None).unwrap();
+        assert_eq!(fee.source, "royalty");
+        assert_eq!(fee.royalty_amount, Some(BigDecimal::from(50)));
+        assert_eq!(fee.marketplace_fee, None);
+        assert_eq!(fee.seller_proceeds, None);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{models::db::action::Action, schema::trade_flags};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{pg::Pg, prelude::*, query_builder::QueryFragment};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+pub const TRADE_FLAGS_TABLE_NAME: &str = "trade_flags";
+
+/// Why a sale looks like a wash trade
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum TradeFlagReason {
+    /// The same wallets traded the other way around shortly before
+    RoundTrip,
+    /// The buyer or the seller was recently funded by the other
+    MutualFunding,
+    /// The price is far above the recent median of the collection
+    PriceOutlier,
+}
+
+/// A sale flagged as a likely wash trade, keyed by the action of the sale and the reason.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(tx_index, tx_id, reason))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = trade_flags)]
+pub struct TradeFlag {
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub reason: String,
+    pub collection_id: Option<String>,
+    pub nft_id: Option<String>,
+    pub buyer: Option<String>,
+    pub seller: Option<String>,
+    pub price: Option<BigDecimal>,
+    pub details: Option<String>,
+    pub block_time: NaiveDateTime,
+}
+
+impl TradeFlag {
+    pub fn new(sale: &Action, reason: TradeFlagReason, details: String) -> Self {
+        Self {
+            tx_index: sale.tx_index,
+            tx_id: sale.tx_id.clone(),
+            reason: reason.to_string(),
+            collection_id: sale.collection_id.clone(),
+            nft_id: sale.nft_id.clone(),
+            buyer: sale.receiver.clone(),
+            seller: sale.sender.clone(),
+            price: sale.price.clone(),
+            details: Some(details),
+            block_time: sale.block_time.unwrap_or_default(),
+        }
+    }
+}
+
+pub fn insert_trade_flags(
+    items_to_insert: Vec<TradeFlag>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::trade_flags::dsl::*;
+
+    diesel::insert_into(trade_flags)
+        .values(items_to_insert)
+        .on_conflict((tx_index, tx_id, reason))
+        .do_nothing()
+}
@@ -0,0 +1,130 @@
+use crate::{models::db::payment_token::APT_COIN_TYPE, schema::wallet_transfers};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::{
+        transaction::TxnData, transaction_payload::Payload, Transaction,
+    },
+    postgres::utils::database::DbPoolConnection,
+    utils::convert::standardize_address,
+};
+use chrono::NaiveDateTime;
+use diesel::{pg::Pg, prelude::*, query_builder::QueryFragment};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+pub const WALLET_TRANSFERS_TABLE_NAME: &str = "wallet_transfers";
+
+/// (module, function) of the `0x1` entry functions that transfer coins to a wallet. They all
+/// take the receiver and the amount as their arguments.
+pub const WALLET_TRANSFER_FUNCTIONS: [(&str, &str); 3] = [
+    ("aptos_account", "transfer"),
+    ("aptos_account", "transfer_coins"),
+    ("coin", "transfer"),
+];
+
+/// A direct coin transfer from one wallet to another, i.e. a user transaction calling one of
+/// the [`WALLET_TRANSFER_FUNCTIONS`].
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(tx_index, tx_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = wallet_transfers)]
+pub struct WalletTransfer {
+    /// The transfer isn't tied to an event, so this is txn_version * 100_000
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub sender: String,
+    pub receiver: String,
+    /// Only known for APT transfers through `aptos_account::transfer`
+    pub coin_type: Option<String>,
+    pub amount: Option<i64>,
+    pub block_time: NaiveDateTime,
+}
+
+impl WalletTransfer {
+    pub fn from_transaction(
+        txn: &Transaction,
+        txn_id: &str,
+        block_time: NaiveDateTime,
+    ) -> Option<Self> {
+        let Some(TxnData::User(user_txn)) = txn.txn_data.as_ref() else {
+            return None;
+        };
+        let request = user_txn.request.as_ref()?;
+        let Some(Payload::EntryFunctionPayload(payload)) = request.payload.as_ref()?.payload.as_ref()
+        else {
+            return None;
+        };
+
+        let function = payload.function.as_ref()?;
+        let module = function.module.as_ref()?;
+        if standardize_address(&module.address) != standardize_address("0x1")
+            || !WALLET_TRANSFER_FUNCTIONS
+                .iter()
+                .any(|(name, function_name)| module.name == *name && function.name == *function_name)
+        {
+            return None;
+        }
+
+        let receiver = payload.arguments.first().map(|arg| parse_argument(arg))?;
+        let amount = payload
+            .arguments
+            .get(1)
+            .and_then(|arg| parse_argument(arg).parse().ok());
+        let coin_type = (module.name == "aptos_account" && function.name == "transfer")
+            .then(|| APT_COIN_TYPE.to_string());
+
+        Some(Self {
+            tx_index: txn.version as i64 * 100_000,
+            tx_id: txn_id.to_string(),
+            sender: standardize_address(&request.sender),
+            receiver: standardize_address(&receiver),
+            coin_type,
+            amount,
+            block_time,
+        })
+    }
+
+    /// Returns the senders of the last `limit` transfers `receiver` got before `before_tx_index`.
+    pub async fn get_recent_senders(
+        conn: &mut DbPoolConnection<'_>,
+        receiver: &str,
+        before_tx_index: i64,
+        limit: i64,
+    ) -> QueryResult<Vec<String>> {
+        wallet_transfers::table
+            .filter(wallet_transfers::receiver.eq(receiver))
+            .filter(wallet_transfers::tx_index.lt(before_tx_index))
+            .order(wallet_transfers::tx_index.desc())
+            .select(wallet_transfers::sender)
+            .limit(limit)
+            .load::<String>(conn)
+            .await
+    }
+}
+
+/// Entry function arguments are JSON encoded, e.g. `"\"0x1\""`.
+fn parse_argument(arg: &str) -> String {
+    serde_json::from_str::<String>(arg).unwrap_or_else(|_| arg.trim_matches('"').to_string())
+}
+
+pub fn insert_wallet_transfers(
+    items_to_insert: Vec<WalletTransfer>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::wallet_transfers::dsl::*;
+
+    diesel::insert_into(wallet_transfers)
+        .values(items_to_insert)
+        .on_conflict((tx_index, tx_id))
+        .do_nothing()
+}
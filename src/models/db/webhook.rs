@@ -0,0 +1,429 @@
+use crate::{
+    config::webhook_config::WebhookConfig,
+    models::db::action::Action,
+    schema::{webhook_deliveries, webhooks},
+};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use chrono::NaiveDateTime;
+use diesel::{pg::upsert::excluded, prelude::*};
+use diesel_async::RunQueryDsl;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The delivery is waiting for its next attempt
+pub const DELIVERY_STATUS_PENDING: &str = "pending";
+/// The endpoint acknowledged the delivery
+pub const DELIVERY_STATUS_DELIVERED: &str = "delivered";
+/// Every attempt failed, the delivery is no longer retried
+pub const DELIVERY_STATUS_DEAD: &str = "dead";
+
+/// Maximum number of deliveries inserted per statement
+const DELIVERY_INSERT_CHUNK_SIZE: usize = 1000;
+
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = webhooks)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub collection_ids: Option<Vec<String>>,
+    pub marketplaces: Option<Vec<String>>,
+    pub event_types: Option<Vec<String>>,
+    pub active: bool,
+    pub delivered_count: i64,
+    pub failed_attempt_count: i64,
+    pub dead_count: i64,
+    pub last_delivered_at: Option<NaiveDateTime>,
+    pub last_failed_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// Written from the config on startup, as opposed to registered directly in the table
+    pub from_config: bool,
+}
+
+impl Webhook {
+    pub fn from_config(config: &WebhookConfig, created_at: NaiveDateTime) -> Self {
+        Self {
+            id: config.id.clone(),
+            url: config.url.clone(),
+            secret: config.secret.clone(),
+            collection_ids: config.collection_ids.clone(),
+            marketplaces: config.marketplaces.clone(),
+            event_types: config.event_types.as_ref().map(|event_types| {
+                event_types
+                    .iter()
+                    .map(|event_type| event_type.to_string())
+                    .collect()
+            }),
+            active: config.active,
+            created_at,
+            from_config: true,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the action passes every filter of the webhook.
+    pub fn matches(&self, action: &Action) -> bool {
+        let matches_any = |values: &Option<Vec<String>>, value: &Option<String>| {
+            values.as_ref().map_or(true, |values| {
+                value.as_ref().map_or(false, |value| values.contains(value))
+            })
+        };
+
+        self.active
+            && matches_any(&self.collection_ids, &action.collection_id)
+            && matches_any(&self.marketplaces, &action.market_name)
+            && matches_any(&self.event_types, &action.tx_type)
+    }
+
+    pub async fn get_active(conn: &mut DbPoolConnection<'_>) -> QueryResult<Vec<Self>> {
+        webhooks::table
+            .filter(webhooks::active.eq(true))
+            .select(Self::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Writes the webhooks declared in the config, keeping their delivery metrics.
+    pub async fn upsert_all(conn: &mut DbPoolConnection<'_>, items: &[Self]) -> QueryResult<usize> {
+        if items.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::insert_into(webhooks::table)
+            .values(items)
+            .on_conflict(webhooks::id)
+            .do_update()
+            .set((
+                webhooks::url.eq(excluded(webhooks::url)),
+                webhooks::secret.eq(excluded(webhooks::secret)),
+                webhooks::collection_ids.eq(excluded(webhooks::collection_ids)),
+                webhooks::marketplaces.eq(excluded(webhooks::marketplaces)),
+                webhooks::event_types.eq(excluded(webhooks::event_types)),
+                webhooks::active.eq(excluded(webhooks::active)),
+                webhooks::from_config.eq(excluded(webhooks::from_config)),
+            ))
+            .execute(conn)
+            .await
+    }
+
+    /// Writes the webhooks of the config and deactivates the ones removed from it. Returns the
+    /// number of deactivated webhooks.
+    pub async fn sync_config(
+        conn: &mut DbPoolConnection<'_>,
+        items: &[Self],
+    ) -> QueryResult<usize> {
+        Self::upsert_all(conn, items).await?;
+        let config_ids = items
+            .iter()
+            .map(|webhook| webhook.id.clone())
+            .collect::<Vec<_>>();
+
+        Self::deactivate_missing(conn, &config_ids).await
+    }
+
+    /// Deactivates the webhooks written from the config that are no longer declared in it,
+    /// keeping their rows and delivery metrics. Their pending deliveries are not sent. Webhooks
+    /// registered directly in the table are left alone.
+    pub async fn deactivate_missing(
+        conn: &mut DbPoolConnection<'_>,
+        config_ids: &[String],
+    ) -> QueryResult<usize> {
+        diesel::update(
+            webhooks::table
+                .filter(webhooks::from_config.eq(true))
+                .filter(webhooks::active.eq(true))
+                .filter(webhooks::id.ne_all(config_ids)),
+        )
+        .set(webhooks::active.eq(false))
+        .execute(conn)
+        .await
+    }
+}
+
+/// An entry of the webhook outbox. There is one delivery per webhook and matching action.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FieldCount,
+    Identifiable,
+    Insertable,
+    Serialize,
+    Queryable,
+    Selectable,
+)]
+#[diesel(primary_key(webhook_id, tx_index, tx_id))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub webhook_id: String,
+    pub tx_index: i64,
+    pub tx_id: String,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+impl WebhookDelivery {
+    pub fn new(webhook: &Webhook, action: &Action, created_at: NaiveDateTime) -> Self {
+        let event_type = action.tx_type.clone().unwrap_or_default();
+
+        Self {
+            webhook_id: webhook.id.clone(),
+            tx_index: action.tx_index,
+            tx_id: action.tx_id.clone(),
+            payload: json!({
+                "webhook_id": webhook.id,
+                "event_type": event_type,
+                "action": action,
+            }),
+            event_type,
+            status: DELIVERY_STATUS_PENDING.to_string(),
+            attempts: 0,
+            next_attempt_at: created_at,
+            created_at,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the pending deliveries due at `now`, oldest first, with their webhook.
+    pub async fn get_due(
+        conn: &mut DbPoolConnection<'_>,
+        now: NaiveDateTime,
+        limit: i64,
+    ) -> QueryResult<Vec<(Self, Webhook)>> {
+        webhook_deliveries::table
+            .inner_join(webhooks::table)
+            .filter(webhook_deliveries::status.eq(DELIVERY_STATUS_PENDING))
+            .filter(webhook_deliveries::next_attempt_at.le(now))
+            .filter(webhooks::active.eq(true))
+            .order(webhook_deliveries::next_attempt_at.asc())
+            .select((Self::as_select(), Webhook::as_select()))
+            .limit(limit)
+            .load(conn)
+            .await
+    }
+
+    pub async fn mark_delivered(
+        conn: &mut DbPoolConnection<'_>,
+        delivery: &Self,
+        status_code: i32,
+        delivered_at: NaiveDateTime,
+    ) -> QueryResult<()> {
+        diesel::update(delivery)
+            .set((
+                webhook_deliveries::status.eq(DELIVERY_STATUS_DELIVERED),
+                webhook_deliveries::attempts.eq(webhook_deliveries::attempts + 1),
+                webhook_deliveries::last_status_code.eq(status_code),
+                webhook_deliveries::delivered_at.eq(delivered_at),
+            ))
+            .execute(conn)
+            .await?;
+
+        diesel::update(webhooks::table.find(&delivery.webhook_id))
+            .set((
+                webhooks::delivered_count.eq(webhooks::delivered_count + 1),
+                webhooks::last_delivered_at.eq(delivered_at),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt. The delivery is retried at `retry_at`, or dead-lettered
+    /// when there is none.
+    pub async fn mark_failed(
+        conn: &mut DbPoolConnection<'_>,
+        delivery: &Self,
+        status_code: Option<i32>,
+        error: &str,
+        failed_at: NaiveDateTime,
+        retry_at: Option<NaiveDateTime>,
+    ) -> QueryResult<()> {
+        let status = if retry_at.is_some() {
+            DELIVERY_STATUS_PENDING
+        } else {
+            DELIVERY_STATUS_DEAD
+        };
+
+        diesel::update(delivery)
+            .set((
+                webhook_deliveries::status.eq(status),
+                webhook_deliveries::attempts.eq(webhook_deliveries::attempts + 1),
+                webhook_deliveries::next_attempt_at.eq(retry_at.unwrap_or(failed_at)),
+                webhook_deliveries::last_status_code.eq(status_code),
+                webhook_deliveries::last_error.eq(error),
+            ))
+            .execute(conn)
+            .await?;
+
+        let dead = i64::from(retry_at.is_none());
+        diesel::update(webhooks::table.find(&delivery.webhook_id))
+            .set((
+                webhooks::failed_attempt_count.eq(webhooks::failed_attempt_count + 1),
+                webhooks::dead_count.eq(webhooks::dead_count + dead),
+                webhooks::last_failed_at.eq(failed_at),
+                webhooks::last_error.eq(error),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Adds a delivery to the outbox for every active webhook matching each of the stored
+/// actions. Deliveries already in the outbox are kept as is, so reprocessing a batch is safe.
+pub async fn enqueue_webhook_deliveries(
+    conn: &mut DbPoolConnection<'_>,
+    actions: &[Action],
+    created_at: NaiveDateTime,
+) -> QueryResult<usize> {
+    if actions.is_empty() {
+        return Ok(0);
+    }
+
+    let webhooks = Webhook::get_active(conn).await?;
+    let deliveries = webhooks
+        .iter()
+        .flat_map(|webhook| {
+            actions
+                .iter()
+                .filter(|action| webhook.matches(action))
+                .map(|action| WebhookDelivery::new(webhook, action, created_at))
+        })
+        .collect::<Vec<_>>();
+
+    let mut inserted = 0;
+    for chunk in deliveries.chunks(DELIVERY_INSERT_CHUNK_SIZE) {
+        inserted += diesel::insert_into(webhook_deliveries::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .await?;
+    }
+
+    Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MIGRATIONS;
+    use aptos_indexer_processor_sdk::postgres::utils::database::{new_db_pool, run_migrations};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_webhook_matches() {
+        let webhook = Webhook {
+            id: "sales".to_string(),
+            marketplaces: Some(vec!["tradeport".to_string()]),
+            event_types: Some(vec!["buy".to_string()]),
+            active: true,
+            ..Default::default()
+        };
+        let action = Action {
+            tx_type: Some("buy".to_string()),
+            market_name: Some("tradeport".to_string()),
+            collection_id: Some("0xc".to_string()),
+            ..Default::default()
+        };
+        assert!(webhook.matches(&action));
+
+        let listing = Action {
+            tx_type: Some("list".to_string()),
+            ..action.clone()
+        };
+        assert!(!webhook.matches(&listing));
+
+        let other_collection = Webhook {
+            collection_ids: Some(vec!["0xd".to_string()]),
+            ..webhook.clone()
+        };
+        assert!(!other_collection.matches(&action));
+
+        let inactive = Webhook {
+            active: false,
+            ..webhook
+        };
+        assert!(!inactive.matches(&action));
+    }
+
+    /// Runs against the database of `DATABASE_URL`, e.g.
+    /// `DATABASE_URL=postgresql://localhost:5432/nft cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_sync_config_keeps_table_webhooks() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        let db_pool = new_db_pool(&url, Some(2)).await.unwrap();
+        run_migrations(url, db_pool.clone(), MIGRATIONS).await;
+        let mut conn = db_pool.get().await.unwrap();
+
+        let created_at = NaiveDateTime::from_str("2025-08-16T00:00:00").unwrap();
+        let registered = Webhook {
+            id: "7e57-registered".to_string(),
+            url: "https://example.com/registered".to_string(),
+            active: true,
+            created_at,
+            ..Default::default()
+        };
+        diesel::insert_into(webhooks::table)
+            .values(&registered)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let removed = Webhook {
+            id: "7e57-removed".to_string(),
+            url: "https://example.com/removed".to_string(),
+            active: true,
+            created_at,
+            from_config: true,
+            ..Default::default()
+        };
+        Webhook::sync_config(&mut conn, std::slice::from_ref(&removed))
+            .await
+            .unwrap();
+
+        // The next startup no longer declares `removed`
+        Webhook::sync_config(&mut conn, &[]).await.unwrap();
+
+        let active_ids = Webhook::get_active(&mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|webhook| webhook.id)
+            .collect::<Vec<_>>();
+        assert!(active_ids.contains(&registered.id));
+        assert!(!active_ids.contains(&removed.id));
+
+        diesel::delete(webhooks::table.filter(webhooks::id.eq_any([&registered.id, &removed.id])))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+}
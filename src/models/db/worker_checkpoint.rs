@@ -0,0 +1,42 @@
+use crate::schema::worker_checkpoints;
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use chrono::Utc;
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+
+/// Last action processed by a background worker that scans the actions in order.
+pub struct WorkerCheckpoint;
+
+impl WorkerCheckpoint {
+    /// Returns the last tx_index processed by `worker`, 0 if it never ran.
+    pub async fn get(conn: &mut DbPoolConnection<'_>, worker: &str) -> QueryResult<i64> {
+        worker_checkpoints::table
+            .filter(worker_checkpoints::worker.eq(worker))
+            .select(worker_checkpoints::last_tx_index)
+            .first::<i64>(conn)
+            .await
+            .optional()
+            .map(|last_tx_index| last_tx_index.unwrap_or(0))
+    }
+
+    pub async fn save(
+        conn: &mut DbPoolConnection<'_>,
+        worker: &str,
+        last_tx_index: i64,
+    ) -> QueryResult<usize> {
+        diesel::insert_into(worker_checkpoints::table)
+            .values((
+                worker_checkpoints::worker.eq(worker),
+                worker_checkpoints::last_tx_index.eq(last_tx_index),
+                worker_checkpoints::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .on_conflict(worker_checkpoints::worker)
+            .do_update()
+            .set((
+                worker_checkpoints::last_tx_index.eq(excluded(worker_checkpoints::last_tx_index)),
+                worker_checkpoints::updated_at.eq(excluded(worker_checkpoints::updated_at)),
+            ))
+            .execute(conn)
+            .await
+    }
+}
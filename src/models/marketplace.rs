@@ -1,11 +1,20 @@
 use crate::{
     config::marketplace_config::MarketplaceEventType,
-    models::db::{action::Action, bid::Bid, listing::Listing},
+    models::{
+        db::{action::Action, bid::Bid, listing::Listing, payment_token::normalize_payment_token},
+        order_status::{BidStatus, ListingStatus},
+    },
+    utils::parse_amount,
 };
-use aptos_indexer_processor_sdk::aptos_indexer_transaction_stream::utils::time::parse_timestamp_secs;
+use aptos_indexer_processor_sdk::{
+    aptos_indexer_transaction_stream::utils::time::parse_timestamp_secs,
+    utils::{convert::standardize_address, extract::hash_str},
+};
+use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use strum::{Display, EnumString};
+use std::{collections::HashMap, str::FromStr};
+use strum::{Display, EnumIter, EnumString};
 
 pub const DEFAULT_SELLER: &str = "unknown";
 pub const DEFAULT_BUYER: &str = "unknown";
@@ -32,7 +41,8 @@ pub struct NftMarketplaceActivity {
     pub collection_name: Option<String>,
     pub token_addr: Option<String>,
     pub token_name: Option<String>,
-    pub price: i64,
+    /// Unset when the event doesn't carry it or it couldn't be parsed
+    pub price: Option<BigDecimal>,
     pub token_amount: Option<i64>,
     pub buyer: Option<String>,
     pub seller: Option<String>,
@@ -45,6 +55,25 @@ pub struct NftMarketplaceActivity {
     pub block_height: i64,
     pub expiration_time: Option<NaiveDateTime>,
     pub bid_key: Option<i64>,
+    /// Correlates the events of a collection offer that has no on-chain id
+    pub offer_match_key: Option<String>,
+    /// Coins deposited to each wallet by the transaction, only set on a sale that is alone in
+    /// its transaction so the deposits can be attributed to it
+    #[serde(default)]
+    pub payments: HashMap<String, BigDecimal>,
+    /// Coin type or fungible asset metadata address the price is paid in
+    pub payment_token: Option<String>,
+    /// Values of the event that couldn't be parsed into their field
+    #[serde(default)]
+    pub field_errors: Vec<FieldError>,
+}
+
+/// A value that couldn't be parsed into a field of an activity, or wasn't found in its event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub value: String,
+    pub error: String,
 }
 
 impl From<NftMarketplaceActivity> for Action {
@@ -58,18 +87,22 @@ impl From<NftMarketplaceActivity> for Action {
             collection_id: value.collection_addr,
             sender: value.seller,
             receiver: value.buyer,
-            price: Some(value.price),
+            price: value.price,
             block_time: Some(value.block_timestamp),
             market_name: value.marketplace,
             block_height: Some(value.block_height),
-            // TODO: handle usd price
+            // Filled from the prices table by the DBWritingStep
             usd_price: None,
+            payment_token: value.payment_token,
         }
     }
 }
 
 impl From<NftMarketplaceActivity> for Bid {
     fn from(value: NftMarketplaceActivity) -> Self {
+        // Only the creation carries the offered amount, fills are applied by the reduction
+        let remaining_count = value.get_created_txn_id().and(value.token_amount);
+
         Self {
             created_tx_id: value.get_created_txn_id(),
             accepted_tx_id: value.get_accepted_txn_id(),
@@ -80,32 +113,44 @@ impl From<NftMarketplaceActivity> for Bid {
             market_name: value.marketplace,
             collection_id: value.collection_addr,
             nft_id: value.token_addr,
-            price: Some(value.price),
-            price_str: Some(value.price.to_string()),
+            price_str: value.price.as_ref().map(|price| price.to_string()),
+            price: value.price,
             expires_at: value.expiration_time,
             nonce: value.offer_id,
             bidder: value.buyer,
-            remaining_count: value.token_amount,
+            remaining_count,
             receiver: value.seller,
+            match_key: value.offer_match_key,
+            payment_token: value.payment_token,
         }
     }
 }
 
 impl From<NftMarketplaceActivity> for Listing {
     fn from(value: NftMarketplaceActivity) -> Self {
+        // Only the listing carries the listed amount, fills are applied by the reduction
+        let status = value.get_listing_status();
+        let remaining_count = status
+            .filter(|status| *status == ListingStatus::Listed)
+            .and(value.token_amount);
+
         Self {
             tx_index: Some(value.get_tx_index()),
-            listed: value.get_listing_status(),
+            listed: status.map(|status| status == ListingStatus::Listed),
             market_contract_id: value.contract_address,
             collection_id: value.collection_addr,
             nft_id: value.token_addr,
             market_name: value.marketplace,
             seller: value.seller,
-            price: Some(value.price),
-            price_str: Some(value.price.to_string()),
+            price_str: value.price.as_ref().map(|price| price.to_string()),
+            price: value.price,
             block_time: Some(value.block_timestamp),
             nonce: value.listing_id,
             block_height: Some(value.block_height),
+            remaining_count,
+            status,
+            expires_at: value.expiration_time,
+            payment_token: value.payment_token,
         }
     }
 }
@@ -114,6 +159,79 @@ impl NftMarketplaceActivity {
     pub fn get_tx_index(&self) -> i64 {
         self.txn_version * 100_000 + self.index
     }
+
+    pub fn is_sale(&self) -> bool {
+        matches!(
+            self.standard_event_type,
+            MarketplaceEventType::Buy
+                | MarketplaceEventType::AcceptBid
+                | MarketplaceEventType::AcceptCollectionBid
+        )
+    }
+
+    /// Number of tokens filled by an accept or buy event, 1 if the event doesn't carry it.
+    pub fn get_filled_amount(&self) -> i64 {
+        self.token_amount.filter(|amount| *amount > 0).unwrap_or(1)
+    }
+
+    /// Id the collection offers of this buyer and collection creator were stored with before
+    /// offers were identified by their creation event. Such offers have no match key.
+    pub fn get_legacy_collection_offer_id(&self) -> Option<String> {
+        let creator = self.creator_address.as_deref().filter(|s| !s.is_empty())?;
+        let buyer = self.buyer.as_deref().filter(|s| !s.is_empty())?;
+        let input = format!(
+            "{}::{}",
+            standardize_address(creator),
+            standardize_address(buyer)
+        );
+        Some(standardize_address(&hash_str(&input)))
+    }
+
+    /// Merges the values extracted from write set resources into this activity.
+    ///
+    /// Resources are keyed by their address, which is matched against the listing id, offer id,
+    /// token address and collection address of the activity. Only fields the event did not
+    /// provide are filled, so event data always takes precedence.
+    pub fn merge_resource_fields(
+        &mut self,
+        resource_updates: &HashMap<String, HashMap<String, String>>,
+    ) {
+        let resource_addresses = [
+            self.listing_id.clone(),
+            self.offer_id.clone(),
+            self.token_addr.clone(),
+            self.collection_addr.clone(),
+        ];
+
+        for resource_address in resource_addresses.into_iter().flatten() {
+            let Some(fields) = resource_updates.get(&standardize_address(&resource_address)) else {
+                continue;
+            };
+
+            for (column, value) in fields {
+                match MarketplaceField::from_str(column) {
+                    Ok(field) => {
+                        if self.is_field_missing(&field) {
+                            self.set_field(field, value.clone());
+                        }
+                    },
+                    Err(e) => {
+                        tracing::warn!("Skipping invalid resource field {}: {}", column, e);
+                    },
+                }
+            }
+        }
+    }
+
+    fn is_field_missing(&self, field: &MarketplaceField) -> bool {
+        match field {
+            MarketplaceField::Price => self.price.is_none(),
+            MarketplaceField::OfferId | MarketplaceField::CollectionOfferId => {
+                self.offer_id.is_none()
+            },
+            _ => self.get_field(field.clone()).is_none(),
+        }
+    }
 }
 
 impl MarketplaceModel for NftMarketplaceActivity {
@@ -129,7 +247,14 @@ impl MarketplaceModel for NftMarketplaceActivity {
             MarketplaceField::TokenName => self.token_name = Some(value),
             MarketplaceField::CreatorAddress => self.creator_address = Some(value),
             MarketplaceField::CollectionName => self.collection_name = Some(value),
-            MarketplaceField::Price => self.price = value.parse().unwrap_or(0),
+            MarketplaceField::Price => match parse_amount(&value) {
+                Ok(price) => self.price = Some(price),
+                Err(e) => self.field_errors.push(FieldError {
+                    field: MarketplaceField::Price.to_string(),
+                    value,
+                    error: format!("{e:#}"),
+                }),
+            },
             MarketplaceField::TokenAmount => self.token_amount = value.parse().ok(),
             MarketplaceField::Buyer => self.buyer = Some(value),
             MarketplaceField::Seller => self.seller = Some(value),
@@ -152,6 +277,9 @@ impl MarketplaceModel for NftMarketplaceActivity {
                 self.block_timestamp = value.parse().unwrap_or(NaiveDateTime::default())
             },
             MarketplaceField::BidKey => self.bid_key = value.parse().ok(),
+            MarketplaceField::PaymentToken => {
+                self.payment_token = Some(normalize_payment_token(&value))
+            },
             _ => tracing::debug!("Unknown field: {:?}", field),
         }
     }
@@ -179,7 +307,7 @@ impl MarketplaceModel for NftMarketplaceActivity {
             MarketplaceField::TokenName => self.token_name.clone(),
             MarketplaceField::CreatorAddress => self.creator_address.clone(),
             MarketplaceField::CollectionName => self.collection_name.clone(),
-            MarketplaceField::Price => Some(self.price.to_string()),
+            MarketplaceField::Price => self.price.as_ref().map(|price| price.to_string()),
             MarketplaceField::TokenAmount => self.token_amount.map(|amount| amount.to_string()),
             MarketplaceField::Buyer => self.buyer.clone(),
             MarketplaceField::Seller => self.seller.clone(),
@@ -192,6 +320,7 @@ impl MarketplaceModel for NftMarketplaceActivity {
             MarketplaceField::ContractAddress => self.contract_address.clone(),
             MarketplaceField::BlockTimestamp => Some(self.block_timestamp.to_string()),
             MarketplaceField::BidKey => self.bid_key.map(|val| val.to_string()),
+            MarketplaceField::PaymentToken => self.payment_token.clone(),
             _ => None,
         }
     }
@@ -210,19 +339,8 @@ impl BidModel for NftMarketplaceActivity {
         self.contract_address.is_some() && self.offer_id.is_some()
     }
 
-    fn get_bid_status(&self) -> Option<String> {
-        match self.standard_event_type {
-            MarketplaceEventType::SoloBid | MarketplaceEventType::CollectionBid => {
-                Some("active".to_string())
-            },
-            MarketplaceEventType::AcceptBid | MarketplaceEventType::AcceptCollectionBid => {
-                Some("matched".to_string())
-            },
-            MarketplaceEventType::UnlistBid | MarketplaceEventType::CancelCollectionBid => {
-                Some("cancelled".to_string())
-            },
-            _ => None,
-        }
+    fn get_bid_status(&self) -> Option<BidStatus> {
+        BidStatus::from_event_type(&self.standard_event_type)
     }
 
     fn get_bid_type(&self) -> Option<String> {
@@ -272,17 +390,12 @@ impl ListingModel for NftMarketplaceActivity {
             && self.get_listing_status().is_some()
     }
 
-    fn get_listing_status(&self) -> Option<bool> {
-        match self.standard_event_type {
-            MarketplaceEventType::List => Some(true),
-            MarketplaceEventType::Unlist => Some(false),
-            MarketplaceEventType::Buy => Some(false),
-            _ => None,
-        }
+    fn get_listing_status(&self) -> Option<ListingStatus> {
+        ListingStatus::from_event_type(&self.standard_event_type)
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Display, EnumString)]
+#[derive(Debug, Clone, PartialEq, Display, EnumIter, EnumString)]
 #[strum(serialize_all = "snake_case")]
 pub enum MarketplaceField {
     CollectionAddr,
@@ -305,6 +418,7 @@ pub enum MarketplaceField {
     RemainingTokenAmount,
     BlockTimestamp,
     BidKey,
+    PaymentToken,
 }
 
 pub trait MarketplaceModel {
@@ -318,7 +432,7 @@ pub trait MarketplaceModel {
 }
 
 pub trait BidModel {
-    fn get_bid_status(&self) -> Option<String>;
+    fn get_bid_status(&self) -> Option<BidStatus>;
     fn get_bid_type(&self) -> Option<String>;
     fn get_created_txn_id(&self) -> Option<String>;
     fn get_cancelled_txn_id(&self) -> Option<String>;
@@ -327,14 +441,13 @@ pub trait BidModel {
 }
 
 pub trait ListingModel {
-    fn get_listing_status(&self) -> Option<bool>;
+    fn get_listing_status(&self) -> Option<ListingStatus>;
     fn is_valid_listing(&self) -> bool;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::str::FromStr;
     use strum::ParseError;
 
     #[test]
@@ -369,4 +482,69 @@ mod tests {
             assert_eq!(result, expected);
         }
     }
+
+    #[test]
+    fn test_merge_resource_fields() {
+        let mut activity = NftMarketplaceActivity {
+            listing_id: Some("0x1234".to_string()),
+            seller: Some("0xseller".to_string()),
+            ..Default::default()
+        };
+
+        let mut fields = HashMap::new();
+        fields.insert("token_addr".to_string(), "0xtoken".to_string());
+        fields.insert("price".to_string(), "100".to_string());
+        fields.insert("seller".to_string(), "0xother".to_string());
+
+        let mut resource_updates = HashMap::new();
+        resource_updates.insert(standardize_address("0x1234"), fields);
+
+        activity.merge_resource_fields(&resource_updates);
+
+        assert_eq!(activity.token_addr, Some("0xtoken".to_string()));
+        assert_eq!(activity.price, Some(BigDecimal::from(100)));
+        // Values coming from the event are never overwritten
+        assert_eq!(activity.seller, Some("0xseller".to_string()));
+    }
+
+    #[test]
+    fn test_get_legacy_collection_offer_id() {
+        let activity = NftMarketplaceActivity {
+            creator_address: Some("0x1".to_string()),
+            buyer: Some("0x02".to_string()),
+            ..Default::default()
+        };
+        let legacy_id = standardize_address(&hash_str(&format!(
+            "{}::{}",
+            standardize_address("0x1"),
+            standardize_address("0x2")
+        )));
+        assert_eq!(activity.get_legacy_collection_offer_id(), Some(legacy_id));
+
+        let without_buyer = NftMarketplaceActivity {
+            buyer: Some(String::new()),
+            ..activity
+        };
+        assert_eq!(without_buyer.get_legacy_collection_offer_id(), None);
+    }
+
+    #[test]
+    fn test_set_price() {
+        let mut activity = NftMarketplaceActivity::default();
+
+        // Above i64::MAX
+        activity.set_field(MarketplaceField::Price, "18446744073709551615".to_string());
+        assert_eq!(
+            activity.price,
+            Some(BigDecimal::from_str("18446744073709551615").unwrap())
+        );
+        assert!(activity.field_errors.is_empty());
+
+        let mut activity = NftMarketplaceActivity::default();
+        activity.set_field(MarketplaceField::Price, "-1".to_string());
+        assert_eq!(activity.price, None);
+        assert_eq!(activity.field_errors.len(), 1);
+        assert_eq!(activity.field_errors[0].field, "price");
+        assert_eq!(activity.field_errors[0].value, "-1");
+    }
 }
@@ -1,6 +1,8 @@
+pub mod change_event;
 pub mod db;
 pub mod marketplace;
 pub mod nft_metadata;
+pub mod order_status;
 pub mod resources;
 
 use crate::config::marketplace_config::EventType;
@@ -0,0 +1,327 @@
+use crate::config::marketplace_config::MarketplaceEventType;
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use strum::{Display, EnumString};
+
+/// Status of a bid, stored in `bids.status`.
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    EnumString,
+    Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum BidStatus {
+    Active,
+    Matched,
+    Cancelled,
+    /// Set by the expiry worker once the chain time passes `expires_at`
+    Expired,
+}
+
+/// Status of a listing, stored in `listings.status`. `listings.listed` is true while `Listed`.
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    EnumString,
+    Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum ListingStatus {
+    Listed,
+    Filled,
+    Cancelled,
+    /// Set by the expiry worker once the chain time passes `expires_at`
+    Expired,
+    /// The seller no longer holds the nft, it was transferred, burned or sold elsewhere
+    Invalid,
+}
+
+/// Why an event can't be applied to the current status of an order. Recorded in `anomalies`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IllegalTransition {
+    pub reason: String,
+}
+
+impl IllegalTransition {
+    fn new(from: Option<impl std::fmt::Display>, event_type: &MarketplaceEventType) -> Self {
+        let from = from.map_or("unknown".to_string(), |status| status.to_string());
+        Self {
+            reason: format!("{event_type} is not allowed on a {from} order"),
+        }
+    }
+}
+
+impl BidStatus {
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, Self::Active)
+    }
+
+    /// Status a bid event leads to, regardless of the current status.
+    pub fn from_event_type(event_type: &MarketplaceEventType) -> Option<Self> {
+        match event_type {
+            MarketplaceEventType::SoloBid | MarketplaceEventType::CollectionBid => {
+                Some(Self::Active)
+            },
+            MarketplaceEventType::AcceptBid | MarketplaceEventType::AcceptCollectionBid => {
+                Some(Self::Matched)
+            },
+            MarketplaceEventType::UnlistBid | MarketplaceEventType::CancelCollectionBid => {
+                Some(Self::Cancelled)
+            },
+            _ => None,
+        }
+    }
+
+    /// Transition table of bids. `from` is `None` when the bid was created before the indexed
+    /// range, and `remaining` is the amount left after a fill, if known. Expired bids can
+    /// still be cancelled to withdraw the escrowed coins.
+    ///
+    /// | from      | place    | accept                         | cancel    |
+    /// |-----------|----------|--------------------------------|-----------|
+    /// | unknown   | active   | matched, active if tokens left | cancelled |
+    /// | active    | illegal  | matched, active if tokens left | cancelled |
+    /// | matched   | illegal  | illegal                        | illegal   |
+    /// | cancelled | illegal  | illegal                        | illegal   |
+    /// | expired   | illegal  | illegal                        | cancelled |
+    pub fn transition(
+        from: Option<Self>,
+        event_type: &MarketplaceEventType,
+        remaining: Option<i64>,
+    ) -> Result<Self, IllegalTransition> {
+        let to = Self::from_event_type(event_type)
+            .ok_or_else(|| IllegalTransition::new(from, event_type))?;
+
+        match (from, to) {
+            (Some(Self::Expired), Self::Cancelled) => Ok(Self::Cancelled),
+            (Some(from), _) if from.is_terminal() => {
+                Err(IllegalTransition::new(Some(from), event_type))
+            },
+            (Some(Self::Active), Self::Active) => Err(IllegalTransition::new(from, event_type)),
+            (_, Self::Matched) if remaining.map_or(false, |remaining| remaining > 0) => {
+                Ok(Self::Active)
+            },
+            (_, to) => Ok(to),
+        }
+    }
+}
+
+impl ListingStatus {
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, Self::Listed)
+    }
+
+    /// Status a listing event leads to, regardless of the current status.
+    pub fn from_event_type(event_type: &MarketplaceEventType) -> Option<Self> {
+        match event_type {
+            MarketplaceEventType::List => Some(Self::Listed),
+            MarketplaceEventType::Buy => Some(Self::Filled),
+            MarketplaceEventType::Unlist => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// Transition table of listings. Listings are keyed by nft, so listing again after a
+    /// fill, cancel or expiry, or to change the price, is allowed. Listings are invalidated
+    /// outside of the marketplace pipeline, which can still see their events afterwards, so
+    /// `invalid` is treated like `unknown`.
+    ///
+    /// | from      | list   | buy                           | unlist    |
+    /// |-----------|--------|-------------------------------|-----------|
+    /// | unknown   | listed | filled, listed if tokens left | cancelled |
+    /// | listed    | listed | filled, listed if tokens left | cancelled |
+    /// | filled    | listed | illegal                       | illegal   |
+    /// | cancelled | listed | illegal                       | illegal   |
+    /// | expired   | listed | illegal                       | cancelled |
+    /// | invalid   | listed | filled, listed if tokens left | cancelled |
+    pub fn transition(
+        from: Option<Self>,
+        event_type: &MarketplaceEventType,
+        remaining: Option<i64>,
+    ) -> Result<Self, IllegalTransition> {
+        let to = Self::from_event_type(event_type)
+            .ok_or_else(|| IllegalTransition::new(from, event_type))?;
+
+        match (from, to) {
+            (_, Self::Listed) => Ok(Self::Listed),
+            (Some(Self::Expired), Self::Cancelled) => Ok(Self::Cancelled),
+            (Some(from), _) if from.is_terminal() && from != Self::Invalid => {
+                Err(IllegalTransition::new(Some(from), event_type))
+            },
+            (_, Self::Filled) if remaining.map_or(false, |remaining| remaining > 0) => {
+                Ok(Self::Listed)
+            },
+            (_, to) => Ok(to),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for BidStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for BidStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let s = std::str::from_utf8(bytes.as_bytes())?;
+        s.parse::<BidStatus>()
+            .map_err(|_| "Unrecognized BidStatus".into())
+    }
+}
+
+impl ToSql<Text, Pg> for ListingStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for ListingStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let s = std::str::from_utf8(bytes.as_bytes())?;
+        s.parse::<ListingStatus>()
+            .map_err(|_| "Unrecognized ListingStatus".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bid_transitions() {
+        use BidStatus::*;
+        use MarketplaceEventType::*;
+
+        assert_eq!(BidStatus::transition(None, &SoloBid, None), Ok(Active));
+        assert_eq!(BidStatus::transition(None, &AcceptBid, None), Ok(Matched));
+        assert_eq!(
+            BidStatus::transition(Some(Active), &AcceptCollectionBid, Some(2)),
+            Ok(Active)
+        );
+        assert_eq!(
+            BidStatus::transition(Some(Active), &AcceptCollectionBid, Some(0)),
+            Ok(Matched)
+        );
+        assert_eq!(
+            BidStatus::transition(Some(Active), &CancelCollectionBid, None),
+            Ok(Cancelled)
+        );
+
+        assert!(BidStatus::transition(Some(Active), &SoloBid, None).is_err());
+        assert!(BidStatus::transition(Some(Matched), &AcceptBid, None).is_err());
+        assert!(BidStatus::transition(Some(Cancelled), &UnlistBid, None).is_err());
+        assert!(BidStatus::transition(Some(Active), &List, None).is_err());
+    }
+
+    #[test]
+    fn test_expired_transitions() {
+        use MarketplaceEventType::*;
+
+        let expired = Some(BidStatus::Expired);
+        assert_eq!(
+            BidStatus::transition(expired, &UnlistBid, None),
+            Ok(BidStatus::Cancelled)
+        );
+        assert!(BidStatus::transition(expired, &AcceptBid, None).is_err());
+        assert!(BidStatus::transition(expired, &SoloBid, None).is_err());
+
+        let expired = Some(ListingStatus::Expired);
+        assert_eq!(
+            ListingStatus::transition(expired, &List, None),
+            Ok(ListingStatus::Listed)
+        );
+        assert_eq!(
+            ListingStatus::transition(expired, &Unlist, None),
+            Ok(ListingStatus::Cancelled)
+        );
+        assert!(ListingStatus::transition(expired, &Buy, None).is_err());
+    }
+
+    #[test]
+    fn test_invalid_listing_transitions() {
+        use ListingStatus::*;
+        use MarketplaceEventType::*;
+
+        // A listing invalidated by a transfer can still be bought if the token pipeline ran
+        // ahead of the marketplace pipeline
+        assert_eq!(
+            ListingStatus::transition(Some(Invalid), &Buy, None),
+            Ok(Filled)
+        );
+        assert_eq!(
+            ListingStatus::transition(Some(Invalid), &Unlist, None),
+            Ok(Cancelled)
+        );
+        assert_eq!(
+            ListingStatus::transition(Some(Invalid), &List, None),
+            Ok(Listed)
+        );
+    }
+
+    #[test]
+    fn test_listing_transitions() {
+        use ListingStatus::*;
+        use MarketplaceEventType::*;
+
+        assert_eq!(ListingStatus::transition(None, &Buy, None), Ok(Filled));
+        assert_eq!(
+            ListingStatus::transition(Some(Listed), &List, None),
+            Ok(Listed)
+        );
+        assert_eq!(
+            ListingStatus::transition(Some(Listed), &Buy, Some(1)),
+            Ok(Listed)
+        );
+        assert_eq!(
+            ListingStatus::transition(Some(Filled), &List, None),
+            Ok(Listed)
+        );
+        assert_eq!(
+            ListingStatus::transition(Some(Listed), &Unlist, None),
+            Ok(Cancelled)
+        );
+
+        let err = ListingStatus::transition(Some(Filled), &Buy, None).unwrap_err();
+        assert_eq!(err.reason, "buy is not allowed on a filled order");
+        assert!(ListingStatus::transition(Some(Cancelled), &Unlist, None).is_err());
+    }
+
+    #[test]
+    fn test_status_strings() {
+        assert_eq!(BidStatus::Cancelled.to_string(), "cancelled");
+        assert_eq!("matched".parse::<BidStatus>(), Ok(BidStatus::Matched));
+        assert_eq!(ListingStatus::Filled.to_string(), "filled");
+    }
+}
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS actions_usd_price_backfill_idx;
+UPDATE actions SET usd_price = 0 WHERE usd_price IS NULL;
+ALTER TABLE actions ALTER COLUMN usd_price SET DEFAULT 0;
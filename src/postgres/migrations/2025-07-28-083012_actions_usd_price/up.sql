@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- usd_price is NULL until the price at block_time is known, so it can be backfilled later
+ALTER TABLE actions ALTER COLUMN usd_price SET DEFAULT NULL;
+UPDATE actions SET usd_price = NULL WHERE usd_price = 0;
+CREATE INDEX IF NOT EXISTS actions_usd_price_backfill_idx ON actions (block_time)
+WHERE usd_price IS NULL AND tx_type IN ('buy', 'accept_bid', 'accept_collection_bid');
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS nfts_metadata_status_idx;
+ALTER TABLE nfts
+  DROP COLUMN IF EXISTS uri,
+  DROP COLUMN IF EXISTS metadata_status,
+  DROP COLUMN IF EXISTS metadata_attempts,
+  DROP COLUMN IF EXISTS metadata_error,
+  DROP COLUMN IF EXISTS metadata_updated_at;
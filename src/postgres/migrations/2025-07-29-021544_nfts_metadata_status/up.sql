@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Tracks the off-chain metadata fetching of every nft
+ALTER TABLE nfts
+  ADD COLUMN IF NOT EXISTS uri VARCHAR DEFAULT NULL,
+  ADD COLUMN IF NOT EXISTS metadata_status VARCHAR(20) DEFAULT NULL,
+  ADD COLUMN IF NOT EXISTS metadata_attempts INT DEFAULT 0,
+  ADD COLUMN IF NOT EXISTS metadata_error TEXT DEFAULT NULL,
+  ADD COLUMN IF NOT EXISTS metadata_updated_at timestamp(6) WITH time zone DEFAULT NULL;
+
+-- Until now the token uri was only kept in image_url
+UPDATE nfts SET uri = image_url WHERE uri IS NULL AND image_url ILIKE '%.json';
+
+CREATE INDEX IF NOT EXISTS nfts_metadata_status_idx ON nfts (metadata_status, metadata_updated_at);
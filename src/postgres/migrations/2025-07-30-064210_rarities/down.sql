@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS collection_rarities;
+DROP TABLE IF EXISTS nft_rarities;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+-- NUMERIC(10, 12) can't hold scores above 0.01
+ALTER TABLE attributes
+  ALTER COLUMN score TYPE NUMERIC,
+  ALTER COLUMN rarity TYPE NUMERIC;
+
+CREATE TABLE IF NOT EXISTS nft_rarities (
+  nft_id VARCHAR(66) NOT NULL,
+  collection_id VARCHAR(66) NOT NULL,
+  trait_count BIGINT DEFAULT 0,
+  rarity_score NUMERIC DEFAULT NULL,
+  statistical_rarity NUMERIC DEFAULT NULL,
+  rarity_rank BIGINT DEFAULT NULL,
+  statistical_rank BIGINT DEFAULT NULL,
+  PRIMARY KEY (nft_id)
+);
+
+CREATE INDEX IF NOT EXISTS nft_rarities_collection_rank_idx ON nft_rarities (collection_id, rarity_rank);
+
+-- Tracks which collections need their rarity recomputed
+CREATE TABLE IF NOT EXISTS collection_rarities (
+  collection_id VARCHAR(66) NOT NULL,
+  supply BIGINT DEFAULT NULL,
+  marked_at timestamp(6) WITH time zone DEFAULT NOW() NOT NULL,
+  computed_at timestamp(6) WITH time zone DEFAULT NULL,
+  PRIMARY KEY (collection_id)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS nfts_collection_owner_idx;
+DROP INDEX IF EXISTS listings_collection_listed_idx;
+DROP INDEX IF EXISTS actions_collection_sales_idx;
+DROP TABLE IF EXISTS collection_stats;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS collection_stats (
+  collection_id VARCHAR(66) NOT NULL,
+  floor_price BIGINT DEFAULT NULL,
+  listed_count BIGINT DEFAULT 0,
+  owner_count BIGINT DEFAULT 0,
+  supply BIGINT DEFAULT 0,
+  listed_percentage NUMERIC(10, 2) DEFAULT 0,
+  sales_count BIGINT DEFAULT 0,
+  volume NUMERIC DEFAULT 0,
+  volume_usd NUMERIC(30, 2) DEFAULT 0,
+  volume_24h NUMERIC DEFAULT 0,
+  volume_24h_usd NUMERIC(30, 2) DEFAULT 0,
+  volume_7d NUMERIC DEFAULT 0,
+  volume_7d_usd NUMERIC(30, 2) DEFAULT 0,
+  volume_30d NUMERIC DEFAULT 0,
+  volume_30d_usd NUMERIC(30, 2) DEFAULT 0,
+  updated_at timestamp(6) WITH time zone DEFAULT NOW(),
+  PRIMARY KEY (collection_id)
+);
+
+-- Stats are aggregated per collection
+CREATE INDEX IF NOT EXISTS actions_collection_sales_idx ON actions (collection_id, block_time)
+WHERE tx_type IN ('buy', 'accept_bid', 'accept_collection_bid');
+CREATE INDEX IF NOT EXISTS listings_collection_listed_idx ON listings (collection_id, price)
+WHERE listed = true;
+CREATE INDEX IF NOT EXISTS nfts_collection_owner_idx ON nfts (collection_id, owner);
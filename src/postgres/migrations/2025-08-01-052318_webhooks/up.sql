@@ -0,0 +1,40 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS webhooks (
+  id VARCHAR(128) NOT NULL,
+  url TEXT NOT NULL,
+  secret TEXT NOT NULL,
+  -- NULL matches everything
+  collection_ids VARCHAR(66) [] DEFAULT NULL,
+  marketplaces VARCHAR(128) [] DEFAULT NULL,
+  event_types VARCHAR(30) [] DEFAULT NULL,
+  active BOOLEAN NOT NULL DEFAULT TRUE,
+  -- Delivery metrics
+  delivered_count BIGINT NOT NULL DEFAULT 0,
+  failed_attempt_count BIGINT NOT NULL DEFAULT 0,
+  dead_count BIGINT NOT NULL DEFAULT 0,
+  last_delivered_at timestamp(6) WITH time zone DEFAULT NULL,
+  last_failed_at timestamp(6) WITH time zone DEFAULT NULL,
+  last_error TEXT DEFAULT NULL,
+  created_at timestamp(6) WITH time zone NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+  webhook_id VARCHAR(128) NOT NULL,
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  event_type VARCHAR(30) NOT NULL,
+  payload JSONB NOT NULL,
+  status VARCHAR(20) NOT NULL DEFAULT 'pending',
+  attempts INT NOT NULL DEFAULT 0,
+  next_attempt_at timestamp(6) WITH time zone NOT NULL DEFAULT NOW(),
+  last_status_code INT DEFAULT NULL,
+  last_error TEXT DEFAULT NULL,
+  created_at timestamp(6) WITH time zone NOT NULL DEFAULT NOW(),
+  delivered_at timestamp(6) WITH time zone DEFAULT NULL,
+  PRIMARY KEY (webhook_id, tx_index, tx_id),
+  FOREIGN KEY (webhook_id) REFERENCES webhooks (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due_idx ON webhook_deliveries (next_attempt_at)
+WHERE status = 'pending';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS bids_open_collection_offers_idx;
+ALTER TABLE bids DROP COLUMN IF EXISTS match_key;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Correlates cancel and fill events of collection offers that don't carry an offer id
+ALTER TABLE bids ADD COLUMN IF NOT EXISTS match_key VARCHAR(66) DEFAULT NULL;
+CREATE INDEX IF NOT EXISTS bids_open_collection_offers_idx ON bids (market_contract_id, match_key, nonce)
+WHERE match_key IS NOT NULL AND status = 'active';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS listing_fills;
+DROP TABLE IF EXISTS bid_fills;
+ALTER TABLE listings DROP COLUMN IF EXISTS remaining_count;
@@ -0,0 +1,42 @@
+-- Your SQL goes here
+-- Number of tokens still listed, NULL when the listed amount is unknown
+ALTER TABLE listings ADD COLUMN IF NOT EXISTS remaining_count BIGINT DEFAULT NULL;
+
+-- Each fill of a bid, keyed by the action of the fill
+CREATE TABLE IF NOT EXISTS bid_fills (
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  market_contract_id VARCHAR(66) NOT NULL,
+  nonce VARCHAR(128) NOT NULL,
+  bid_type VARCHAR(20),
+  collection_id VARCHAR(66),
+  nft_id VARCHAR(66),
+  bidder VARCHAR(66),
+  seller VARCHAR(66),
+  amount BIGINT NOT NULL,
+  price BIGINT,
+  -- Tokens left on the bid after this fill, NULL when the bid amount is unknown
+  remaining_count BIGINT,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  PRIMARY KEY (tx_index, tx_id)
+);
+CREATE INDEX IF NOT EXISTS bid_fills_bid_idx ON bid_fills (market_contract_id, nonce);
+
+-- Each fill of a listing, keyed by the action of the fill
+CREATE TABLE IF NOT EXISTS listing_fills (
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  market_contract_id VARCHAR(66) NOT NULL,
+  nft_id VARCHAR(66) NOT NULL,
+  collection_id VARCHAR(66),
+  nonce VARCHAR(128),
+  seller VARCHAR(66),
+  buyer VARCHAR(66),
+  amount BIGINT NOT NULL,
+  price BIGINT,
+  -- Tokens left on the listing after this fill, NULL when the listed amount is unknown
+  remaining_count BIGINT,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  PRIMARY KEY (tx_index, tx_id)
+);
+CREATE INDEX IF NOT EXISTS listing_fills_listing_idx ON listing_fills (market_contract_id, nft_id);
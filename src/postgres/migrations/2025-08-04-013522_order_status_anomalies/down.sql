@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS anomalies;
+ALTER TABLE listings DROP COLUMN IF EXISTS status;
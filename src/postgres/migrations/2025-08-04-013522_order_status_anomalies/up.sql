@@ -0,0 +1,24 @@
+-- Your SQL goes here
+-- listed, filled or cancelled. Rows stored before only know if they are listed
+ALTER TABLE listings ADD COLUMN IF NOT EXISTS status VARCHAR(20) DEFAULT NULL;
+UPDATE listings SET status = 'listed' WHERE listed = TRUE;
+
+-- Events that can't be applied to the current status of their bid or listing, e.g. an accept
+-- of a cancelled bid. Keyed by the action of the event
+CREATE TABLE IF NOT EXISTS anomalies (
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  -- bid or listing
+  order_type VARCHAR(20) NOT NULL,
+  market_contract_id VARCHAR(66) NOT NULL,
+  market_name VARCHAR(128),
+  -- Nonce of the bid or nft id of the listing
+  order_id VARCHAR(128),
+  event_type VARCHAR(30) NOT NULL,
+  raw_event_type TEXT NOT NULL,
+  from_status VARCHAR(20),
+  reason TEXT NOT NULL,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  PRIMARY KEY (tx_index, tx_id)
+);
+CREATE INDEX IF NOT EXISTS anomalies_market_idx ON anomalies (market_name, event_type);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS listings_listed_expiry_idx;
+DROP INDEX IF EXISTS bids_active_expiry_idx;
+UPDATE bids SET status = 'active' WHERE status = 'expired';
+UPDATE listings SET status = 'listed', listed = TRUE WHERE status = 'expired';
+ALTER TABLE listings DROP COLUMN IF EXISTS expires_at;
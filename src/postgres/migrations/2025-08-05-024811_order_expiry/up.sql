@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- Listings carry the expiration time of the list event when the marketplace maps one
+ALTER TABLE listings ADD COLUMN IF NOT EXISTS expires_at timestamp(6) WITH time zone DEFAULT NULL;
+
+-- Open orders are expired by the expiry worker once the chain time passes `expires_at`
+CREATE INDEX IF NOT EXISTS bids_active_expiry_idx ON bids (market_name, expires_at)
+WHERE status = 'active' AND expires_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS listings_listed_expiry_idx ON listings (market_name, expires_at)
+WHERE status = 'listed' AND expires_at IS NOT NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS actions_nft_ownership_idx;
+UPDATE listings SET status = 'listed', listed = TRUE WHERE status = 'invalid';
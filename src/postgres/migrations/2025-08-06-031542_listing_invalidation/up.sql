@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Latest transfer or burn of an nft, used to invalidate listings of sellers that no longer
+-- hold the nft
+CREATE INDEX IF NOT EXISTS actions_nft_ownership_idx ON actions (nft_id, tx_index DESC)
+WHERE tx_type IN ('transfer', 'burn');
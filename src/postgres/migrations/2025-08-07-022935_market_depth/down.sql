@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS bids_collection_offers_active_idx;
+DROP INDEX IF EXISTS bids_nft_active_idx;
+DROP INDEX IF EXISTS listings_nft_listed_idx;
+DROP TABLE IF EXISTS market_depth;
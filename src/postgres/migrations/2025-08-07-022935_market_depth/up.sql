@@ -0,0 +1,32 @@
+-- Your SQL goes here
+-- Best ask and best bid across all marketplaces, per nft and per collection
+CREATE TABLE IF NOT EXISTS market_depth (
+  -- nft or collection
+  depth_type VARCHAR(20) NOT NULL,
+  -- Nft id or collection id
+  id VARCHAR(66) NOT NULL,
+  collection_id VARCHAR(66),
+  best_ask BIGINT DEFAULT NULL,
+  best_ask_market_name VARCHAR(128),
+  best_ask_market_contract_id VARCHAR(66),
+  best_ask_nft_id VARCHAR(66),
+  best_ask_nonce VARCHAR(128),
+  best_bid BIGINT DEFAULT NULL,
+  best_bid_market_name VARCHAR(128),
+  best_bid_market_contract_id VARCHAR(66),
+  best_bid_nonce VARCHAR(128),
+  -- solo or collection
+  best_bid_type VARCHAR(20),
+  updated_at timestamp(6) WITH time zone DEFAULT NOW(),
+  PRIMARY KEY (depth_type, id)
+);
+CREATE INDEX IF NOT EXISTS market_depth_collection_idx ON market_depth (collection_id)
+WHERE depth_type = 'nft';
+
+-- Best prices are looked up per nft and per collection
+CREATE INDEX IF NOT EXISTS listings_nft_listed_idx ON listings (nft_id, price)
+WHERE listed = true;
+CREATE INDEX IF NOT EXISTS bids_nft_active_idx ON bids (nft_id, price)
+WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS bids_collection_offers_active_idx ON bids (collection_id, price)
+WHERE status = 'active' AND bid_type = 'collection';
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS actions_sale_pair_idx;
+DROP TABLE IF EXISTS worker_checkpoints;
+DROP TABLE IF EXISTS wallet_transfers;
+DROP TABLE IF EXISTS trade_flags;
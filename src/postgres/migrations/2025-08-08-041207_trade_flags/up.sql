@@ -0,0 +1,42 @@
+-- Your SQL goes here
+-- Sales flagged as likely wash trades, one row per reason. Keyed by the action of the sale
+CREATE TABLE IF NOT EXISTS trade_flags (
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  -- round_trip, mutual_funding or price_outlier
+  reason VARCHAR(30) NOT NULL,
+  collection_id VARCHAR(66),
+  nft_id VARCHAR(66),
+  buyer VARCHAR(66),
+  seller VARCHAR(66),
+  price BIGINT,
+  details TEXT,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  PRIMARY KEY (tx_index, tx_id, reason)
+);
+CREATE INDEX IF NOT EXISTS trade_flags_collection_idx ON trade_flags (collection_id);
+
+-- Direct coin transfers between wallets, used to find buyers and sellers funded by each other
+CREATE TABLE IF NOT EXISTS wallet_transfers (
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  sender VARCHAR(66) NOT NULL,
+  receiver VARCHAR(66) NOT NULL,
+  coin_type TEXT,
+  amount BIGINT,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  PRIMARY KEY (tx_index, tx_id)
+);
+CREATE INDEX IF NOT EXISTS wallet_transfers_receiver_idx ON wallet_transfers (receiver, tx_index DESC);
+
+-- Progress of the background workers that scan the actions in order
+CREATE TABLE IF NOT EXISTS worker_checkpoints (
+  worker VARCHAR(50) NOT NULL,
+  last_tx_index BIGINT NOT NULL,
+  updated_at timestamp(6) WITH time zone DEFAULT NOW(),
+  PRIMARY KEY (worker)
+);
+
+-- Previous sales between the same wallets
+CREATE INDEX IF NOT EXISTS actions_sale_pair_idx ON actions (sender, receiver, tx_index)
+WHERE tx_type IN ('buy', 'accept_bid', 'accept_collection_bid');
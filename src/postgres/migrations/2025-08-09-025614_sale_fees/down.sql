@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS sale_fees;
+
+ALTER TABLE commissions DROP COLUMN IF EXISTS payee_address;
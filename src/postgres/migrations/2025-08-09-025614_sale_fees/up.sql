@@ -0,0 +1,25 @@
+-- Your SQL goes here
+-- Wallet the royalty is paid to
+ALTER TABLE commissions ADD COLUMN IF NOT EXISTS payee_address VARCHAR(66) DEFAULT NULL;
+
+-- Where the price of each sale went, keyed by the action of the sale
+CREATE TABLE IF NOT EXISTS sale_fees (
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  market_name VARCHAR(30),
+  market_contract_id VARCHAR(66),
+  collection_id VARCHAR(66),
+  nft_id VARCHAR(66),
+  seller VARCHAR(66),
+  price BIGINT NOT NULL,
+  royalty_amount BIGINT,
+  royalty_payee VARCHAR(66),
+  marketplace_fee BIGINT,
+  seller_proceeds BIGINT,
+  -- `events`, `fee_schedule` or `royalty`
+  source VARCHAR(20) NOT NULL,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  PRIMARY KEY (tx_index, tx_id)
+);
+CREATE INDEX IF NOT EXISTS sale_fees_collection_idx ON sale_fees (collection_id, tx_index DESC);
+CREATE INDEX IF NOT EXISTS sale_fees_royalty_payee_idx ON sale_fees (royalty_payee, tx_index DESC);
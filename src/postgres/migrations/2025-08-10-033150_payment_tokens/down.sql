@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM prices WHERE payment_token <> '0x1::aptos_coin::AptosCoin';
+ALTER TABLE prices DROP CONSTRAINT IF EXISTS prices_pkey;
+ALTER TABLE prices DROP COLUMN IF EXISTS payment_token;
+ALTER TABLE prices ADD PRIMARY KEY (created_at);
+
+ALTER TABLE bids DROP COLUMN IF EXISTS payment_token;
+ALTER TABLE listings DROP COLUMN IF EXISTS payment_token;
+ALTER TABLE actions DROP COLUMN IF EXISTS payment_token;
+
+DROP TABLE IF EXISTS payment_tokens;
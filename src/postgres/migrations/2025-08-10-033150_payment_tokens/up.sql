@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- Coins and fungible assets sales are paid in, keyed by coin type or fungible asset metadata
+-- address
+CREATE TABLE IF NOT EXISTS payment_tokens (
+  id VARCHAR(300) NOT NULL,
+  symbol VARCHAR(32),
+  decimals INT NOT NULL,
+  PRIMARY KEY (id)
+);
+INSERT INTO payment_tokens (id, symbol, decimals)
+VALUES ('0x1::aptos_coin::AptosCoin', 'APT', 8)
+ON CONFLICT (id) DO NOTHING;
+
+-- Everything indexed so far was assumed to be paid in APT
+ALTER TABLE actions ADD COLUMN IF NOT EXISTS payment_token VARCHAR(300) DEFAULT '0x1::aptos_coin::AptosCoin';
+ALTER TABLE listings ADD COLUMN IF NOT EXISTS payment_token VARCHAR(300) DEFAULT '0x1::aptos_coin::AptosCoin';
+ALTER TABLE bids ADD COLUMN IF NOT EXISTS payment_token VARCHAR(300) DEFAULT '0x1::aptos_coin::AptosCoin';
+
+-- USD prices are kept per payment token, the existing candles are APT/USD
+ALTER TABLE prices ADD COLUMN IF NOT EXISTS payment_token VARCHAR(300) NOT NULL DEFAULT '0x1::aptos_coin::AptosCoin';
+ALTER TABLE prices DROP CONSTRAINT IF EXISTS prices_pkey;
+ALTER TABLE prices ADD PRIMARY KEY (payment_token, created_at);
@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS activity_errors;
+
+-- Prices above the BIGINT range are lost
+DELETE FROM sale_fees WHERE price > 9223372036854775807;
+ALTER TABLE sale_fees
+  ALTER COLUMN price TYPE BIGINT USING price::BIGINT,
+  ALTER COLUMN royalty_amount TYPE BIGINT USING CASE WHEN royalty_amount <= 9223372036854775807 THEN royalty_amount::BIGINT END,
+  ALTER COLUMN marketplace_fee TYPE BIGINT USING CASE WHEN marketplace_fee <= 9223372036854775807 THEN marketplace_fee::BIGINT END,
+  ALTER COLUMN seller_proceeds TYPE BIGINT USING CASE WHEN seller_proceeds <= 9223372036854775807 THEN seller_proceeds::BIGINT END;
+ALTER TABLE market_depth
+  ALTER COLUMN best_ask TYPE BIGINT USING CASE WHEN best_ask <= 9223372036854775807 THEN best_ask::BIGINT END,
+  ALTER COLUMN best_bid TYPE BIGINT USING CASE WHEN best_bid <= 9223372036854775807 THEN best_bid::BIGINT END;
+ALTER TABLE collection_stats ALTER COLUMN floor_price TYPE BIGINT USING CASE WHEN floor_price <= 9223372036854775807 THEN floor_price::BIGINT END;
+ALTER TABLE trade_flags ALTER COLUMN price TYPE BIGINT USING CASE WHEN price <= 9223372036854775807 THEN price::BIGINT END;
+ALTER TABLE listing_fills ALTER COLUMN price TYPE BIGINT USING CASE WHEN price <= 9223372036854775807 THEN price::BIGINT END;
+ALTER TABLE bid_fills ALTER COLUMN price TYPE BIGINT USING CASE WHEN price <= 9223372036854775807 THEN price::BIGINT END;
+ALTER TABLE bids ALTER COLUMN price TYPE BIGINT USING CASE WHEN price <= 9223372036854775807 THEN price::BIGINT END;
+ALTER TABLE listings ALTER COLUMN price TYPE BIGINT USING CASE WHEN price <= 9223372036854775807 THEN price::BIGINT END;
+ALTER TABLE actions ALTER COLUMN price TYPE BIGINT USING CASE WHEN price <= 9223372036854775807 THEN price::BIGINT END;
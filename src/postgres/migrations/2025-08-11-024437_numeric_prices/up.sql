@@ -0,0 +1,34 @@
+-- Your SQL goes here
+-- Move u64 prices don't fit in BIGINT, the stored prices are kept as they are
+ALTER TABLE actions ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC;
+ALTER TABLE listings ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC;
+ALTER TABLE bids ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC;
+ALTER TABLE bid_fills ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC;
+ALTER TABLE listing_fills ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC;
+ALTER TABLE trade_flags ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC;
+ALTER TABLE collection_stats ALTER COLUMN floor_price TYPE NUMERIC USING floor_price::NUMERIC;
+ALTER TABLE market_depth
+  ALTER COLUMN best_ask TYPE NUMERIC USING best_ask::NUMERIC,
+  ALTER COLUMN best_bid TYPE NUMERIC USING best_bid::NUMERIC;
+ALTER TABLE sale_fees
+  ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC,
+  ALTER COLUMN royalty_amount TYPE NUMERIC USING royalty_amount::NUMERIC,
+  ALTER COLUMN marketplace_fee TYPE NUMERIC USING marketplace_fee::NUMERIC,
+  ALTER COLUMN seller_proceeds TYPE NUMERIC USING seller_proceeds::NUMERIC;
+
+-- Values of marketplace events that couldn't be parsed into their activity field, which is
+-- left empty instead. Keyed by the action of the event and the field
+CREATE TABLE IF NOT EXISTS activity_errors (
+  tx_index BIGINT NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  field VARCHAR(50) NOT NULL,
+  market_contract_id VARCHAR(66),
+  market_name VARCHAR(128),
+  event_type VARCHAR(30) NOT NULL,
+  raw_event_type TEXT NOT NULL,
+  raw_value TEXT NOT NULL,
+  error TEXT NOT NULL,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  PRIMARY KEY (tx_index, tx_id, field)
+);
+CREATE INDEX IF NOT EXISTS activity_errors_market_idx ON activity_errors (market_name, field);
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- Marketplace events held back because their activity couldn't be keyed after some json paths
+-- of the config matched nothing. Replayed by the `replay-dead-letters` command
+CREATE TABLE IF NOT EXISTS dead_letter_events (
+  txn_version BIGINT NOT NULL,
+  event_index BIGINT NOT NULL,
+  market_name VARCHAR(128) NOT NULL,
+  tx_id VARCHAR(66) NOT NULL,
+  market_contract_id VARCHAR(66) NOT NULL,
+  event_type TEXT NOT NULL,
+  json_path TEXT NOT NULL,
+  error TEXT NOT NULL,
+  event JSONB NOT NULL,
+  block_time timestamp(6) WITH time zone NOT NULL,
+  created_at timestamp(6) WITH time zone NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (txn_version, event_index, market_name)
+);
+CREATE INDEX IF NOT EXISTS dead_letter_events_market_idx ON dead_letter_events (market_name, txn_version);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE prices ALTER COLUMN price TYPE NUMERIC(20, 2) USING price::NUMERIC(20, 2);
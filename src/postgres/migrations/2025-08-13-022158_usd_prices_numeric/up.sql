@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- USD prices of tokens worth less than a cent were rounded to 0.00
+ALTER TABLE prices ALTER COLUMN price TYPE NUMERIC USING price::NUMERIC;
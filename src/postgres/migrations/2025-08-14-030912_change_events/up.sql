@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Outbox of the changes published to real-time subscribers. `id` follows the publish order
+-- across pipelines and workers, so clients resume from the last id they received
+CREATE TABLE IF NOT EXISTS change_events (
+  id BIGSERIAL NOT NULL,
+  event_type VARCHAR(30) NOT NULL,
+  -- NULL for changes made by the workers rather than by a transaction
+  tx_index BIGINT DEFAULT NULL,
+  tx_id VARCHAR(66) DEFAULT NULL,
+  nft_id VARCHAR(66) DEFAULT NULL,
+  collection_id VARCHAR(66) DEFAULT NULL,
+  sender VARCHAR(66) DEFAULT NULL,
+  receiver VARCHAR(66) DEFAULT NULL,
+  price NUMERIC DEFAULT NULL,
+  market_name VARCHAR(30) DEFAULT NULL,
+  market_contract_id VARCHAR(66) DEFAULT NULL,
+  block_time timestamp(6) WITH time zone DEFAULT NULL,
+  created_at timestamp(6) WITH time zone NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (id)
+);
+CREATE INDEX IF NOT EXISTS change_events_collection_idx ON change_events (collection_id, id);
+CREATE INDEX IF NOT EXISTS change_events_nft_idx ON change_events (nft_id, id);
+CREATE INDEX IF NOT EXISTS change_events_created_at_idx ON change_events (created_at);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE collection_stats DROP COLUMN IF EXISTS nft_count;
+ALTER TABLE actions
+  DROP COLUMN IF EXISTS stats_counted,
+  DROP COLUMN IF EXISTS stats_volume,
+  DROP COLUMN IF EXISTS stats_volume_usd;
+DROP TABLE IF EXISTS collection_owners;
@@ -0,0 +1,59 @@
+-- Your SQL goes here
+-- Nfts held per owner, so that the owner count of a collection is updated from the nfts that
+-- changed hands instead of a scan of the whole collection
+CREATE TABLE IF NOT EXISTS collection_owners (
+  collection_id VARCHAR(66) NOT NULL,
+  owner VARCHAR(66) NOT NULL,
+  nft_count BIGINT NOT NULL DEFAULT 0,
+  PRIMARY KEY (collection_id, owner)
+);
+
+INSERT INTO collection_owners (collection_id, owner, nft_count)
+SELECT collection_id, owner, COUNT(*)
+FROM nfts
+WHERE collection_id IS NOT NULL AND owner IS NOT NULL AND burned IS NOT TRUE
+GROUP BY collection_id, owner
+ON CONFLICT (collection_id, owner) DO NOTHING;
+
+-- What each sale currently adds to the running totals of its collection
+ALTER TABLE actions
+  ADD COLUMN IF NOT EXISTS stats_counted BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD COLUMN IF NOT EXISTS stats_volume NUMERIC NOT NULL DEFAULT 0,
+  ADD COLUMN IF NOT EXISTS stats_volume_usd NUMERIC NOT NULL DEFAULT 0;
+
+UPDATE actions a
+SET
+  stats_counted = TRUE,
+  stats_volume = CASE WHEN a.payment_token = '0x1::aptos_coin::AptosCoin'
+    THEN COALESCE(a.price, 0)
+    ELSE 0
+  END,
+  stats_volume_usd = COALESCE(a.usd_price, 0)
+WHERE a.tx_type IN ('buy', 'accept_bid', 'accept_collection_bid')
+  AND a.collection_id IS NOT NULL
+  AND NOT EXISTS (
+    SELECT 1 FROM trade_flags f WHERE f.tx_index = a.tx_index AND f.tx_id = a.tx_id
+  );
+
+ALTER TABLE collection_stats ADD COLUMN IF NOT EXISTS nft_count BIGINT DEFAULT 0;
+
+-- Seed the running totals
+UPDATE collection_stats SET owner_count = 0, nft_count = 0, sales_count = 0, volume = 0, volume_usd = 0;
+
+INSERT INTO collection_stats (collection_id, owner_count, nft_count)
+SELECT collection_id, COUNT(*), SUM(nft_count)
+FROM collection_owners
+GROUP BY collection_id
+ON CONFLICT (collection_id) DO UPDATE SET
+  owner_count = EXCLUDED.owner_count,
+  nft_count = EXCLUDED.nft_count;
+
+INSERT INTO collection_stats (collection_id, sales_count, volume, volume_usd)
+SELECT collection_id, COUNT(*), SUM(stats_volume), SUM(stats_volume_usd)
+FROM actions
+WHERE stats_counted
+GROUP BY collection_id
+ON CONFLICT (collection_id) DO UPDATE SET
+  sales_count = EXCLUDED.sales_count,
+  volume = EXCLUDED.volume,
+  volume_usd = EXCLUDED.volume_usd;
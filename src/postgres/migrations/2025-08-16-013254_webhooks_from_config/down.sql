@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE webhooks DROP COLUMN IF EXISTS from_config;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Webhooks written from the config, the others were registered directly in the table
+ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS from_config BOOLEAN NOT NULL DEFAULT FALSE;
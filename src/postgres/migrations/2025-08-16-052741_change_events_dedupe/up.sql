@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- A batch that is stored again, e.g. after a restart, publishes its changes once. Changes made
+-- by the workers have no tx_index and are never deduplicated.
+DELETE FROM change_events a
+USING change_events b
+WHERE a.tx_index = b.tx_index
+  AND a.tx_id = b.tx_id
+  AND a.event_type = b.event_type
+  AND a.id > b.id;
+CREATE UNIQUE INDEX IF NOT EXISTS change_events_tx_idx ON change_events (tx_index, tx_id, event_type);
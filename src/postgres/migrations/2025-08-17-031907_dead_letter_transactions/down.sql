@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE dead_letter_events DROP COLUMN IF EXISTS transaction;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- The transaction of a dead-lettered event, without its payload and the write set changes other
+-- than resources, so the event is replayed with its write set resources and coin deposits
+ALTER TABLE dead_letter_events ADD COLUMN IF NOT EXISTS transaction JSONB;
@@ -1,5 +1,25 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    activity_errors (tx_index, tx_id, field) {
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 50]
+        field -> Varchar,
+        #[max_length = 66]
+        market_contract_id -> Nullable<Varchar>,
+        #[max_length = 128]
+        market_name -> Nullable<Varchar>,
+        #[max_length = 30]
+        event_type -> Varchar,
+        raw_event_type -> Text,
+        raw_value -> Text,
+        error -> Text,
+        block_time -> Timestamptz,
+    }
+}
+
 diesel::table! {
     actions (tx_index, tx_id) {
         #[max_length = 30]
@@ -11,7 +31,7 @@ diesel::table! {
         sender -> Nullable<Varchar>,
         #[max_length = 66]
         receiver -> Nullable<Varchar>,
-        price -> Nullable<Int8>,
+        price -> Nullable<Numeric>,
         #[max_length = 66]
         nft_id -> Nullable<Varchar>,
         #[max_length = 66]
@@ -23,6 +43,34 @@ diesel::table! {
         usd_price -> Nullable<Numeric>,
         block_time -> Timestamptz,
         block_height -> Int8,
+        #[max_length = 300]
+        payment_token -> Nullable<Varchar>,
+        stats_counted -> Bool,
+        stats_volume -> Numeric,
+        stats_volume_usd -> Numeric,
+    }
+}
+
+diesel::table! {
+    anomalies (tx_index, tx_id) {
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 20]
+        order_type -> Varchar,
+        #[max_length = 66]
+        market_contract_id -> Varchar,
+        #[max_length = 128]
+        market_name -> Nullable<Varchar>,
+        #[max_length = 128]
+        order_id -> Nullable<Varchar>,
+        #[max_length = 30]
+        event_type -> Varchar,
+        raw_event_type -> Text,
+        #[max_length = 20]
+        from_status -> Nullable<Varchar>,
+        reason -> Text,
+        block_time -> Timestamptz,
     }
 }
 
@@ -53,6 +101,32 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    bid_fills (tx_index, tx_id) {
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 66]
+        market_contract_id -> Varchar,
+        #[max_length = 128]
+        nonce -> Varchar,
+        #[max_length = 20]
+        bid_type -> Nullable<Varchar>,
+        #[max_length = 66]
+        collection_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        nft_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        bidder -> Nullable<Varchar>,
+        #[max_length = 66]
+        seller -> Nullable<Varchar>,
+        amount -> Int8,
+        price -> Nullable<Numeric>,
+        remaining_count -> Nullable<Int8>,
+        block_time -> Timestamptz,
+    }
+}
+
 diesel::table! {
     bids (market_contract_id, nonce) {
         #[max_length = 66]
@@ -74,7 +148,7 @@ diesel::table! {
         nonce -> Varchar,
         #[max_length = 66]
         nft_id -> Nullable<Varchar>,
-        price -> Nullable<Int8>,
+        price -> Nullable<Numeric>,
         #[max_length = 128]
         price_str -> Nullable<Varchar>,
         #[max_length = 66]
@@ -84,6 +158,59 @@ diesel::table! {
         status -> Nullable<Varchar>,
         #[max_length = 20]
         bid_type -> Nullable<Varchar>,
+        #[max_length = 66]
+        match_key -> Nullable<Varchar>,
+        #[max_length = 300]
+        payment_token -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    change_events (id) {
+        id -> Int8,
+        #[max_length = 30]
+        event_type -> Varchar,
+        tx_index -> Nullable<Int8>,
+        #[max_length = 66]
+        tx_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        nft_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        collection_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        sender -> Nullable<Varchar>,
+        #[max_length = 66]
+        receiver -> Nullable<Varchar>,
+        price -> Nullable<Numeric>,
+        #[max_length = 30]
+        market_name -> Nullable<Varchar>,
+        #[max_length = 66]
+        market_contract_id -> Nullable<Varchar>,
+        block_time -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    collection_stats (collection_id) {
+        #[max_length = 66]
+        collection_id -> Varchar,
+        floor_price -> Nullable<Numeric>,
+        listed_count -> Nullable<Int8>,
+        owner_count -> Nullable<Int8>,
+        supply -> Nullable<Int8>,
+        listed_percentage -> Nullable<Numeric>,
+        sales_count -> Nullable<Int8>,
+        volume -> Nullable<Numeric>,
+        volume_usd -> Nullable<Numeric>,
+        volume_24h -> Nullable<Numeric>,
+        volume_24h_usd -> Nullable<Numeric>,
+        volume_7d -> Nullable<Numeric>,
+        volume_7d_usd -> Nullable<Numeric>,
+        volume_30d -> Nullable<Numeric>,
+        volume_30d_usd -> Nullable<Numeric>,
+        updated_at -> Nullable<Timestamptz>,
+        nft_count -> Nullable<Int8>,
     }
 }
 
@@ -106,6 +233,26 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    collection_owners (collection_id, owner) {
+        #[max_length = 66]
+        collection_id -> Varchar,
+        #[max_length = 66]
+        owner -> Varchar,
+        nft_count -> Int8,
+    }
+}
+
+diesel::table! {
+    collection_rarities (collection_id) {
+        #[max_length = 66]
+        collection_id -> Varchar,
+        supply -> Nullable<Int8>,
+        marked_at -> Timestamptz,
+        computed_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     commissions (id) {
         id -> Uuid,
@@ -114,6 +261,52 @@ diesel::table! {
         nft_id -> Nullable<Varchar>,
         #[max_length = 664]
         collection_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        payee_address -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    dead_letter_events (txn_version, event_index, market_name) {
+        txn_version -> Int8,
+        event_index -> Int8,
+        #[max_length = 128]
+        market_name -> Varchar,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 66]
+        market_contract_id -> Varchar,
+        event_type -> Text,
+        json_path -> Text,
+        error -> Text,
+        event -> Jsonb,
+        block_time -> Timestamptz,
+        created_at -> Timestamptz,
+        transaction -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    listing_fills (tx_index, tx_id) {
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 66]
+        market_contract_id -> Varchar,
+        #[max_length = 66]
+        nft_id -> Varchar,
+        #[max_length = 66]
+        collection_id -> Nullable<Varchar>,
+        #[max_length = 128]
+        nonce -> Nullable<Varchar>,
+        #[max_length = 66]
+        seller -> Nullable<Varchar>,
+        #[max_length = 66]
+        buyer -> Nullable<Varchar>,
+        amount -> Int8,
+        price -> Nullable<Numeric>,
+        remaining_count -> Nullable<Int8>,
+        block_time -> Timestamptz,
     }
 }
 
@@ -132,12 +325,62 @@ diesel::table! {
         market_name -> Nullable<Varchar>,
         #[max_length = 128]
         nonce -> Nullable<Varchar>,
-        price -> Nullable<Int8>,
+        price -> Nullable<Numeric>,
         #[max_length = 128]
         price_str -> Nullable<Varchar>,
         #[max_length = 66]
         seller -> Nullable<Varchar>,
         tx_index -> Nullable<Int8>,
+        remaining_count -> Nullable<Int8>,
+        #[max_length = 20]
+        status -> Nullable<Varchar>,
+        expires_at -> Nullable<Timestamptz>,
+        #[max_length = 300]
+        payment_token -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    market_depth (depth_type, id) {
+        #[max_length = 20]
+        depth_type -> Varchar,
+        #[max_length = 66]
+        id -> Varchar,
+        #[max_length = 66]
+        collection_id -> Nullable<Varchar>,
+        best_ask -> Nullable<Numeric>,
+        #[max_length = 128]
+        best_ask_market_name -> Nullable<Varchar>,
+        #[max_length = 66]
+        best_ask_market_contract_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        best_ask_nft_id -> Nullable<Varchar>,
+        #[max_length = 128]
+        best_ask_nonce -> Nullable<Varchar>,
+        best_bid -> Nullable<Numeric>,
+        #[max_length = 128]
+        best_bid_market_name -> Nullable<Varchar>,
+        #[max_length = 66]
+        best_bid_market_contract_id -> Nullable<Varchar>,
+        #[max_length = 128]
+        best_bid_nonce -> Nullable<Varchar>,
+        #[max_length = 20]
+        best_bid_type -> Nullable<Varchar>,
+        updated_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    nft_rarities (nft_id) {
+        #[max_length = 66]
+        nft_id -> Varchar,
+        #[max_length = 66]
+        collection_id -> Varchar,
+        trait_count -> Nullable<Int8>,
+        rarity_score -> Nullable<Numeric>,
+        statistical_rarity -> Nullable<Numeric>,
+        rarity_rank -> Nullable<Int8>,
+        statistical_rank -> Nullable<Int8>,
     }
 }
 
@@ -164,13 +407,31 @@ diesel::table! {
         #[max_length = 10]
         version -> Nullable<Varchar>,
         created_at -> Nullable<Timestamptz>,
+        uri -> Nullable<Varchar>,
+        #[max_length = 20]
+        metadata_status -> Nullable<Varchar>,
+        metadata_attempts -> Nullable<Int4>,
+        metadata_error -> Nullable<Text>,
+        metadata_updated_at -> Nullable<Timestamptz>,
     }
 }
 
 diesel::table! {
-    prices (created_at) {
+    payment_tokens (id) {
+        #[max_length = 300]
+        id -> Varchar,
+        #[max_length = 32]
+        symbol -> Nullable<Varchar>,
+        decimals -> Int4,
+    }
+}
+
+diesel::table! {
+    prices (payment_token, created_at) {
         created_at -> Timestamptz,
         price -> Numeric,
+        #[max_length = 300]
+        payment_token -> Varchar,
     }
 }
 
@@ -184,15 +445,149 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    sale_fees (tx_index, tx_id) {
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 30]
+        market_name -> Nullable<Varchar>,
+        #[max_length = 66]
+        market_contract_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        collection_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        nft_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        seller -> Nullable<Varchar>,
+        price -> Numeric,
+        royalty_amount -> Nullable<Numeric>,
+        #[max_length = 66]
+        royalty_payee -> Nullable<Varchar>,
+        marketplace_fee -> Nullable<Numeric>,
+        seller_proceeds -> Nullable<Numeric>,
+        #[max_length = 20]
+        source -> Varchar,
+        block_time -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    trade_flags (tx_index, tx_id, reason) {
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 30]
+        reason -> Varchar,
+        #[max_length = 66]
+        collection_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        nft_id -> Nullable<Varchar>,
+        #[max_length = 66]
+        buyer -> Nullable<Varchar>,
+        #[max_length = 66]
+        seller -> Nullable<Varchar>,
+        price -> Nullable<Numeric>,
+        details -> Nullable<Text>,
+        block_time -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    wallet_transfers (tx_index, tx_id) {
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 66]
+        sender -> Varchar,
+        #[max_length = 66]
+        receiver -> Varchar,
+        coin_type -> Nullable<Text>,
+        amount -> Nullable<Int8>,
+        block_time -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (webhook_id, tx_index, tx_id) {
+        #[max_length = 128]
+        webhook_id -> Varchar,
+        tx_index -> Int8,
+        #[max_length = 66]
+        tx_id -> Varchar,
+        #[max_length = 30]
+        event_type -> Varchar,
+        payload -> Jsonb,
+        #[max_length = 20]
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_status_code -> Nullable<Int4>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        delivered_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        #[max_length = 128]
+        id -> Varchar,
+        url -> Text,
+        secret -> Text,
+        collection_ids -> Nullable<Array<Text>>,
+        marketplaces -> Nullable<Array<Text>>,
+        event_types -> Nullable<Array<Text>>,
+        active -> Bool,
+        delivered_count -> Int8,
+        failed_attempt_count -> Int8,
+        dead_count -> Int8,
+        last_delivered_at -> Nullable<Timestamptz>,
+        last_failed_at -> Nullable<Timestamptz>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        from_config -> Bool,
+    }
+}
+
+diesel::table! {
+    worker_checkpoints (worker) {
+        #[max_length = 50]
+        worker -> Varchar,
+        last_tx_index -> Int8,
+        updated_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    activity_errors,
     actions,
+    anomalies,
     attributes,
     backfill_processor_status,
+    bid_fills,
     bids,
+    change_events,
+    collection_owners,
+    collection_rarities,
+    collection_stats,
     collections,
     commissions,
+    dead_letter_events,
+    listing_fills,
     listings,
+    market_depth,
+    nft_rarities,
     nfts,
+    payment_tokens,
     prices,
     processor_status,
+    sale_fees,
+    trade_flags,
+    wallet_transfers,
+    webhook_deliveries,
+    webhooks,
+    worker_checkpoints,
 );
@@ -1,10 +1,23 @@
+#[cfg(feature = "api")]
+use crate::api::ApiServer;
 use crate::{
-    config::{marketplace_config::NFTMarketplaceConfig, DbConfig, IndexerProcessorConfig},
+    config::{
+        marketplace_config::{MarketplaceDeployment, NFTMarketplaceConfig},
+        DbConfig, IndexerProcessorConfig,
+    },
+    models::db::{
+        dead_letter_event::{insert_dead_letter_events, DeadLetterEvent},
+        payment_token::PaymentToken,
+        wallet_transfer::WALLET_TRANSFER_FUNCTIONS,
+        webhook::Webhook,
+    },
+    postgres::postgres_utils::execute_in_chunks,
     steps::{
         marketplace::{
             db_writing_step::DBWritingStep as MarketplaceDBWritingStep,
             reduction_step::NFTReductionStep as MarketplaceNFTReductionStep,
             remapper_step::ProcessStep as MarketplaceProcessStep,
+            remappers::event_remapper::RemappedEvent,
         },
         processor_status_saver_step::{
             get_end_version, get_starting_version, PostgresProcessorStatusSaver,
@@ -13,14 +26,21 @@ use crate::{
             db_writing_step::DBWritingStep as TokenDBWritingStep, extractor_step::TokenExtractor,
         },
     },
-    workers::price_worker::PriceWorker,
+    utils::event_bus::EventBus,
+    workers::{
+        attribute_worker::AttributeWorker, expiry_worker::ExpiryWorker,
+        outbox_worker::OutboxWorker, price_worker::PriceWorker, rarity_worker::RarityWorker,
+        spawn_worker, stats_worker::StatsWorker, wash_trade_worker::WashTradeWorker,
+        webhook_worker::WebhookWorker,
+    },
     MIGRATIONS,
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
     aptos_indexer_transaction_stream::{
-        BooleanTransactionFilter, EventFilterBuilder, MoveStructTagFilterBuilder,
-        TransactionRootFilterBuilder, TransactionStreamConfig,
+        BooleanTransactionFilter, EntryFunctionFilterBuilder, EventFilterBuilder,
+        MoveStructTagFilterBuilder, TransactionRootFilterBuilder, TransactionStreamConfig,
+        UserTransactionFilterBuilder, UserTransactionPayloadFilterBuilder,
     },
     aptos_protos::transaction::v1::transaction::TransactionType,
     builder::ProcessorBuilder,
@@ -31,15 +51,20 @@ use aptos_indexer_processor_sdk::{
         checkpoint::PostgresChainIdChecker,
         database::{new_db_pool, run_migrations, ArcDbPool},
     },
-    traits::{processor_trait::ProcessorTrait, IntoRunnableStep},
+    traits::{processor_trait::ProcessorTrait, IntoRunnableStep, Processable},
+    types::transaction_context::{TransactionContext, TransactionMetadata},
     utils::chain_id_check::check_or_update_chain_id,
 };
+use chrono::Utc;
 use futures::future::join_all;
-use tracing::{debug, error, info};
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
 
 pub struct Processor {
     pub config: IndexerProcessorConfig,
     pub db_pool: ArcDbPool,
+    /// Changes committed by the writing steps, streamed by the api
+    pub event_bus: EventBus,
 }
 
 impl Processor {
@@ -61,6 +86,7 @@ impl Processor {
                 Ok(Self {
                     config,
                     db_pool: conn_pool,
+                    event_bus: EventBus::default(),
                 })
             },
         }
@@ -114,10 +140,29 @@ impl Processor {
             .txn_type(TransactionType::User)
             .build()?;
 
-        let token_filter = BooleanTransactionFilter::from(token_v1_filter)
+        let mut token_filter = BooleanTransactionFilter::from(token_v1_filter)
             .or(token_v2_filter)
             .or(object_filter);
 
+        // The wash trade worker checks whether the traders funded each other
+        if self.config.wash_trade_worker_config.funding_lookback_txns > 0 {
+            for (module, function) in WALLET_TRANSFER_FUNCTIONS {
+                let entry_function_filter = EntryFunctionFilterBuilder::default()
+                    .address("0x1")
+                    .module(module)
+                    .function(function)
+                    .build()?;
+                let payload_filter = UserTransactionPayloadFilterBuilder::default()
+                    .function(entry_function_filter)
+                    .build()?;
+                let transfer_filter = UserTransactionFilterBuilder::default()
+                    .payload(payload_filter)
+                    .build()?;
+
+                token_filter = token_filter.or(transfer_filter);
+            }
+        }
+
         let filter = BooleanTransactionFilter::from(tx_filter).and(token_filter);
 
         // Define processor steps
@@ -130,7 +175,7 @@ impl Processor {
         .await?;
 
         let process = TokenExtractor::new(self.db_pool.clone());
-        let db_writing = TokenDBWritingStep::new(self.db_pool.clone());
+        let db_writing = TokenDBWritingStep::new(self.db_pool.clone(), self.event_bus.clone());
         let version_tracker = VersionTrackerStep::new(
             PostgresProcessorStatusSaver::new(
                 processor_name,
@@ -168,22 +213,44 @@ impl Processor {
         Ok(())
     }
 
-    async fn get_marketplace_event_stream(&self, config: &NFTMarketplaceConfig) -> Result<()> {
+    async fn get_marketplace_event_stream(&self, deployment: &MarketplaceDeployment) -> Result<()> {
+        let config = &deployment.config;
         let (starting_version, ending_version) = (
             get_starting_version(
-                &config.name,
+                &deployment.checkpoint_name,
                 &self.config.processor_mode,
                 self.db_pool.clone(),
             )
             .await?,
             get_end_version(
-                &config.name,
+                &deployment.checkpoint_name,
                 &self.config.processor_mode,
                 self.db_pool.clone(),
             )
             .await?,
         );
 
+        // The deployment is only streamed in its active version range
+        let starting_version = match (starting_version, deployment.starting_version) {
+            (Some(version), Some(deployed)) => Some(version.max(deployed)),
+            (version, deployed) => version.or(deployed),
+        };
+        let ending_version = match (ending_version, deployment.ending_version) {
+            (Some(version), Some(retired)) => Some(version.min(retired)),
+            (version, retired) => version.or(retired),
+        };
+        if let (Some(starting_version), Some(ending_version)) = (starting_version, ending_version) {
+            if starting_version > ending_version {
+                info!(
+                    checkpoint = %deployment.checkpoint_name,
+                    starting_version,
+                    ending_version,
+                    "Deployment is outside of the versions to process, skipping"
+                );
+                return Ok(());
+            }
+        }
+
         let addr = config.contract_address.clone();
         let struct_filter_builder = MoveStructTagFilterBuilder::default()
             .address(addr)
@@ -210,11 +277,13 @@ impl Processor {
         .await?;
 
         let process = MarketplaceProcessStep::new(config.clone())?;
-        let reduction_step = MarketplaceNFTReductionStep::new();
-        let db_writing = MarketplaceDBWritingStep::new(self.db_pool.clone());
+        let reduction_step =
+            MarketplaceNFTReductionStep::new(self.db_pool.clone(), config.fee_schedule.clone());
+        let db_writing =
+            MarketplaceDBWritingStep::new(self.db_pool.clone(), self.event_bus.clone());
         let version_tracker = VersionTrackerStep::new(
             PostgresProcessorStatusSaver::new(
-                config.name.clone(),
+                deployment.checkpoint_name.clone(),
                 self.config.processor_mode.clone(),
                 self.db_pool.clone(),
             ),
@@ -249,6 +318,168 @@ impl Processor {
 
         Ok(())
     }
+
+    /// Remaps the dead-lettered events of the configured marketplaces, or only of `marketplace`,
+    /// with the current configs and stores the activities of the events that now remap. Events
+    /// that still can't be remapped stay dead-lettered with their latest error.
+    ///
+    /// Events are remapped with the write set resources and coin deposits of the transaction
+    /// kept with them. Events dead-lettered before transactions were kept are remapped from the
+    /// event alone, so their activities miss those fields.
+    pub async fn replay_dead_letters(&self, marketplace: Option<&str>) -> Result<()> {
+        let DbConfig::PostgresConfig(ref postgres_config) = self.config.db_config;
+        run_migrations(
+            postgres_config.connection_string.clone(),
+            self.db_pool.clone(),
+            MIGRATIONS,
+        )
+        .await;
+
+        for config in self
+            .config
+            .nft_marketplace_configs
+            .iter()
+            .filter(|config| marketplace.map_or(true, |name| name == config.name))
+        {
+            let process_step = MarketplaceProcessStep::new(config.clone())?;
+            let dead_letters = {
+                let mut conn = self.db_pool.get().await?;
+                DeadLetterEvent::get_by_marketplace(&mut conn, &config.name).await?
+            };
+
+            let mut activities = vec![];
+            let mut replayed = vec![];
+            let mut still_dead = vec![];
+            for dead_letter in dead_letters {
+                match process_step.remap_dead_letter(&dead_letter)? {
+                    Some(RemappedEvent::Activity(activity)) => {
+                        activities.push(activity);
+                        replayed.push(dead_letter);
+                    },
+                    Some(RemappedEvent::DeadLetter(dead_letter)) => still_dead.push(dead_letter),
+                    None => warn!(
+                        txn_version = dead_letter.txn_version,
+                        event_index = dead_letter.event_index,
+                        "{} no longer maps event {}",
+                        config.name,
+                        dead_letter.event_type
+                    ),
+                }
+            }
+
+            if !activities.is_empty() {
+                let metadata = TransactionMetadata {
+                    start_version: replayed.first().map_or(0, |d| d.txn_version as u64),
+                    end_version: replayed.last().map_or(0, |d| d.txn_version as u64),
+                    ..Default::default()
+                };
+                let mut reduction_step = MarketplaceNFTReductionStep::new(
+                    self.db_pool.clone(),
+                    config.fee_schedule.clone(),
+                );
+                let mut db_writing =
+                    MarketplaceDBWritingStep::new(self.db_pool.clone(), self.event_bus.clone());
+
+                let reduced = reduction_step
+                    .process(TransactionContext {
+                        data: (vec![activities], vec![]),
+                        metadata,
+                    })
+                    .await?;
+                if let Some(reduced) = reduced {
+                    db_writing.process(reduced).await?;
+                }
+
+                let mut conn = self.db_pool.get().await?;
+                for dead_letter in &replayed {
+                    dead_letter.delete(&mut conn).await?;
+                }
+            }
+
+            execute_in_chunks(
+                self.db_pool.clone(),
+                insert_dead_letter_events,
+                &still_dead,
+                200,
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store dead letters: {e:?}"))?;
+
+            info!(
+                marketplace = %config.name,
+                replayed = replayed.len(),
+                still_dead = still_dead.len(),
+                "Replayed dead-lettered events"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Writes the webhooks declared in the config to the `webhooks` table and deactivates the
+    /// ones that were removed from the config.
+    async fn sync_webhooks(&self) -> Result<()> {
+        let now = Utc::now().naive_utc();
+        let webhooks = self
+            .config
+            .webhooks
+            .iter()
+            .map(|config| Webhook::from_config(config, now))
+            .collect::<Vec<_>>();
+
+        let mut conn = self.db_pool.get().await?;
+        let deactivated = Webhook::sync_config(&mut conn, &webhooks).await?;
+        if deactivated > 0 {
+            info!(
+                "Deactivated {} webhooks removed from the config",
+                deactivated
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Writes the payment tokens declared in the config to the `payment_tokens` table.
+    async fn sync_payment_tokens(&self) -> Result<()> {
+        let payment_tokens = self
+            .config
+            .payment_tokens
+            .iter()
+            .map(PaymentToken::from_config)
+            .collect::<Vec<_>>();
+
+        let mut conn = self.db_pool.get().await?;
+        PaymentToken::upsert_all(&mut conn, &payment_tokens).await?;
+
+        Ok(())
+    }
+
+    /// Serves the indexed tables over HTTP when `api_config` is set.
+    #[cfg(feature = "api")]
+    fn start_api_server(&self) {
+        if let Some(api_config) = self.config.api_config.clone() {
+            let api_server = Arc::new(ApiServer::new(
+                api_config,
+                self.db_pool.clone(),
+                self.event_bus.clone(),
+            ));
+            spawn_worker("api_server", move || {
+                let api_server = api_server.clone();
+                async move {
+                    if let Err(e) = api_server.start().await {
+                        error!("Api server failed: {:?}", e);
+                    }
+                }
+            });
+        }
+    }
+
+    #[cfg(not(feature = "api"))]
+    fn start_api_server(&self) {
+        if self.config.api_config.is_some() {
+            warn!("api_config is set but the processor was built without the `api` feature");
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -274,26 +505,114 @@ impl ProcessorTrait for Processor {
         )
         .await?;
 
-        let price_worker = PriceWorker::new(&self.config.tapp_url, self.db_pool.clone());
+        self.sync_payment_tokens().await?;
+        let price_worker = Arc::new(PriceWorker::new(
+            &self.config.tapp_url,
+            &self.config.payment_tokens,
+            self.db_pool.clone(),
+        ));
+        spawn_worker("price_worker", move || {
+            let price_worker = price_worker.clone();
+            async move { price_worker.start().await }
+        });
+
+        let attribute_worker = Arc::new(AttributeWorker::new(
+            self.config.attribute_worker_config.clone(),
+            self.db_pool.clone(),
+        )?);
+        spawn_worker("attribute_worker", move || {
+            let attribute_worker = attribute_worker.clone();
+            async move {
+                if let Err(e) = attribute_worker.start().await {
+                    error!("Attribute worker failed: {:?}", e);
+                }
+            }
+        });
+
+        let expiry_worker = Arc::new(ExpiryWorker::new(
+            self.db_pool.clone(),
+            self.config
+                .nft_marketplace_configs
+                .iter()
+                .map(|config| {
+                    let deployments = config
+                        .get_deployments()
+                        .into_iter()
+                        .map(|deployment| (deployment.checkpoint_name, deployment.ending_version))
+                        .collect();
+                    (config.name.clone(), deployments)
+                })
+                .collect(),
+            self.event_bus.clone(),
+        ));
+        spawn_worker("expiry_worker", move || {
+            let expiry_worker = expiry_worker.clone();
+            async move { expiry_worker.start().await }
+        });
+
+        let wash_trade_worker = Arc::new(WashTradeWorker::new(
+            self.config.wash_trade_worker_config.clone(),
+            self.db_pool.clone(),
+            self.config
+                .nft_marketplace_configs
+                .iter()
+                .flat_map(|config| config.get_deployments())
+                .map(|deployment| (deployment.checkpoint_name, deployment.ending_version))
+                .collect(),
+        ));
+        spawn_worker("wash_trade_worker", move || {
+            let wash_trade_worker = wash_trade_worker.clone();
+            async move { wash_trade_worker.start().await }
+        });
+
+        let rarity_worker = Arc::new(RarityWorker::new(self.db_pool.clone()));
+        spawn_worker("rarity_worker", move || {
+            let rarity_worker = rarity_worker.clone();
+            async move { rarity_worker.start().await }
+        });
+
+        let stats_worker = Arc::new(StatsWorker::new(self.db_pool.clone()));
+        spawn_worker("stats_worker", move || {
+            let stats_worker = stats_worker.clone();
+            async move { stats_worker.start().await }
+        });
+
+        let outbox_worker = Arc::new(OutboxWorker::new(self.db_pool.clone()));
+        spawn_worker("outbox_worker", move || {
+            let outbox_worker = outbox_worker.clone();
+            async move { outbox_worker.start().await }
+        });
+
+        self.sync_webhooks().await?;
+        let webhook_worker = Arc::new(WebhookWorker::new(
+            self.config.webhook_worker_config.clone(),
+            self.db_pool.clone(),
+        )?);
+        spawn_worker("webhook_worker", move || {
+            let webhook_worker = webhook_worker.clone();
+            async move { webhook_worker.start().await }
+        });
 
-        tokio::spawn(async move { price_worker.start().await });
+        self.start_api_server();
 
         let mut nft_marketplace_configs = self.config.nft_marketplace_configs.clone();
         nft_marketplace_configs.push(NFTMarketplaceConfig::default());
 
+        // Each deployment of a marketplace is streamed with its own checkpoint
         let poll_futures: Vec<_> = nft_marketplace_configs
-            .into_iter()
-            .map(|config| async move {
-                let result = if config.name.is_empty() {
+            .iter()
+            .flat_map(|config| config.get_deployments())
+            .map(|deployment| async move {
+                let result = if deployment.config.name.is_empty() {
                     self.get_token_event_stream().await
                 } else {
-                    self.get_marketplace_event_stream(&config).await
+                    self.get_marketplace_event_stream(&deployment).await
                 };
 
                 if let Err(e) = result {
                     error!(
                         err = ?e,
-                        module_addr = %config.contract_address,
+                        module_addr = %deployment.config.contract_address,
                         "Error streaming and publishing events"
                     );
                 }
@@ -1,34 +1,249 @@
 use crate::{
-    models::db::{action::Action, bid::Bid, listing::Listing},
+    models::db::{
+        action::Action,
+        activity_error::ActivityError,
+        anomaly::Anomaly,
+        bid::Bid,
+        collection_stats::{refresh_collection_stats, CollectionStats},
+        dead_letter_event::insert_dead_letter_events,
+        fill::{BidFill, ListingFill},
+        listing::{invalidate_stale_listings, Listing},
+        market_depth::MarketDepthChanges,
+        payment_token::{PaymentToken, APT_COIN_TYPE},
+        price::{calc_usd_price, Price},
+        sale_fee::insert_sale_fees,
+        webhook::enqueue_webhook_deliveries,
+    },
     postgres::postgres_utils::{execute_in_chunks, ArcDbPool},
     schema,
+    steps::marketplace::reduction_step::ReducedBatch,
+    utils::event_bus::EventBus,
 };
 use aptos_indexer_processor_sdk::{
     traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
     types::transaction_context::TransactionContext,
     utils::errors::ProcessorError,
 };
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Utc};
 use diesel::{
     pg::{upsert::excluded, Pg},
     query_builder::QueryFragment,
     query_dsl::methods::FilterDsl,
+    sql_types::{BigInt, Bool, Nullable},
     ExpressionMethods,
 };
+use std::collections::HashMap;
 use tonic::async_trait;
 
 pub struct DBWritingStep {
     pub db_pool: ArcDbPool,
+    pub event_bus: EventBus,
 }
 
 impl DBWritingStep {
-    pub fn new(db_pool: ArcDbPool) -> Self {
-        Self { db_pool }
+    pub fn new(db_pool: ArcDbPool, event_bus: EventBus) -> Self {
+        Self { db_pool, event_bus }
+    }
+
+    /// Sets `usd_price` of the sale actions from the closest USD price of their payment token
+    /// at or before their block time. Sales without a known price are left empty for the
+    /// backfill, and sales paid in a token missing from `payment_tokens` are never priced.
+    async fn set_usd_prices(&self, actions: &mut [Action]) -> Result<(), ProcessorError> {
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        let mut decimals_of: HashMap<String, Option<i32>> = HashMap::new();
+        let mut prices_at: HashMap<(String, NaiveDateTime), Option<BigDecimal>> = HashMap::new();
+        for action in actions.iter_mut().filter(|action| action.is_sale()) {
+            let (Some(price), Some(block_time)) = (action.price.clone(), action.block_time) else {
+                continue;
+            };
+            let payment_token = action
+                .payment_token
+                .clone()
+                .unwrap_or_else(|| APT_COIN_TYPE.to_string());
+
+            if !decimals_of.contains_key(&payment_token) {
+                let decimals = PaymentToken::get_by_id(&mut conn, &payment_token)
+                    .await
+                    .map_err(|e| ProcessorError::DBStoreError {
+                        message: format!("Failed to query payment_tokens table. {e:?}"),
+                        query: None,
+                    })?
+                    .map(|token| token.decimals);
+                decimals_of.insert(payment_token.clone(), decimals);
+            }
+            let Some(Some(decimals)) = decimals_of.get(&payment_token).copied() else {
+                continue;
+            };
+
+            let key = (payment_token, block_time);
+            if !prices_at.contains_key(&key) {
+                let usd_price = Price::get_price_at(&mut conn, &key.0, block_time)
+                    .await
+                    .map_err(|e| ProcessorError::DBStoreError {
+                        message: format!("Failed to query prices table. {e:?}"),
+                        query: None,
+                    })?;
+                prices_at.insert(key.clone(), usd_price);
+            }
+
+            if let Some(Some(usd_price)) = prices_at.get(&key) {
+                action.usd_price = Some(calc_usd_price(&price, decimals, usd_price));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the stored sales to the running totals of their collections and recomputes the
+    /// listing stats of the collections touched by the stored actions and listings.
+    async fn refresh_collection_stats(
+        &self,
+        actions: &[Action],
+        listings: &[Listing],
+    ) -> Result<(), ProcessorError> {
+        let collection_ids = actions
+            .iter()
+            .filter_map(|action| action.collection_id.clone())
+            .chain(
+                listings
+                    .iter()
+                    .filter_map(|listing| listing.collection_id.clone()),
+            );
+
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        let sale_keys = actions
+            .iter()
+            .filter(|action| action.is_sale())
+            .map(|action| (action.tx_index, action.tx_id.clone()))
+            .collect::<Vec<_>>();
+        CollectionStats::count_sales(&mut conn, &sale_keys)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to count sales in collection stats. {e:?}"),
+                query: None,
+            })?;
+
+        refresh_collection_stats(&mut conn, collection_ids)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to refresh collection stats. {e:?}"),
+                query: None,
+            })?;
+
+        Ok(())
+    }
+
+    /// Invalidates the stored listings whose nft was already transferred or burned, in case the
+    /// token pipeline ran ahead of this one, then refreshes the market depth of the nfts and
+    /// collections of the stored bids and listings. Returns the invalidated listings.
+    async fn refresh_open_orders(
+        &self,
+        bids: &[Bid],
+        listings: &[Listing],
+    ) -> Result<Vec<Listing>, ProcessorError> {
+        let nft_ids = listings
+            .iter()
+            .filter(|listing| listing.listed == Some(true))
+            .filter_map(|listing| listing.nft_id.clone());
+
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        let invalidated = invalidate_stale_listings(&mut conn, nft_ids)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to invalidate stale listings. {e:?}"),
+                query: None,
+            })?;
+
+        let mut depth_changes = MarketDepthChanges::default();
+        bids.iter().for_each(|bid| depth_changes.add_bid(bid));
+        listings
+            .iter()
+            .for_each(|listing| depth_changes.add_listing(listing));
+        depth_changes
+            .refresh(&mut conn)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to refresh market depth. {e:?}"),
+                query: None,
+            })?;
+
+        Ok(invalidated)
+    }
+
+    /// Publishes the stored actions and the listings they invalidated to the subscribers.
+    async fn publish_changes(
+        &self,
+        actions: &[Action],
+        invalidated_listings: &[Listing],
+    ) -> Result<(), ProcessorError> {
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        self.event_bus
+            .publish_actions(&mut conn, actions, invalidated_listings)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to publish changes. {e:?}"),
+                query: None,
+            })
+    }
+
+    /// Adds the stored actions to the outbox of the webhooks they match.
+    async fn enqueue_webhook_deliveries(&self, actions: &[Action]) -> Result<(), ProcessorError> {
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        enqueue_webhook_deliveries(&mut conn, actions, Utc::now().naive_utc())
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to enqueue webhook deliveries. {e:?}"),
+                query: None,
+            })?;
+
+        Ok(())
     }
 }
 
 #[async_trait]
 impl Processable for DBWritingStep {
-    type Input = (Vec<Action>, Vec<Bid>, Vec<Listing>);
+    type Input = ReducedBatch;
     type Output = ();
     type RunType = AsyncRunType;
 
@@ -36,16 +251,81 @@ impl Processable for DBWritingStep {
         &mut self,
         input: TransactionContext<Self::Input>,
     ) -> Result<Option<TransactionContext<()>>, ProcessorError> {
-        let (actions, bids, listings) = input.data;
+        let ReducedBatch {
+            mut actions,
+            bids,
+            listings,
+            bid_fills,
+            listing_fills,
+            anomalies,
+            sale_fees,
+            activity_errors,
+            dead_letters,
+        } = input.data;
+
+        self.set_usd_prices(&mut actions).await?;
 
         let action_fut = execute_in_chunks(self.db_pool.clone(), insert_actions, &actions, 200);
         let bid_fut = execute_in_chunks(self.db_pool.clone(), insert_bids, &bids, 200);
         let listing_fut = execute_in_chunks(self.db_pool.clone(), insert_listings, &listings, 200);
+        let bid_fill_fut =
+            execute_in_chunks(self.db_pool.clone(), insert_bid_fills, &bid_fills, 200);
+        let listing_fill_fut = execute_in_chunks(
+            self.db_pool.clone(),
+            insert_listing_fills,
+            &listing_fills,
+            200,
+        );
+        let anomaly_fut =
+            execute_in_chunks(self.db_pool.clone(), insert_anomalies, &anomalies, 200);
+        let sale_fee_fut =
+            execute_in_chunks(self.db_pool.clone(), insert_sale_fees, &sale_fees, 200);
+        let activity_error_fut = execute_in_chunks(
+            self.db_pool.clone(),
+            insert_activity_errors,
+            &activity_errors,
+            200,
+        );
+        let dead_letter_fut = execute_in_chunks(
+            self.db_pool.clone(),
+            insert_dead_letter_events,
+            &dead_letters,
+            200,
+        );
 
-        let (action_result, bid_result, listing_result) =
-            tokio::join!(action_fut, bid_fut, listing_fut);
+        let (
+            action_result,
+            bid_result,
+            listing_result,
+            bid_fill_result,
+            listing_fill_result,
+            anomaly_result,
+            sale_fee_result,
+            activity_error_result,
+            dead_letter_result,
+        ) = tokio::join!(
+            action_fut,
+            bid_fut,
+            listing_fut,
+            bid_fill_fut,
+            listing_fill_fut,
+            anomaly_fut,
+            sale_fee_fut,
+            activity_error_fut,
+            dead_letter_fut
+        );
 
-        for result in [action_result, bid_result, listing_result] {
+        for result in [
+            action_result,
+            bid_result,
+            listing_result,
+            bid_fill_result,
+            listing_fill_result,
+            anomaly_result,
+            sale_fee_result,
+            activity_error_result,
+            dead_letter_result,
+        ] {
             match result {
                 Ok(_) => (),
                 Err(e) => {
@@ -57,6 +337,12 @@ impl Processable for DBWritingStep {
             }
         }
 
+        let invalidated_listings = self.refresh_open_orders(&bids, &listings).await?;
+        self.refresh_collection_stats(&actions, &listings).await?;
+        self.enqueue_webhook_deliveries(&actions).await?;
+        self.publish_changes(&actions, &invalidated_listings)
+            .await?;
+
         Ok(Some(TransactionContext {
             data: (),
             metadata: input.metadata,
@@ -100,6 +386,14 @@ pub fn insert_bids(
             receiver.eq(excluded(receiver)),
             expires_at.eq(excluded(expires_at)),
             nft_id.eq(excluded(nft_id)),
+            // Only set by the creation and by fills of collection offers
+            remaining_count.eq(diesel::dsl::sql::<Nullable<BigInt>>(
+                "COALESCE(excluded.remaining_count, bids.remaining_count)",
+            )),
+        ))
+        // Bids expired by the expiry worker can only be cancelled afterwards
+        .filter(diesel::dsl::sql::<Bool>(
+            "bids.status IS DISTINCT FROM 'expired' OR excluded.status = 'cancelled'",
         ))
 }
 
@@ -121,6 +415,54 @@ pub fn insert_listings(
             price_str.eq(excluded(price_str)),
             seller.eq(excluded(seller)),
             tx_index.eq(excluded(tx_index)),
+            remaining_count.eq(excluded(remaining_count)),
+            status.eq(excluded(status)),
+            expires_at.eq(excluded(expires_at)),
+            payment_token.eq(excluded(payment_token)),
         ))
         .filter(block_time.le(excluded(block_time)))
 }
+
+pub fn insert_bid_fills(
+    items_to_insert: Vec<BidFill>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::bid_fills::dsl::*;
+
+    diesel::insert_into(schema::bid_fills::table)
+        .values(items_to_insert)
+        .on_conflict((tx_index, tx_id))
+        .do_nothing()
+}
+
+pub fn insert_listing_fills(
+    items_to_insert: Vec<ListingFill>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::listing_fills::dsl::*;
+
+    diesel::insert_into(schema::listing_fills::table)
+        .values(items_to_insert)
+        .on_conflict((tx_index, tx_id))
+        .do_nothing()
+}
+
+pub fn insert_anomalies(
+    items_to_insert: Vec<Anomaly>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::anomalies::dsl::*;
+
+    diesel::insert_into(schema::anomalies::table)
+        .values(items_to_insert)
+        .on_conflict((tx_index, tx_id))
+        .do_nothing()
+}
+
+pub fn insert_activity_errors(
+    items_to_insert: Vec<ActivityError>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::activity_errors::dsl::*;
+
+    diesel::insert_into(schema::activity_errors::table)
+        .values(items_to_insert)
+        .on_conflict((tx_index, tx_id, field))
+        .do_nothing()
+}
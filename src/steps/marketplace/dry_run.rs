@@ -0,0 +1,86 @@
+use crate::{
+    config::marketplace_config::NFTMarketplaceConfig,
+    models::db::{
+        action::Action, anomaly::Anomaly, bid::Bid, dead_letter_event::DeadLetterEvent,
+        listing::Listing,
+    },
+    steps::marketplace::{
+        reduction_step::{NFTReductionStep, ReducedBatch},
+        remapper_step::ProcessStep,
+    },
+};
+use anyhow::Result;
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::Transaction,
+    traits::Processable,
+    types::transaction_context::{TransactionContext, TransactionMetadata},
+};
+use serde::Serialize;
+
+/// Rows the marketplace pipeline would write for a set of transactions
+#[derive(Debug, Default, Serialize)]
+pub struct DryRunOutput {
+    pub actions: Vec<Action>,
+    pub bids: Vec<Bid>,
+    pub listings: Vec<Listing>,
+    pub anomalies: Vec<Anomaly>,
+    pub dead_letters: Vec<DeadLetterEvent>,
+}
+
+/// Runs transactions through the remapping and the reduction of a marketplace config without
+/// touching the database. Orders created before the given transactions aren't known, so their
+/// cancels and fills are reported as anomalies.
+pub async fn dry_run(
+    config: &NFTMarketplaceConfig,
+    mut transactions: Vec<Transaction>,
+) -> Result<DryRunOutput> {
+    transactions.sort_by_key(|transaction| transaction.version);
+    let metadata = TransactionMetadata {
+        start_version: transactions.first().map_or(0, |txn| txn.version),
+        end_version: transactions.last().map_or(0, |txn| txn.version),
+        ..Default::default()
+    };
+
+    let mut process_step = ProcessStep::new(config.clone())?;
+    let mut reduction_step = NFTReductionStep::without_db(config.fee_schedule.clone());
+
+    let Some(remapped) = process_step
+        .process(TransactionContext {
+            data: transactions,
+            metadata,
+        })
+        .await?
+    else {
+        return Ok(DryRunOutput::default());
+    };
+    let Some(reduced) = reduction_step.process(remapped).await? else {
+        return Ok(DryRunOutput::default());
+    };
+
+    let ReducedBatch {
+        actions,
+        bids,
+        listings,
+        anomalies,
+        dead_letters,
+        ..
+    } = reduced.data;
+    let mut output = DryRunOutput {
+        actions,
+        bids,
+        listings,
+        anomalies,
+        dead_letters,
+    };
+    // The reduction keys its rows by hash maps
+    output.actions.sort_by_key(|action| action.tx_index);
+    output
+        .bids
+        .sort_by(|a, b| (&a.market_contract_id, &a.nonce).cmp(&(&b.market_contract_id, &b.nonce)));
+    output.listings.sort_by(|a, b| {
+        (&a.market_contract_id, &a.nft_id).cmp(&(&b.market_contract_id, &b.nft_id))
+    });
+    output.anomalies.sort_by_key(|anomaly| anomaly.tx_index);
+
+    Ok(output)
+}
@@ -1,4 +1,5 @@
-use jsonpath_rust::JsonPath;
+use jsonpath_rust::{JsonPath, JsonPathValue};
+use serde::{Deserialize, Serialize};
 use serde_json::Value as SerdeJsonValue;
 use std::{
     hash::{Hash, Hasher},
@@ -6,6 +7,8 @@ use std::{
 };
 
 pub mod db_writing_step;
+pub mod dry_run;
+pub mod open_orders;
 pub mod reduction_step;
 pub mod remapper_step;
 pub mod remappers;
@@ -18,8 +21,9 @@ pub fn extract_string(paths: &HashableJsonPath, from: &SerdeJsonValue) -> Option
         .and_then(|v| v.as_str().map(String::from))
 }
 
-/// A wrapper around JsonPath so that it can be hashed
-#[derive(Debug, Clone)]
+/// A wrapper around JsonPath so that it can be hashed, (de)serialized from its raw string
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct HashableJsonPath {
     json_path: JsonPath,
     /// The raw string representation of the JsonPath
@@ -35,15 +39,27 @@ impl HashableJsonPath {
         })
     }
 
-    /// Executes the JsonPath to extract the value from the provided serde_json::Value
+    /// Executes the JsonPath to extract the value from the provided serde_json::Value, failing
+    /// when the path matches nothing
     pub fn extract_from(&self, value: &SerdeJsonValue) -> anyhow::Result<SerdeJsonValue> {
-        Ok(self
-            .json_path
-            .find_slice(value)
-            .first()
-            .unwrap() // Unwrap is safe here because the fields are guaranteed by the move types; TODO: Perform checks here or check config
-            .clone()
-            .to_data())
+        match self.json_path.find_slice(value).into_iter().next() {
+            Some(JsonPathValue::NoValue) | None => anyhow::bail!("No value found at {}", self.raw),
+            Some(found) => Ok(found.to_data()),
+        }
+    }
+}
+
+impl TryFrom<String> for HashableJsonPath {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: String) -> anyhow::Result<Self> {
+        Self::new(&raw)
+    }
+}
+
+impl From<HashableJsonPath> for String {
+    fn from(path: HashableJsonPath) -> Self {
+        path.raw
     }
 }
 
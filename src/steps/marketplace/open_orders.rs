@@ -0,0 +1,436 @@
+use crate::models::{
+    db::{bid::Bid, listing::Listing},
+    order_status::{BidStatus, ListingStatus},
+};
+use std::collections::{BTreeSet, HashMap};
+
+/// (market_contract_id, nonce) of a bid or (market_contract_id, nft_id) of a listing
+pub type OrderKey = (String, String);
+
+/// (market_contract_id, match_key)
+pub type MatchKey = (String, String);
+
+/// Batches an order or match key is kept for after its last use. Longer than the batches
+/// queued between the reduction and the database writes, so an evicted order is only reloaded
+/// from the database once its last status is stored.
+const RETAINED_BATCHES: u64 = 50;
+
+pub trait OrderStatus: Copy {
+    fn is_open(&self) -> bool;
+
+    /// Whether the status is only set by the workers, behind the pipeline. The database is
+    /// then ahead of the cache.
+    fn is_set_by_worker(&self) -> bool;
+}
+
+impl OrderStatus for BidStatus {
+    fn is_open(&self) -> bool {
+        !self.is_terminal()
+    }
+
+    fn is_set_by_worker(&self) -> bool {
+        *self == BidStatus::Expired
+    }
+}
+
+impl OrderStatus for ListingStatus {
+    fn is_open(&self) -> bool {
+        !self.is_terminal()
+    }
+
+    /// Listings are keyed by nft, an expired or invalid row can belong to an earlier listing
+    /// of the nft that the pipeline already replaced, so the cached status is kept.
+    fn is_set_by_worker(&self) -> bool {
+        false
+    }
+}
+
+/// Last known state of an order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OrderState<S> {
+    /// `None` if the order was created before the indexed range
+    pub status: Option<S>,
+    /// Tokens left on the order, `None` if its amount is unknown
+    pub remaining: Option<i64>,
+}
+
+impl<S> Default for OrderState<S> {
+    fn default() -> Self {
+        Self {
+            status: None,
+            remaining: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CachedOrder<S> {
+    state: OrderState<S>,
+    /// Match key the order is resolved by while open
+    match_key: Option<String>,
+    /// Batch the order was last used in
+    batch: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+struct MatchKeyOrders {
+    /// Ids of the open orders with the match key
+    order_ids: BTreeSet<String>,
+    /// Whether the open orders stored in the database were loaded
+    loaded: bool,
+    /// Batch the match key was last used in
+    batch: u64,
+}
+
+/// Bids or listings seen by the marketplace pipeline.
+///
+/// Keeps the status and remaining token amount of the orders used in recent batches so
+/// transitions and partial fills can be validated across batches, and the open orders of every
+/// match key, ordered by id, so cancel and fill events of collection offers without an offer id
+/// can be correlated back to the offer they close. Orders are loaded from the database on first
+/// use and evicted once unused for `RETAINED_BATCHES` batches.
+#[derive(Clone, Debug)]
+pub struct OpenOrders<S> {
+    orders: HashMap<OrderKey, CachedOrder<S>>,
+    match_keys: HashMap<MatchKey, MatchKeyOrders>,
+    batch: u64,
+}
+
+impl<S> Default for OpenOrders<S> {
+    fn default() -> Self {
+        Self {
+            orders: HashMap::new(),
+            match_keys: HashMap::new(),
+            batch: 0,
+        }
+    }
+}
+
+impl<S: OrderStatus> OpenOrders<S> {
+    pub fn get(&self, key: &OrderKey) -> OrderState<S> {
+        self.orders
+            .get(key)
+            .map(|order| order.state)
+            .unwrap_or_default()
+    }
+
+    /// Tokens left on an open order after a fill of `amount`, or `None` if unknown.
+    pub fn remaining_after_fill(&self, key: &OrderKey, amount: i64) -> Option<i64> {
+        let state = self.get(key);
+        if state.status.map_or(false, |status| !status.is_open()) {
+            return None;
+        }
+        state.remaining.map(|remaining| (remaining - amount).max(0))
+    }
+
+    /// Stores the new state of an order. Open orders with a `match_key` can be resolved by it
+    /// until they are closed.
+    pub fn update(
+        &mut self,
+        key: OrderKey,
+        status: S,
+        remaining: Option<i64>,
+        match_key: Option<String>,
+    ) {
+        let batch = self.batch;
+        let order = self.touch_order(key.clone());
+        order.state = OrderState {
+            status: Some(status),
+            remaining,
+        };
+        if match_key.is_some() {
+            order.match_key = match_key;
+        }
+        let Some(match_key) = order.match_key.clone() else {
+            return;
+        };
+
+        let (contract, order_id) = key;
+        if status.is_open() {
+            let orders = self.match_keys.entry((contract, match_key)).or_default();
+            orders.order_ids.insert(order_id);
+            orders.batch = batch;
+        } else if let Some(orders) = self.match_keys.get_mut(&(contract, match_key)) {
+            orders.order_ids.remove(&order_id);
+        }
+    }
+
+    /// Returns the id of the oldest open order with this match key. Generated offer ids sort
+    /// by creation.
+    pub fn resolve(&mut self, match_key: &MatchKey) -> Option<String> {
+        let batch = self.batch;
+        let orders = self.match_keys.get_mut(match_key)?;
+        orders.batch = batch;
+        orders.order_ids.first().cloned()
+    }
+
+    /// Ids of the cached open orders with this match key.
+    pub fn open_order_ids(&self, match_key: &MatchKey) -> Vec<String> {
+        self.match_keys
+            .get(match_key)
+            .map(|orders| orders.order_ids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn is_match_key_loaded(&self, match_key: &MatchKey) -> bool {
+        self.match_keys
+            .get(match_key)
+            .map_or(false, |orders| orders.loaded)
+    }
+
+    /// Marks an order as looked up, so that an order missing from the database is only
+    /// queried once per batch.
+    pub fn mark_order_loaded(&mut self, key: OrderKey) {
+        self.touch_order(key);
+    }
+
+    pub fn mark_match_key_loaded(&mut self, match_key: MatchKey) {
+        let batch = self.batch;
+        let orders = self.match_keys.entry(match_key).or_default();
+        orders.loaded = true;
+        orders.batch = batch;
+    }
+
+    /// Evicts the orders and match keys that weren't used in the last `RETAINED_BATCHES`
+    /// batches, they are reloaded from the database on their next use. Called after every
+    /// batch.
+    pub fn end_batch(&mut self) {
+        self.batch += 1;
+        let Some(oldest) = self.batch.checked_sub(RETAINED_BATCHES) else {
+            return;
+        };
+
+        self.orders.retain(|_, order| order.batch >= oldest);
+        self.match_keys.retain(|_, orders| orders.batch >= oldest);
+    }
+
+    fn touch_order(&mut self, key: OrderKey) -> &mut CachedOrder<S> {
+        let batch = self.batch;
+        let order = self.orders.entry(key).or_insert_with(|| CachedOrder {
+            state: OrderState::default(),
+            match_key: None,
+            batch,
+        });
+        order.batch = batch;
+        order
+    }
+
+    /// Merges the stored state of an order. The pipeline is ahead of the database, so the
+    /// cached state wins, except over statuses set by the workers in the meantime.
+    fn load(
+        &mut self,
+        key: OrderKey,
+        status: Option<S>,
+        remaining: Option<i64>,
+        match_key: Option<String>,
+    ) {
+        let cached = self.get(&key);
+        match (cached.status, status) {
+            (None, Some(status)) => self.update(key, status, remaining, match_key),
+            (Some(cached_status), Some(status))
+                if cached_status.is_open() && status.is_set_by_worker() =>
+            {
+                self.update(key, status, remaining, match_key)
+            },
+            // Adds the order back to its match key if the match key was evicted
+            (Some(cached_status), _) if cached_status.is_open() => {
+                self.update(key, cached_status, cached.remaining, match_key)
+            },
+            _ => self.mark_order_loaded(key),
+        }
+    }
+}
+
+impl OpenOrders<BidStatus> {
+    /// Merges the bids stored in the `bids` table.
+    pub fn load_bids(&mut self, bids: Vec<Bid>) {
+        for bid in bids {
+            let (Some(contract), Some(nonce)) = (bid.market_contract_id, bid.nonce) else {
+                continue;
+            };
+            self.load(
+                (contract, nonce),
+                bid.status,
+                bid.remaining_count,
+                bid.match_key,
+            );
+        }
+    }
+}
+
+impl OpenOrders<ListingStatus> {
+    /// Merges the listings stored in the `listings` table.
+    pub fn load_listings(&mut self, listings: Vec<Listing>) {
+        for listing in listings {
+            let (Some(contract), Some(nft_id)) = (listing.market_contract_id, listing.nft_id)
+            else {
+                continue;
+            };
+            // Listings stored before the status column only know if they are listed
+            let status = listing.status.or_else(|| {
+                listing
+                    .listed
+                    .filter(|listed| *listed)
+                    .map(|_| ListingStatus::Listed)
+            });
+            self.load((contract, nft_id), status, listing.remaining_count, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(order_id: &str) -> OrderKey {
+        ("0xmarket".to_string(), order_id.to_string())
+    }
+
+    fn match_key() -> MatchKey {
+        ("0xmarket".to_string(), "0xkey".to_string())
+    }
+
+    #[test]
+    fn test_resolves_oldest_open_order() {
+        let mut orders = OpenOrders::default();
+        orders.update(
+            key("1"),
+            BidStatus::Active,
+            Some(1),
+            Some("0xkey".to_string()),
+        );
+        orders.update(
+            key("2"),
+            BidStatus::Active,
+            Some(1),
+            Some("0xkey".to_string()),
+        );
+
+        assert_eq!(orders.resolve(&match_key()), Some("1".to_string()));
+        orders.update(key("1"), BidStatus::Cancelled, None, None);
+        assert_eq!(orders.resolve(&match_key()), Some("2".to_string()));
+        orders.update(key("2"), BidStatus::Matched, Some(0), None);
+        assert_eq!(orders.resolve(&match_key()), None);
+    }
+
+    #[test]
+    fn test_remaining_after_fill() {
+        let mut orders = OpenOrders::default();
+        orders.update(key("1"), BidStatus::Active, Some(3), None);
+        assert_eq!(orders.remaining_after_fill(&key("1"), 1), Some(2));
+        assert_eq!(orders.remaining_after_fill(&key("1"), 5), Some(0));
+
+        // Orders without a known amount or already closed are never partially filled
+        orders.update(key("2"), BidStatus::Active, None, None);
+        assert_eq!(orders.remaining_after_fill(&key("2"), 1), None);
+        orders.update(key("1"), BidStatus::Cancelled, Some(3), None);
+        assert_eq!(orders.remaining_after_fill(&key("1"), 1), None);
+    }
+
+    #[test]
+    fn test_load_bids() {
+        let mut orders = OpenOrders::default();
+        orders.load_bids(vec![
+            Bid {
+                market_contract_id: Some("0xmarket".to_string()),
+                nonce: Some("1".to_string()),
+                status: Some(BidStatus::Cancelled),
+                match_key: Some("0xkey".to_string()),
+                ..Default::default()
+            },
+            Bid {
+                market_contract_id: Some("0xmarket".to_string()),
+                nonce: Some("2".to_string()),
+                status: Some(BidStatus::Active),
+                match_key: Some("0xkey".to_string()),
+                remaining_count: Some(2),
+                ..Default::default()
+            },
+        ]);
+
+        assert_eq!(orders.get(&key("1")).status, Some(BidStatus::Cancelled));
+        assert_eq!(orders.resolve(&match_key()), Some("2".to_string()));
+
+        // Newer offers opened in this run come after the loaded ones
+        orders.update(
+            key("3"),
+            BidStatus::Active,
+            Some(1),
+            Some("0xkey".to_string()),
+        );
+        assert_eq!(orders.resolve(&match_key()), Some("2".to_string()));
+        assert_eq!(orders.remaining_after_fill(&key("2"), 1), Some(1));
+    }
+
+    #[test]
+    fn test_load_listings() {
+        let mut orders = OpenOrders::default();
+        orders.load_listings(vec![Listing {
+            market_contract_id: Some("0xmarket".to_string()),
+            nft_id: Some("0xnft".to_string()),
+            listed: Some(true),
+            remaining_count: Some(5),
+            ..Default::default()
+        }]);
+
+        assert_eq!(
+            orders.get(&key("0xnft")).status,
+            Some(ListingStatus::Listed)
+        );
+        assert_eq!(orders.remaining_after_fill(&key("0xnft"), 2), Some(3));
+    }
+
+    #[test]
+    fn test_evicts_unused_orders() {
+        let mut orders = OpenOrders::default();
+        orders.update(
+            key("1"),
+            BidStatus::Active,
+            Some(1),
+            Some("0xkey".to_string()),
+        );
+        orders.update(key("2"), BidStatus::Active, Some(1), None);
+        orders.mark_match_key_loaded(match_key());
+
+        for _ in 0..RETAINED_BATCHES {
+            orders.end_batch();
+            orders.update(key("2"), BidStatus::Active, Some(1), None);
+        }
+        assert_eq!(orders.get(&key("1")).status, Some(BidStatus::Active));
+        assert!(orders.is_match_key_loaded(&match_key()));
+
+        orders.end_batch();
+        assert_eq!(orders.get(&key("1")).status, None);
+        assert!(!orders.is_match_key_loaded(&match_key()));
+        assert_eq!(orders.resolve(&match_key()), None);
+        assert_eq!(orders.get(&key("2")).status, Some(BidStatus::Active));
+    }
+
+    #[test]
+    fn test_load_expired_bid() {
+        let mut orders = OpenOrders::default();
+        orders.update(
+            key("1"),
+            BidStatus::Active,
+            Some(1),
+            Some("0xkey".to_string()),
+        );
+        orders.update(key("2"), BidStatus::Matched, Some(0), None);
+
+        // Stored rows lag behind the pipeline, except for bids the expiry worker closed
+        let bid = |nonce: &str, status| Bid {
+            market_contract_id: Some("0xmarket".to_string()),
+            nonce: Some(nonce.to_string()),
+            status: Some(status),
+            ..Default::default()
+        };
+        orders.load_bids(vec![
+            bid("1", BidStatus::Expired),
+            bid("2", BidStatus::Active),
+        ]);
+
+        assert_eq!(orders.get(&key("1")).status, Some(BidStatus::Expired));
+        assert_eq!(orders.resolve(&match_key()), None);
+        assert_eq!(orders.get(&key("2")).status, Some(BidStatus::Matched));
+    }
+}
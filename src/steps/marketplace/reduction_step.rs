@@ -1,9 +1,26 @@
-use crate::models::{
-    db::{action::Action, bid::Bid, listing::Listing},
-    marketplace::{BidModel, ListingModel, NftMarketplaceActivity},
+use super::open_orders::OpenOrders;
+use crate::{
+    config::marketplace_config::{FeeSchedule, MarketplaceEventType},
+    models::{
+        db::{
+            action::Action,
+            activity_error::ActivityError,
+            anomaly::{Anomaly, BID_ORDER_TYPE, LISTING_ORDER_TYPE},
+            bid::Bid,
+            commission::Commission,
+            dead_letter_event::DeadLetterEvent,
+            fill::{BidFill, ListingFill},
+            listing::Listing,
+            sale_fee::SaleFee,
+        },
+        marketplace::{BidModel, ListingModel, NftMarketplaceActivity},
+        order_status::{BidStatus, ListingStatus},
+    },
+    postgres::postgres_utils::ArcDbPool,
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
+    postgres::utils::database::DbPoolConnection,
     traits::{AsyncRunType, AsyncStep, NamedStep, Processable},
     types::transaction_context::TransactionContext,
     utils::errors::ProcessorError,
@@ -14,11 +31,34 @@ pub type BidIdType = (Option<String>, Option<String>);
 
 pub type ListingIdType = (Option<String>, Option<String>);
 
+/// Rows reduced from a batch of activities, written by the DBWritingStep
+#[derive(Debug, Default)]
+pub struct ReducedBatch {
+    pub actions: Vec<Action>,
+    pub bids: Vec<Bid>,
+    pub listings: Vec<Listing>,
+    pub bid_fills: Vec<BidFill>,
+    pub listing_fills: Vec<ListingFill>,
+    pub anomalies: Vec<Anomaly>,
+    pub sale_fees: Vec<SaleFee>,
+    pub activity_errors: Vec<ActivityError>,
+    pub dead_letters: Vec<DeadLetterEvent>,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct NFTAccumulator {
     actions: HashMap<i64, Action>,
     bids: HashMap<BidIdType, Bid>,
     listings: HashMap<ListingIdType, Listing>,
+    bid_fills: HashMap<i64, BidFill>,
+    listing_fills: HashMap<i64, ListingFill>,
+    anomalies: HashMap<i64, Anomaly>,
+    sale_fees: HashMap<i64, SaleFee>,
+    activity_errors: HashMap<(i64, String), ActivityError>,
+    dead_letters: Vec<DeadLetterEvent>,
+    /// Not drained, the state of recently used bids and listings is tracked across batches
+    open_bids: OpenOrders<BidStatus>,
+    open_listings: OpenOrders<ListingStatus>,
 }
 
 impl NFTAccumulator {
@@ -29,125 +69,555 @@ impl NFTAccumulator {
         self.actions.insert(key, action);
     }
 
-    pub fn fold_bidding(&mut self, activity: &NftMarketplaceActivity) {
-        if activity.is_valid_bid() {
-            let bid: Bid = activity.to_owned().into();
-            let key = (bid.market_contract_id.clone(), bid.nonce.clone());
-            self.bids
-                .entry(key)
-                .and_modify(|existing: &mut Bid| {
-                    let is_active = bid
+    /// Sets the offer id of collection offer events that don't carry one to the oldest open
+    /// collection offer with their match key, or else to the open offer stored under the legacy
+    /// id of the buyer and collection.
+    pub fn resolve_collection_offer(&mut self, activity: &mut NftMarketplaceActivity) {
+        let is_offer_close = matches!(
+            activity.standard_event_type,
+            MarketplaceEventType::UnlistBid
+                | MarketplaceEventType::AcceptBid
+                | MarketplaceEventType::CancelCollectionBid
+                | MarketplaceEventType::AcceptCollectionBid
+        );
+        if !is_offer_close || activity.offer_id.is_some() {
+            return;
+        }
+
+        if let (Some(contract), Some(match_key)) = (
+            activity.contract_address.clone(),
+            activity.offer_match_key.clone(),
+        ) {
+            activity.offer_id = self.open_bids.resolve(&(contract.clone(), match_key));
+            if activity.offer_id.is_none() {
+                activity.offer_id = activity.get_legacy_collection_offer_id().filter(|nonce| {
+                    self.open_bids
+                        .get(&(contract, nonce.clone()))
                         .status
-                        .clone()
-                        .map_or(false, |status| status.as_str() == "active");
+                        .map_or(false, |status| status == BidStatus::Active)
+                });
+            }
+        }
+    }
 
-                    if let Some(tx_id) = bid.created_tx_id.clone() {
-                        existing.created_tx_id = Some(tx_id);
-                    }
+    pub fn fold_bidding(&mut self, activity: &NftMarketplaceActivity) {
+        if activity.get_bid_status().is_none() {
+            return;
+        }
+        if !activity.is_valid_bid() {
+            if activity.contract_address.is_some() {
+                self.record_anomaly(Anomaly::new(
+                    activity,
+                    BID_ORDER_TYPE,
+                    None,
+                    None,
+                    "no bid matches the event".to_string(),
+                ));
+            }
+            return;
+        }
 
-                    if let Some(tx_id) = bid.accepted_tx_id.clone() {
-                        existing.accepted_tx_id = Some(tx_id);
-                        if is_active {
-                            existing.status = Some("matched".to_string());
-                        }
-                    }
+        let bid: Bid = activity.to_owned().into();
+        let key = (bid.market_contract_id.clone(), bid.nonce.clone());
+        let order_key = (
+            bid.market_contract_id.clone().unwrap_or_default(),
+            bid.nonce.clone().unwrap_or_default(),
+        );
 
-                    if let Some(tx_id) = bid.canceled_tx_id.clone() {
-                        existing.canceled_tx_id = Some(tx_id);
-                        if is_active {
-                            existing.status = Some("cancelled".to_string());
-                        };
-                    }
+        let from = self.open_bids.get(&order_key);
+        let is_fill = matches!(
+            activity.standard_event_type,
+            MarketplaceEventType::AcceptBid | MarketplaceEventType::AcceptCollectionBid
+        );
+        let remaining = if is_fill {
+            self.open_bids
+                .remaining_after_fill(&order_key, activity.get_filled_amount())
+        } else {
+            bid.remaining_count.or(from.remaining)
+        };
 
-                    if let Some(receiver) = bid.receiver.clone() {
-                        existing.receiver = Some(receiver);
-                    }
-                })
-                .or_insert(bid);
+        let status =
+            match BidStatus::transition(from.status, &activity.standard_event_type, remaining) {
+                Ok(status) => status,
+                Err(e) => {
+                    self.record_anomaly(Anomaly::new(
+                        activity,
+                        BID_ORDER_TYPE,
+                        bid.nonce.clone(),
+                        from.status.map(|status| status.to_string()),
+                        e.reason,
+                    ));
+                    return;
+                },
+            };
+
+        self.open_bids.update(
+            order_key,
+            status,
+            remaining,
+            activity.offer_match_key.clone(),
+        );
+        if is_fill {
+            if let Some(fill) = BidFill::new(activity, remaining) {
+                self.bid_fills.insert(fill.tx_index, fill);
+            }
         }
+
+        self.bids
+            .entry(key)
+            .and_modify(|existing: &mut Bid| {
+                if let Some(tx_id) = bid.created_tx_id.clone() {
+                    existing.created_tx_id = Some(tx_id);
+                }
+
+                if let Some(tx_id) = bid.accepted_tx_id.clone() {
+                    existing.accepted_tx_id = Some(tx_id);
+                }
+
+                if let Some(tx_id) = bid.canceled_tx_id.clone() {
+                    existing.canceled_tx_id = Some(tx_id);
+                }
+
+                if let Some(receiver) = bid.receiver.clone() {
+                    existing.receiver = Some(receiver);
+                }
+
+                existing.status = Some(status);
+                existing.remaining_count = remaining.or(existing.remaining_count);
+            })
+            .or_insert(Bid {
+                status: Some(status),
+                remaining_count: remaining,
+                ..bid
+            });
     }
 
     pub fn fold_listing(&mut self, activity: &NftMarketplaceActivity) {
-        if activity.is_valid_listing() {
-            let listing: Listing = activity.to_owned().into();
-            let key = (listing.market_contract_id.clone(), listing.nft_id.clone());
-            self.listings
-                .entry(key)
-                .and_modify(|existing: &mut Listing| {
-                    let is_listed = listing.listed.unwrap_or(false);
-                    let is_latest = listing
-                        .block_time
-                        .zip(existing.block_time)
-                        .map_or(false, |(current, existing)| current.gt(&existing));
-
-                    if is_latest {
-                        existing.block_time = listing.block_time.clone();
-                        existing.listed = listing.listed.clone();
-                        existing.block_height = listing.block_height.clone();
-                        existing.nft_id = listing.nft_id.clone();
-                        existing.nonce = listing.nonce.clone();
-                        existing.price = listing.price.clone();
-                        existing.price_str = listing.price_str.clone();
-                        existing.seller = listing.seller.clone();
-                        existing.tx_index = listing.tx_index.clone();
-
-                        if !is_listed {
-                            existing.nonce = None;
-                            existing.price = None;
-                            existing.price_str = None;
-                            existing.seller = None;
-                            existing.tx_index = None;
-                        }
-                    }
-                })
-                .or_insert(listing);
+        if activity.get_listing_status().is_none() {
+            return;
         }
+        if !activity.is_valid_listing() {
+            if activity.contract_address.is_some() {
+                self.record_anomaly(Anomaly::new(
+                    activity,
+                    LISTING_ORDER_TYPE,
+                    None,
+                    None,
+                    "no listing matches the event".to_string(),
+                ));
+            }
+            return;
+        }
+
+        let mut listing: Listing = activity.to_owned().into();
+        let key = (listing.market_contract_id.clone(), listing.nft_id.clone());
+        let order_key = (
+            listing.market_contract_id.clone().unwrap_or_default(),
+            listing.nft_id.clone().unwrap_or_default(),
+        );
+
+        let from = self.open_listings.get(&order_key);
+        let is_fill = activity.standard_event_type == MarketplaceEventType::Buy;
+        let remaining = if is_fill {
+            self.open_listings
+                .remaining_after_fill(&order_key, activity.get_filled_amount())
+        } else {
+            listing.remaining_count
+        };
+
+        let status = match ListingStatus::transition(
+            from.status,
+            &activity.standard_event_type,
+            remaining,
+        ) {
+            Ok(status) => status,
+            Err(e) => {
+                self.record_anomaly(Anomaly::new(
+                    activity,
+                    LISTING_ORDER_TYPE,
+                    listing.nft_id.clone(),
+                    from.status.map(|status| status.to_string()),
+                    e.reason,
+                ));
+                return;
+            },
+        };
+
+        self.open_listings
+            .update(order_key, status, remaining, None);
+        if is_fill {
+            if let Some(fill) = ListingFill::new(activity, remaining) {
+                self.listing_fills.insert(fill.tx_index, fill);
+            }
+        }
+
+        // A buy that leaves tokens keeps the listing up with the tokens that are left
+        let is_partial_fill = is_fill && status == ListingStatus::Listed;
+        listing.status = Some(status);
+        listing.listed = Some(status == ListingStatus::Listed);
+        listing.remaining_count = remaining.filter(|_| status == ListingStatus::Listed);
+
+        self.listings
+            .entry(key)
+            .and_modify(|existing: &mut Listing| {
+                existing.block_time = listing.block_time;
+                existing.block_height = listing.block_height;
+                existing.listed = listing.listed;
+                existing.status = listing.status;
+                existing.remaining_count = listing.remaining_count;
+
+                if is_partial_fill {
+                    return;
+                }
+
+                if status == ListingStatus::Listed {
+                    existing.nft_id = listing.nft_id.clone();
+                    existing.nonce = listing.nonce.clone();
+                    existing.price = listing.price.clone();
+                    existing.price_str = listing.price_str.clone();
+                    existing.seller = listing.seller.clone();
+                    existing.tx_index = listing.tx_index;
+                    existing.expires_at = listing.expires_at;
+                    existing.payment_token = listing.payment_token.clone();
+                } else {
+                    existing.nonce = None;
+                    existing.price = None;
+                    existing.price_str = None;
+                    existing.seller = None;
+                    existing.tx_index = None;
+                    existing.expires_at = None;
+                }
+            })
+            .or_insert(listing);
     }
 
-    pub fn drain(&mut self) -> (Vec<Action>, Vec<Bid>, Vec<Listing>) {
-        (
-            self.actions.drain().map(|(_, v)| v).collect(),
-            self.bids.drain().map(|(_, v)| v).collect(),
-            self.listings.drain().map(|(_, v)| v).collect(),
-        )
+    pub fn fold_sale_fee(&mut self, sale_fee: SaleFee) {
+        self.sale_fees.insert(sale_fee.tx_index, sale_fee);
+    }
+
+    /// Dead-lettered events are only passed through to be stored.
+    pub fn fold_dead_letters(&mut self, dead_letters: Vec<DeadLetterEvent>) {
+        self.dead_letters.extend(dead_letters);
+    }
+
+    pub fn fold_field_errors(&mut self, activity: &NftMarketplaceActivity) {
+        for field_error in &activity.field_errors {
+            tracing::warn!(
+                "Failed to parse {} {:?} of event {} at tx_index {}: {}",
+                field_error.field,
+                field_error.value,
+                activity.raw_event_type,
+                activity.get_tx_index(),
+                field_error.error
+            );
+            let error = ActivityError::new(activity, field_error);
+            self.activity_errors
+                .insert((error.tx_index, error.field.clone()), error);
+        }
+    }
+
+    fn record_anomaly(&mut self, anomaly: Anomaly) {
+        tracing::debug!(
+            "Skipping {} event {} at tx_index {}: {}",
+            anomaly.order_type,
+            anomaly.event_type,
+            anomaly.tx_index,
+            anomaly.reason
+        );
+        self.anomalies.insert(anomaly.tx_index, anomaly);
+    }
+
+    pub fn drain(&mut self) -> ReducedBatch {
+        ReducedBatch {
+            actions: self.actions.drain().map(|(_, v)| v).collect(),
+            bids: self.bids.drain().map(|(_, v)| v).collect(),
+            listings: self.listings.drain().map(|(_, v)| v).collect(),
+            bid_fills: self.bid_fills.drain().map(|(_, v)| v).collect(),
+            listing_fills: self.listing_fills.drain().map(|(_, v)| v).collect(),
+            anomalies: self.anomalies.drain().map(|(_, v)| v).collect(),
+            sale_fees: self.sale_fees.drain().map(|(_, v)| v).collect(),
+            activity_errors: self.activity_errors.drain().map(|(_, v)| v).collect(),
+            dead_letters: std::mem::take(&mut self.dead_letters),
+        }
+    }
+
+    /// Evicts the bids and listings that weren't used recently.
+    pub fn end_batch(&mut self) {
+        self.open_bids.end_batch();
+        self.open_listings.end_batch();
     }
 }
 
-#[derive(Clone, Debug, Default)]
 pub struct NFTReductionStep
 where
     Self: Sized + Send + 'static,
 {
     accumulator: NFTAccumulator,
+    /// Unset when dry-running, orders and royalties then only come from the processed batches
+    db_pool: Option<ArcDbPool>,
+    fee_schedule: Option<FeeSchedule>,
+    /// Royalty of the nfts sold in the current batch
+    commissions: HashMap<String, Commission>,
+    /// Royalty of the collections of the nfts sold in the current batch
+    collection_commissions: HashMap<String, Commission>,
 }
 
 impl NFTReductionStep {
-    pub fn new() -> Self {
+    pub fn new(db_pool: ArcDbPool, fee_schedule: Option<FeeSchedule>) -> Self {
         Self {
             accumulator: NFTAccumulator::default(),
+            db_pool: Some(db_pool),
+            fee_schedule,
+            commissions: HashMap::new(),
+            collection_commissions: HashMap::new(),
         }
     }
+
+    /// Creates a reduction step that doesn't read the database, for dry runs.
+    pub fn without_db(fee_schedule: Option<FeeSchedule>) -> Self {
+        Self {
+            accumulator: NFTAccumulator::default(),
+            db_pool: None,
+            fee_schedule,
+            commissions: HashMap::new(),
+            collection_commissions: HashMap::new(),
+        }
+    }
+
+    /// Breaks down the price of a sale into royalty, marketplace fee and seller proceeds. The
+    /// royalty of the nft falls back to the royalty of its collection.
+    fn get_sale_fee(&self, activity: &NftMarketplaceActivity) -> Option<SaleFee> {
+        let commission = activity.token_addr.as_ref().and_then(|nft_id| {
+            self.commissions.get(nft_id).or_else(|| {
+                activity
+                    .collection_addr
+                    .as_ref()
+                    .and_then(|collection_id| self.collection_commissions.get(collection_id))
+            })
+        });
+
+        SaleFee::new(activity, commission, self.fee_schedule.as_ref())
+    }
+
+    /// Loads the royalties of the nfts sold in a batch and of their collections from the
+    /// database, in one query.
+    async fn load_commissions(
+        &mut self,
+        activities_by_txn: &[Vec<NftMarketplaceActivity>],
+    ) -> Result<(), ProcessorError> {
+        let mut nft_ids = vec![];
+        let mut collection_ids = vec![];
+        for activity in activities_by_txn.iter().flatten() {
+            if !activity.is_sale() {
+                continue;
+            }
+            if let Some(nft_id) = activity.token_addr.clone() {
+                nft_ids.push(nft_id);
+                collection_ids.extend(activity.collection_addr.clone());
+            }
+        }
+        if nft_ids.is_empty() {
+            return Ok(());
+        }
+
+        let Some(mut conn) = get_conn(&self.db_pool).await? else {
+            return Ok(());
+        };
+        nft_ids.sort();
+        nft_ids.dedup();
+        collection_ids.sort();
+        collection_ids.dedup();
+        let commissions = Commission::get_by_nfts(&mut conn, &nft_ids, &collection_ids)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to query commissions. {e:?}"),
+                query: None,
+            })?;
+
+        for commission in commissions {
+            match (commission.nft_id.clone(), commission.collection_id.clone()) {
+                (Some(nft_id), _) => {
+                    self.commissions.entry(nft_id).or_insert(commission);
+                },
+                (None, Some(collection_id)) => {
+                    self.collection_commissions
+                        .entry(collection_id)
+                        .or_insert(commission);
+                },
+                (None, None) => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the bids and listings a batch refers to from the database, with the open
+    /// collection offers of the match keys of events that don't carry an offer id. One query
+    /// per marketplace and kind of order. Cached orders are read again, the expiry worker may
+    /// have closed them since.
+    async fn load_open_orders(
+        &mut self,
+        activities_by_txn: &[Vec<NftMarketplaceActivity>],
+    ) -> Result<(), ProcessorError> {
+        let mut nonces: HashMap<String, Vec<String>> = HashMap::new();
+        let mut match_keys: HashMap<String, Vec<String>> = HashMap::new();
+        let mut nft_ids: HashMap<String, Vec<String>> = HashMap::new();
+        for activity in activities_by_txn.iter().flatten() {
+            let Some(contract) = activity.contract_address.clone() else {
+                continue;
+            };
+
+            match activity.standard_event_type {
+                MarketplaceEventType::UnlistBid
+                | MarketplaceEventType::AcceptBid
+                | MarketplaceEventType::CancelCollectionBid
+                | MarketplaceEventType::AcceptCollectionBid => {
+                    if let Some(nonce) = activity.offer_id.clone() {
+                        nonces.entry(contract).or_default().push(nonce);
+                    } else if let Some(match_key) = activity.offer_match_key.clone() {
+                        if let Some(nonce) = activity.get_legacy_collection_offer_id() {
+                            nonces.entry(contract.clone()).or_default().push(nonce);
+                        }
+                        match_keys.entry(contract).or_default().push(match_key);
+                    }
+                },
+                MarketplaceEventType::Unlist | MarketplaceEventType::Buy => {
+                    if let Some(nft_id) = activity.token_addr.clone() {
+                        nft_ids.entry(contract).or_default().push(nft_id);
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let Some(mut conn) = get_conn(&self.db_pool).await? else {
+            return Ok(());
+        };
+        let open_bids = &mut self.accumulator.open_bids;
+        let open_listings = &mut self.accumulator.open_listings;
+
+        for (contract, mut match_keys) in match_keys {
+            match_keys.sort();
+            match_keys.dedup();
+
+            let mut unloaded = vec![];
+            for match_key in match_keys {
+                let match_key = (contract.clone(), match_key);
+                nonces
+                    .entry(contract.clone())
+                    .or_default()
+                    .extend(open_bids.open_order_ids(&match_key));
+                if !open_bids.is_match_key_loaded(&match_key) {
+                    unloaded.push(match_key.1);
+                }
+            }
+            if unloaded.is_empty() {
+                continue;
+            }
+
+            let bids = Bid::get_open_collection_offers(&mut conn, &contract, &unloaded)
+                .await
+                .map_err(|e| ProcessorError::DBStoreError {
+                    message: format!("Failed to query collection offers. {e:?}"),
+                    query: None,
+                })?;
+            open_bids.load_bids(bids);
+            for match_key in unloaded {
+                open_bids.mark_match_key_loaded((contract.clone(), match_key));
+            }
+        }
+
+        for (contract, mut nonces) in nonces {
+            nonces.sort();
+            nonces.dedup();
+            let bids = Bid::get_by_nonces(&mut conn, &contract, &nonces)
+                .await
+                .map_err(|e| ProcessorError::DBStoreError {
+                    message: format!("Failed to query bids. {e:?}"),
+                    query: None,
+                })?;
+            open_bids.load_bids(bids);
+            for nonce in nonces {
+                open_bids.mark_order_loaded((contract.clone(), nonce));
+            }
+        }
+
+        for (contract, mut nft_ids) in nft_ids {
+            nft_ids.sort();
+            nft_ids.dedup();
+            let listings = Listing::get_by_nft_ids(&mut conn, &contract, &nft_ids)
+                .await
+                .map_err(|e| ProcessorError::DBStoreError {
+                    message: format!("Failed to query listings. {e:?}"),
+                    query: None,
+                })?;
+            open_listings.load_listings(listings);
+            for nft_id in nft_ids {
+                open_listings.mark_order_loaded((contract.clone(), nft_id));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `None` without a database.
+async fn get_conn(
+    db_pool: &Option<ArcDbPool>,
+) -> Result<Option<DbPoolConnection<'_>>, ProcessorError> {
+    let Some(db_pool) = db_pool else {
+        return Ok(None);
+    };
+
+    db_pool
+        .get()
+        .await
+        .map(Some)
+        .map_err(|e| ProcessorError::DBStoreError {
+            message: format!("Failed to get database connection. {e:?}"),
+            query: None,
+        })
 }
 
 #[async_trait::async_trait]
 impl Processable for NFTReductionStep {
-    type Input = Vec<Vec<NftMarketplaceActivity>>;
-    type Output = (Vec<Action>, Vec<Bid>, Vec<Listing>);
+    type Input = (Vec<Vec<NftMarketplaceActivity>>, Vec<DeadLetterEvent>);
+    type Output = ReducedBatch;
     type RunType = AsyncRunType;
 
     async fn process(
         &mut self,
         input: TransactionContext<Self::Input>,
     ) -> Result<Option<TransactionContext<Self::Output>>, ProcessorError> {
-        for activities in input.data.iter() {
-            for activity in activities {
+        let (mut activities_by_txn, dead_letters) = input.data;
+        self.load_open_orders(&activities_by_txn).await?;
+        self.load_commissions(&activities_by_txn).await?;
+        for activities in activities_by_txn.iter_mut() {
+            for activity in activities.iter_mut() {
+                self.accumulator.resolve_collection_offer(activity);
+
                 self.accumulator.fold_actions(activity);
                 self.accumulator.fold_bidding(activity);
                 self.accumulator.fold_listing(activity);
+                self.accumulator.fold_field_errors(activity);
+
+                if activity.is_sale() {
+                    if let Some(sale_fee) = self.get_sale_fee(activity) {
+                        self.accumulator.fold_sale_fee(sale_fee);
+                    }
+                }
             }
         }
 
+        // Royalties set by the token pipeline in the meantime are picked up by the next batch
+        self.commissions.clear();
+        self.collection_commissions.clear();
+        self.accumulator.fold_dead_letters(dead_letters);
         let reduced_data = self.accumulator.drain();
+        // Without a database the cached orders are all there is to validate events against
+        if self.db_pool.is_some() {
+            self.accumulator.end_batch();
+        }
 
         Ok(Some(TransactionContext {
             data: reduced_data,
@@ -1,7 +1,9 @@
 use super::remappers::resource_remapper::ResourceMapper;
 use crate::{
-    config::marketplace_config::NFTMarketplaceConfig, models::marketplace::NftMarketplaceActivity,
-    steps::marketplace::remappers::event_remapper::EventRemapper,
+    config::marketplace_config::NFTMarketplaceConfig,
+    models::{db::dead_letter_event::DeadLetterEvent, marketplace::NftMarketplaceActivity},
+    steps::marketplace::remappers::event_remapper::{EventRemapper, RemappedEvent},
+    utils::payment_utils::get_deposits_by_wallet,
 };
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
@@ -18,7 +20,7 @@ where
     Self: Sized + Send + 'static,
 {
     event_remapper: Arc<EventRemapper>,
-    _resource_remapper: Arc<ResourceMapper>,
+    resource_remapper: Arc<ResourceMapper>,
 }
 
 impl ProcessStep {
@@ -27,37 +29,99 @@ impl ProcessStep {
         let resource_remapper: Arc<ResourceMapper> = ResourceMapper::new(&config)?;
         Ok(Self {
             event_remapper,
-            _resource_remapper: resource_remapper,
+            resource_remapper,
         })
     }
+
+    /// Remaps the events of a transaction into activities, filling the fields the events don't
+    /// carry from its write set resources and its coin deposits.
+    pub fn remap_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<(Vec<NftMarketplaceActivity>, Vec<DeadLetterEvent>)> {
+        let (mut activities, mut dead_letters) =
+            self.event_remapper.remap_events(transaction.clone())?;
+        for dead_letter in dead_letters.iter_mut() {
+            dead_letter.set_transaction(transaction);
+        }
+
+        // Fill the fields that events don't carry from the write set resources of the same
+        // transaction
+        if !activities.is_empty() {
+            let resource_updates = self
+                .resource_remapper
+                .remap_resources(transaction.clone())?;
+            if !resource_updates.is_empty() {
+                for activity in activities.iter_mut() {
+                    activity.merge_resource_fields(&resource_updates);
+                }
+            }
+        }
+
+        // The deposits of a transaction with several sales can't be split between them
+        let mut sales = activities.iter_mut().filter(|activity| activity.is_sale());
+        if let (Some(sale), None) = (sales.next(), sales.next()) {
+            sale.payments = get_deposits_by_wallet(transaction);
+        }
+
+        Ok((activities, dead_letters))
+    }
+
+    /// Remaps a dead-lettered event along with the other events of its transaction, so it gets
+    /// the same write set resources and coin deposits as in `remap_transaction`. Events
+    /// dead-lettered without their transaction are remapped from the event alone.
+    pub fn remap_dead_letter(
+        &self,
+        dead_letter: &DeadLetterEvent,
+    ) -> Result<Option<RemappedEvent>> {
+        let Some(transaction) = dead_letter.get_transaction()? else {
+            let event = dead_letter.get_event()?;
+            return self
+                .event_remapper
+                .remap_event(&dead_letter.tx_id, &event, &[]);
+        };
+
+        let (activities, dead_letters) = self.remap_transaction(&transaction)?;
+        let is_dead_letter = |txn_version: i64, event_index: i64| {
+            txn_version == dead_letter.txn_version && event_index == dead_letter.event_index
+        };
+        if let Some(activity) = activities
+            .into_iter()
+            .find(|activity| is_dead_letter(activity.txn_version, activity.index))
+        {
+            return Ok(Some(RemappedEvent::Activity(activity)));
+        }
+
+        Ok(dead_letters
+            .into_iter()
+            .find(|remapped| is_dead_letter(remapped.txn_version, remapped.event_index))
+            .map(RemappedEvent::DeadLetter))
+    }
 }
 
 #[async_trait::async_trait]
 impl Processable for ProcessStep {
     type Input = Vec<Transaction>;
-    type Output = Vec<Vec<NftMarketplaceActivity>>;
+    type Output = (Vec<Vec<NftMarketplaceActivity>>, Vec<DeadLetterEvent>);
     type RunType = AsyncRunType;
 
     async fn process(
         &mut self,
         transactions: TransactionContext<Vec<Transaction>>,
     ) -> Result<Option<TransactionContext<Self::Output>>, ProcessorError> {
-        let activities = transactions
+        let (activities, dead_letters): (Vec<_>, Vec<_>) = transactions
             .data
             .par_iter()
-            .map(|transaction| {
-                let event_remapper = self.event_remapper.clone();
-                let activities = event_remapper.remap_events(transaction.clone())?;
-
-                Ok(activities)
-            })
+            .map(|transaction| self.remap_transaction(transaction))
             .collect::<anyhow::Result<Vec<_>>>()
             .map_err(|e| ProcessorError::ProcessError {
                 message: format!("{e:#}"),
-            })?;
+            })?
+            .into_iter()
+            .unzip();
 
         Ok(Some(TransactionContext {
-            data: activities,
+            data: (activities, dead_letters.into_iter().flatten().collect()),
             metadata: transactions.metadata,
         }))
     }
@@ -0,0 +1,101 @@
+use crate::{
+    config::marketplace_config::{EventModelMapping, EventPredicate, MarketplaceEventType},
+    steps::marketplace::remappers::value_transform::to_column_value,
+};
+use serde_json::Value as SerdeJsonValue;
+
+impl EventModelMapping {
+    /// Returns the standardized event type of an event from its data, or `None` when the event
+    /// matches none of the rules.
+    pub fn resolve(&self, data: &SerdeJsonValue) -> Option<MarketplaceEventType> {
+        match self {
+            EventModelMapping::Fixed(event_type) => Some(event_type.clone()),
+            EventModelMapping::Rules(rules) => rules
+                .iter()
+                .find(|rule| rule.when.iter().all(|predicate| predicate.matches(data)))
+                .map(|rule| rule.event_type.clone()),
+        }
+    }
+}
+
+impl EventPredicate {
+    pub fn matches(&self, data: &SerdeJsonValue) -> bool {
+        let value = self.path.extract_from(data).ok();
+        if let Some(exists) = self.exists {
+            if value.is_some() != exists {
+                return false;
+            }
+        }
+        let Some(value) = value else {
+            // Only `exists: false` matches a missing value
+            return self.exists == Some(false);
+        };
+
+        self.equals
+            .as_ref()
+            .map_or(true, |expected| loosely_equals(&value, expected))
+            && self
+                .not_equals
+                .as_ref()
+                .map_or(true, |unexpected| !loosely_equals(&value, unexpected))
+            && self.one_of.as_ref().map_or(true, |expected| {
+                expected
+                    .iter()
+                    .any(|expected| loosely_equals(&value, expected))
+            })
+    }
+}
+
+fn loosely_equals(value: &SerdeJsonValue, expected: &SerdeJsonValue) -> bool {
+    match (to_column_value(value), to_column_value(expected)) {
+        (Some(value), Some(expected)) => value == expected,
+        _ => value == expected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_event_model_mapping() {
+        let mapping: EventModelMapping = serde_json::from_value(json!([
+            { "when": [{ "path": "$.kind", "equals": 2 }], "event_type": "unlist" },
+            {
+                "when": [
+                    { "path": "$.kind", "in": ["0", "1"] },
+                    { "path": "$.buyer", "exists": false }
+                ],
+                "event_type": "list"
+            },
+            { "when": [{ "path": "$.buyer" }], "event_type": "buy" }
+        ]))
+        .unwrap();
+
+        // Move u8 values are numbers while u64 values are strings
+        assert_eq!(
+            mapping.resolve(&json!({ "kind": 2 })),
+            Some(MarketplaceEventType::Unlist)
+        );
+        assert_eq!(
+            mapping.resolve(&json!({ "kind": "2" })),
+            Some(MarketplaceEventType::Unlist)
+        );
+        assert_eq!(
+            mapping.resolve(&json!({ "kind": 1 })),
+            Some(MarketplaceEventType::List)
+        );
+        assert_eq!(
+            mapping.resolve(&json!({ "kind": 1, "buyer": "0x1" })),
+            Some(MarketplaceEventType::Buy)
+        );
+        assert_eq!(mapping.resolve(&json!({ "kind": 3 })), None);
+
+        let mapping: EventModelMapping = serde_json::from_value(json!("list")).unwrap();
+        assert_eq!(
+            mapping.resolve(&json!({ "kind": 2 })),
+            Some(MarketplaceEventType::List)
+        );
+    }
+}
@@ -1,9 +1,13 @@
 use crate::{
     config::marketplace_config::{
-        EventFieldRemappings, EventType, MarketplaceEventType, NFTMarketplaceConfig,
+        CollectionOfferMatching, EventFieldRemappings, EventModelMapping, EventType,
+        MarketplaceEventType, NFTMarketplaceConfig,
     },
     models::{
-        marketplace::{BidModel, MarketplaceField, MarketplaceModel, NftMarketplaceActivity},
+        db::{dead_letter_event::DeadLetterEvent, payment_token::APT_COIN_TYPE},
+        marketplace::{
+            BidModel, FieldError, MarketplaceField, MarketplaceModel, NftMarketplaceActivity,
+        },
         EventModel,
     },
     steps::marketplace::{remappers::TableType, HashableJsonPath},
@@ -11,54 +15,112 @@ use crate::{
 use anyhow::Result;
 use aptos_indexer_processor_sdk::{
     aptos_indexer_transaction_stream::utils::time::parse_timestamp,
-    aptos_protos::transaction::v1::{transaction::TxnData, Transaction},
+    aptos_protos::transaction::v1::{transaction::TxnData, write_set_change, Transaction},
     utils::{convert::standardize_address, extract::hash_str},
 };
+use chrono::Utc;
 use std::{collections::HashMap, str::FromStr, sync::Arc};
 use tracing::{debug, warn};
 
+/// Outcome of remapping a single event
+pub enum RemappedEvent {
+    Activity(NftMarketplaceActivity),
+    DeadLetter(DeadLetterEvent),
+}
+
 pub struct EventRemapper {
-    field_remappings: EventFieldRemappings,
+    deployments: Vec<DeploymentRemapping>,
     marketplace_name: String,
-    marketplace_event_type_mapping: HashMap<String, MarketplaceEventType>,
+    offer_matcher: OfferMatcher,
+}
+
+/// Remappings of the events of one deployment of the marketplace. A contract upgraded in place
+/// keeps its address, so its deployments are told apart by their version range.
+struct DeploymentRemapping {
+    starting_version: Option<u64>,
+    ending_version: Option<u64>,
+    field_remappings: EventFieldRemappings,
+    marketplace_event_type_mapping: HashMap<String, EventModelMapping>,
+}
+
+impl DeploymentRemapping {
+    fn is_active_at(&self, version: i64) -> bool {
+        let version = version as u64;
+        self.starting_version
+            .map_or(true, |starting| version >= starting)
+            && self.ending_version.map_or(true, |ending| version <= ending)
+    }
+}
+
+/// Parsed [`CollectionOfferMatching`] rule
+enum OfferMatcher {
+    EventFields(Vec<MarketplaceField>),
+    ResourceAddress(String),
 }
 
 impl EventRemapper {
     pub fn new(config: &NFTMarketplaceConfig) -> Result<Arc<Self>> {
-        let mut field_remappings: EventFieldRemappings = HashMap::new();
-        for (event_type, event_remapping) in &config.events {
-            let event_type: EventType = format!("{}::{}", config.contract_address, event_type)
-                .as_str()
-                .try_into()?;
-            let mut db_mappings_for_event = HashMap::new();
-
-            for (json_path, db_mappings) in &event_remapping.event_fields {
-                let json_path = HashableJsonPath::new(json_path)?;
-                let db_mappings = db_mappings
-                    .iter()
-                    .map(|db_mapping| {
-                        // We only map json path here for now, might have to support move_type as well.
-                        Ok(db_mapping.clone())
-                    })
-                    .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut deployments = vec![];
+        for deployment in config.get_deployments() {
+            let mut field_remappings: EventFieldRemappings = HashMap::new();
+            let mut marketplace_event_type_mapping: HashMap<String, EventModelMapping> =
+                HashMap::new();
+            for (event_type, event_remapping) in &deployment.config.events {
+                let event_type: EventType =
+                    format!("{}::{}", deployment.config.contract_address, event_type)
+                        .as_str()
+                        .try_into()?;
+                let mut db_mappings_for_event = HashMap::new();
+
+                for (json_path, db_mappings) in &event_remapping.event_fields {
+                    let json_path = HashableJsonPath::new(json_path)?;
+                    let db_mappings = db_mappings
+                        .iter()
+                        .map(|db_mapping| {
+                            // We only map json path here for now, might have to support move_type as well.
+                            Ok(db_mapping.clone())
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+
+                    db_mappings_for_event.insert(json_path, db_mappings);
+                }
 
-                db_mappings_for_event.insert(json_path, db_mappings);
+                field_remappings.insert(event_type, db_mappings_for_event);
             }
 
-            field_remappings.insert(event_type, db_mappings_for_event);
-        }
+            for (event_type, marketplace_event_type) in &deployment.config.event_model_mapping {
+                let event_type = format!("{}::{}", deployment.config.contract_address, event_type);
+                marketplace_event_type_mapping.insert(event_type, marketplace_event_type.clone());
+            }
 
-        let mut marketplace_event_type_mapping: HashMap<String, MarketplaceEventType> =
-            HashMap::new();
-        for (event_type, marketplace_event_type) in &config.event_model_mapping {
-            let event_type = format!("{}::{}", config.contract_address, event_type);
-            marketplace_event_type_mapping.insert(event_type, marketplace_event_type.clone());
+            deployments.push(DeploymentRemapping {
+                starting_version: deployment.starting_version,
+                ending_version: deployment.ending_version,
+                field_remappings,
+                marketplace_event_type_mapping,
+            });
         }
 
+        let offer_matcher = match &config.collection_offer_matching {
+            CollectionOfferMatching::EventFields { fields } => OfferMatcher::EventFields(
+                fields
+                    .iter()
+                    .map(|field| {
+                        MarketplaceField::from_str(field).map_err(|e| {
+                            anyhow::anyhow!("Invalid collection offer matching field {field}: {e}")
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            CollectionOfferMatching::ResourceAddress { resource_type } => {
+                OfferMatcher::ResourceAddress(resource_type.clone())
+            },
+        };
+
         Ok(Arc::new(Self {
-            field_remappings,
+            deployments,
             marketplace_name: config.name.clone(),
-            marketplace_event_type_mapping,
+            offer_matcher,
         }))
     }
 
@@ -70,138 +132,316 @@ impl EventRemapper {
     /// 3. Creates marketplace activity for event
     /// 4. Updates current models (listings, token offers, collection offers)
     /// 5. Generate necessary id fields for models that don't have an id if possible
-    pub fn remap_events(&self, txn: Transaction) -> Result<Vec<NftMarketplaceActivity>> {
+    pub fn remap_events(
+        &self,
+        txn: Transaction,
+    ) -> Result<(Vec<NftMarketplaceActivity>, Vec<DeadLetterEvent>)> {
         let mut activities: Vec<NftMarketplaceActivity> = Vec::new();
+        let mut dead_letters: Vec<DeadLetterEvent> = Vec::new();
 
         if let Some(txn_info) = txn.info.as_ref() {
             let txn_id = format!("0x{}", hex::encode(txn_info.hash.clone()));
+            let offer_resource_addresses = self.get_offer_resource_addresses(&txn);
             let events = self.get_events(Arc::new(txn))?;
 
             for event in events {
-                let event_type_str = event.event_type.to_string();
-
-                // Handle nft activity event
-                if let Some(remappings) = self.field_remappings.get(&event.event_type) {
-                    let event_type = self.marketplace_event_type_mapping.get(&event_type_str);
-
-                    if let Some(event_type) = event_type.cloned() {
-                        let mut activity = NftMarketplaceActivity {
-                            marketplace: Some(self.marketplace_name.clone()),
-                            txn_id: txn_id.to_string(),
-                            txn_version: event.transaction_version,
-                            index: event.event_index,
-                            contract_address: Some(event.account_address.clone()),
-                            block_timestamp: event.block_timestamp,
-                            block_height: event.transaction_block_height,
-                            raw_event_type: event.event_type.to_string(),
-                            json_data: serde_json::to_value(&event).unwrap(),
-                            standard_event_type: event_type.clone(),
-                            ..Default::default()
-                        };
-
-                        // Step 2: Build model structs from the values obtained by the JsonPaths
-                        remappings.iter().try_for_each(|(json_path, db_mappings)| {
-                            db_mappings.iter().try_for_each(|db_mapping| {
-                                // Extract value, continue on error instead of failing
-                                let extracted_value = match json_path.extract_from(&event.data) {
-                                    Ok(value) => value,
-                                    Err(e) => {
-                                        debug!(
-                                            "Failed to extract value for path {}: {}",
-                                            json_path.raw, e
-                                        );
-                                        return Ok::<(), anyhow::Error>(());
-                                    },
-                                };
-
-                                let value = extracted_value
-                                    .as_str()
-                                    .map(|s| s.to_string())
-                                    .or_else(|| extracted_value.as_u64().map(|n| n.to_string()))
-                                    .unwrap_or_default();
-
-                                if value.is_empty() {
-                                    debug!(
-                                        "Skipping empty value for path {} for column {}",
-                                        json_path.raw, db_mapping.column
-                                    );
-                                    return Ok(());
-                                }
-
-                                match TableType::from_str(db_mapping.table.as_str()) {
-                                    Some(TableType::Activities) => {
-                                        match MarketplaceField::from_str(db_mapping.column.as_str())
-                                        {
-                                            Ok(field) => {
-                                                activity.set_field(field, value);
-                                            },
-                                            Err(e) => {
-                                                warn!(
-                                                    "Skipping invalid field {}: {}",
-                                                    db_mapping.column, e
-                                                );
-                                            },
-                                        }
-                                    },
-                                    _ => {
-                                        warn!("Unknown table: {}", db_mapping.table);
-                                        return Ok(());
-                                    },
-                                }
-
-                                Ok(())
-                            })
-                        })?;
-
-                        // After processing all field remappings, generate necessary id fields if needed for PK
-                        if activity
-                            .get_field(MarketplaceField::CollectionAddr)
-                            .is_none()
-                        {
-                            let collection_addr = generate_collection_addr(
-                                activity.creator_address.clone(),
-                                activity.collection_name.clone(),
-                            );
-
-                            if let Some(collection_addr) = collection_addr {
-                                activity
-                                    .set_field(MarketplaceField::CollectionAddr, collection_addr);
-                            }
-                        }
+                match self.remap_event(&txn_id, &event, &offer_resource_addresses)? {
+                    Some(RemappedEvent::Activity(activity)) => activities.push(activity),
+                    Some(RemappedEvent::DeadLetter(dead_letter)) => dead_letters.push(dead_letter),
+                    None => {},
+                }
+            }
+        }
 
-                        if activity.get_field(MarketplaceField::TokenAddr).is_none() {
-                            let token_addr = generate_token_addr(
-                                activity.creator_address.clone(),
-                                activity.collection_name.clone(),
-                                activity.token_name.clone(),
-                            );
+        Ok((activities, dead_letters))
+    }
 
-                            if let Some(token_addr) = token_addr {
-                                activity.set_field(MarketplaceField::TokenAddr, token_addr);
-                            }
-                        }
+    /// Remaps a single event into a marketplace activity. Returns `None` for events the
+    /// marketplace config doesn't map.
+    ///
+    /// When the activity is left without the nft or collection it's keyed by, because its json
+    /// paths match nothing or its value can't be parsed, the event is dead-lettered instead so
+    /// it can be replayed once the config is fixed. Other fields whose paths match nothing are
+    /// recorded as field errors of the activity.
+    pub fn remap_event(
+        &self,
+        txn_id: &str,
+        event: &EventModel,
+        offer_resource_addresses: &[String],
+    ) -> Result<Option<RemappedEvent>> {
+        let event_type_str = event.event_type.to_string();
+        // Events of generic structs are configured without their type args
+        let base_event_type = event.event_type.without_type_args();
 
-                        // Handle collection_offer_id separately since it's specific to collection offers
-                        let is_collection_bid = activity
-                            .get_bid_type()
-                            .map_or(false, |bid_type| bid_type.as_str() == "collection");
-                        let is_offer_id_exists = activity
-                            .get_field(MarketplaceField::CollectionOfferId)
-                            .is_some();
-                        if is_collection_bid && !is_offer_id_exists {
-                            activity.offer_id = generate_collection_offer_id(
-                                activity.creator_address.clone(),
-                                activity.buyer.clone(),
-                            );
-                        }
+        // Handle nft activity event with the deployment that was live at its version
+        let Some((remappings, event_model_mapping)) = self
+            .deployments
+            .iter()
+            .filter(|deployment| deployment.is_active_at(event.transaction_version))
+            .find_map(|deployment| {
+                let remappings = deployment
+                    .field_remappings
+                    .get(&event.event_type)
+                    .or_else(|| deployment.field_remappings.get(&base_event_type))?;
+                let event_model_mapping = deployment
+                    .marketplace_event_type_mapping
+                    .get(&event_type_str)
+                    .or_else(|| {
+                        deployment
+                            .marketplace_event_type_mapping
+                            .get(&base_event_type.to_string())
+                    })?;
+                Some((remappings, event_model_mapping))
+            })
+        else {
+            return Ok(None);
+        };
+        let Some(event_type) = event_model_mapping.resolve(&event.data) else {
+            debug!(
+                "Event {} at version {} index {} matches no event_model_mapping rule",
+                event_type_str, event.transaction_version, event.event_index
+            );
+            return Ok(None);
+        };
+
+        let mut activity = NftMarketplaceActivity {
+            marketplace: Some(self.marketplace_name.clone()),
+            txn_id: txn_id.to_string(),
+            txn_version: event.transaction_version,
+            index: event.event_index,
+            contract_address: Some(event.account_address.clone()),
+            block_timestamp: event.block_timestamp,
+            block_height: event.transaction_block_height,
+            raw_event_type: event.event_type.to_string(),
+            json_data: serde_json::to_value(event).unwrap(),
+            standard_event_type: event_type.clone(),
+            ..Default::default()
+        };
+        let mut failed_json_paths: Vec<String> = Vec::new();
+        let mut missing_columns: Vec<(String, String)> = Vec::new();
 
-                        activities.push(activity);
+        // Step 2: Build model structs from the values obtained by the JsonPaths
+        remappings.iter().try_for_each(|(json_path, db_mappings)| {
+            db_mappings.iter().try_for_each(|db_mapping| {
+                // Extract value, continue on error instead of failing
+                let Some(extracted_value) = db_mapping.find_value(json_path, &event.data) else {
+                    if !failed_json_paths.contains(&json_path.raw) {
+                        failed_json_paths.push(json_path.raw.clone());
                     }
+                    missing_columns.push((db_mapping.column.clone(), json_path.raw.clone()));
+                    return Ok::<(), anyhow::Error>(());
+                };
+
+                let value = match db_mapping.transform(extracted_value.clone(), &event.data) {
+                    Ok(value) => value.unwrap_or_default(),
+                    Err(e) => {
+                        if !failed_json_paths.contains(&json_path.raw) {
+                            failed_json_paths.push(json_path.raw.clone());
+                        }
+                        activity.field_errors.push(FieldError {
+                            field: db_mapping.column.clone(),
+                            value: extracted_value.to_string(),
+                            error: format!("{e:#}"),
+                        });
+                        return Ok(());
+                    },
+                };
+
+                if value.is_empty() {
+                    debug!(
+                        "Skipping empty value for path {} for column {}",
+                        json_path.raw, db_mapping.column
+                    );
+                    return Ok(());
+                }
+
+                match TableType::from_str(db_mapping.table.as_str()) {
+                    Some(TableType::Activities) => {
+                        match MarketplaceField::from_str(db_mapping.column.as_str()) {
+                            Ok(field) => {
+                                activity.set_field(field, value);
+                            },
+                            Err(e) => {
+                                warn!("Skipping invalid field {}: {}", db_mapping.column, e);
+                            },
+                        }
+                    },
+                    _ => {
+                        warn!("Unknown table: {}", db_mapping.table);
+                        return Ok(());
+                    },
                 }
+
+                Ok(())
+            })
+        })?;
+
+        // After processing all field remappings, generate necessary id fields if needed for PK
+        if activity
+            .get_field(MarketplaceField::CollectionAddr)
+            .is_none()
+        {
+            let collection_addr = generate_collection_addr(
+                activity.creator_address.clone(),
+                activity.collection_name.clone(),
+            );
+
+            if let Some(collection_addr) = collection_addr {
+                activity.set_field(MarketplaceField::CollectionAddr, collection_addr);
+            }
+        }
+
+        if activity.get_field(MarketplaceField::TokenAddr).is_none() {
+            let token_addr = generate_token_addr(
+                activity.creator_address.clone(),
+                activity.collection_name.clone(),
+                activity.token_name.clone(),
+            );
+
+            if let Some(token_addr) = token_addr {
+                activity.set_field(MarketplaceField::TokenAddr, token_addr);
+            }
+        }
+
+        if let Some(key_field) = get_missing_key_field(&activity) {
+            let key_column = key_field.to_string();
+            let mut error = format!("{key_column} is missing");
+            if let Some(field_error) = activity
+                .field_errors
+                .iter()
+                .find(|field_error| field_error.field == key_column)
+            {
+                error = format!("{error}: {}", field_error.error);
+            }
+            warn!(
+                "Dead-lettering event {} at version {} index {}: {}",
+                event_type_str, event.transaction_version, event.event_index, error
+            );
+            return Ok(Some(RemappedEvent::DeadLetter(DeadLetterEvent::new(
+                txn_id,
+                event,
+                &self.marketplace_name,
+                &failed_json_paths,
+                error,
+                Utc::now().naive_utc(),
+            ))));
+        }
+
+        // A column is only missing when none of the paths mapped to it matched
+        for (column, json_path) in missing_columns {
+            let is_missing = MarketplaceField::from_str(&column)
+                .is_ok_and(|field| activity.get_field(field).is_none());
+            if is_missing
+                && !activity
+                    .field_errors
+                    .iter()
+                    .any(|field_error| field_error.field == column)
+            {
+                activity.field_errors.push(FieldError {
+                    field: column,
+                    value: String::new(),
+                    error: format!("No value found at {json_path}"),
+                });
+            }
+        }
+
+        // Without a mapped payment token, the first struct type arg of the event is the coin
+        // it's paid in, e.g. `0x1::aptos_coin::AptosCoin` of
+        // `Listing<0x1::aptos_coin::AptosCoin>`
+        if activity.payment_token.is_none() {
+            let payment_token = event
+                .event_type
+                .get_type_args()
+                .into_iter()
+                .find(|type_arg| type_arg.contains("::"))
+                .unwrap_or_else(|| APT_COIN_TYPE.to_string());
+            activity.set_field(MarketplaceField::PaymentToken, payment_token);
+        }
+
+        // Handle collection_offer_id separately since it's specific to collection offers
+        let is_collection_bid = activity
+            .get_bid_type()
+            .map_or(false, |bid_type| bid_type.as_str() == "collection");
+        let is_offer_id_exists = activity
+            .get_field(MarketplaceField::CollectionOfferId)
+            .is_some();
+        if is_collection_bid && !is_offer_id_exists {
+            activity.offer_match_key =
+                self.get_offer_match_key(&activity, offer_resource_addresses);
+
+            // Cancel and fill events are correlated back to this id by the reduction step
+            // using the match key
+            if activity.standard_event_type == MarketplaceEventType::CollectionBid {
+                activity.offer_id = Some(generate_collection_offer_id(
+                    activity.txn_version,
+                    activity.index,
+                ));
             }
         }
 
-        Ok(activities)
+        Ok(Some(RemappedEvent::Activity(activity)))
+    }
+
+    /// Computes the key used to match the events of a collection offer without an on-chain id,
+    /// either from the configured activity fields or from the offer resource in the write set.
+    fn get_offer_match_key(
+        &self,
+        activity: &NftMarketplaceActivity,
+        offer_resource_addresses: &[String],
+    ) -> Option<String> {
+        match &self.offer_matcher {
+            OfferMatcher::EventFields(fields) => {
+                let values = fields
+                    .iter()
+                    .map(|field| {
+                        activity.get_field(field.clone()).map(|value| {
+                            if value.starts_with("0x") {
+                                standardize_address(&value)
+                            } else {
+                                value
+                            }
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                if values.is_empty() {
+                    return None;
+                }
+
+                Some(standardize_address(&hash_str(&values.join("::"))))
+            },
+            // A transaction is expected to touch a single offer resource
+            OfferMatcher::ResourceAddress(_) => offer_resource_addresses.first().cloned(),
+        }
+    }
+
+    /// Returns the addresses of the offer resources written by the transaction when offers
+    /// are matched by resource address.
+    fn get_offer_resource_addresses(&self, txn: &Transaction) -> Vec<String> {
+        let OfferMatcher::ResourceAddress(resource_type) = &self.offer_matcher else {
+            return vec![];
+        };
+
+        txn.info
+            .as_ref()
+            .map(|info| {
+                info.changes
+                    .iter()
+                    .filter_map(|wsc| match wsc.change.as_ref() {
+                        Some(write_set_change::Change::WriteResource(wr))
+                            if &wr.type_str == resource_type =>
+                        {
+                            Some(standardize_address(&wr.address))
+                        },
+                        Some(write_set_change::Change::DeleteResource(dr))
+                            if &dr.type_str == resource_type =>
+                        {
+                            Some(standardize_address(&dr.address))
+                        },
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     fn get_events(&self, transaction: Arc<Transaction>) -> Result<Vec<EventModel>> {
@@ -225,6 +465,23 @@ impl EventRemapper {
     }
 }
 
+/// Returns the field the activity is keyed by when it's missing, the collection for collection
+/// offers and the nft otherwise.
+fn get_missing_key_field(activity: &NftMarketplaceActivity) -> Option<MarketplaceField> {
+    let key_field = match activity.standard_event_type {
+        MarketplaceEventType::CollectionBid
+        | MarketplaceEventType::CancelCollectionBid
+        | MarketplaceEventType::AcceptCollectionBid => MarketplaceField::CollectionAddr,
+        MarketplaceEventType::Unknown => return None,
+        _ => MarketplaceField::TokenAddr,
+    };
+
+    activity
+        .get_field(key_field.clone())
+        .is_none()
+        .then_some(key_field)
+}
+
 fn generate_token_addr(
     creator_address: Option<String>,
     collection_name: Option<String>,
@@ -264,22 +521,161 @@ fn generate_collection_addr(
     }
 }
 
-#[allow(dead_code)]
-fn generate_collection_offer_id(
-    creator_address: Option<String>,
-    buyer: Option<String>,
-) -> Option<String> {
-    match (creator_address, buyer) {
-        (Some(creator), Some(buyer)) if !creator.is_empty() && !buyer.is_empty() => {
-            let creator_address = standardize_address(&creator);
-            let buyer_address = standardize_address(&buyer);
-            let input = format!("{creator_address}::{buyer_address}");
-            let hash_str = hash_str(&input);
-            Some(standardize_address(&hash_str))
-        },
-        _ => {
-            debug!("Missing required fields for collection bid id generation - skipping");
-            None
-        },
+/// Collection offers without an on-chain id are identified by the tx index of their creation
+/// event, zero padded so that ids sort by creation.
+fn generate_collection_offer_id(txn_version: i64, event_index: i64) -> String {
+    format!("{:020}", txn_version * 100_000 + event_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_collection_offer_id() {
+        let first = generate_collection_offer_id(2_000_000_000, 3);
+        let second = generate_collection_offer_id(10_000_000_000, 0);
+
+        assert_eq!(first, "00000200000000000003");
+        assert!(first < second);
+    }
+
+    fn list_event(data: serde_json::Value) -> EventModel {
+        EventModel {
+            sequence_number: 0,
+            creation_number: 0,
+            account_address: standardize_address("0x1"),
+            transaction_version: 100,
+            transaction_block_height: 10,
+            event_type: EventType::try_from("0x1::market::ListEvent").unwrap(),
+            type_str: "0x1::market::ListEvent".to_string(),
+            data,
+            event_index: 2,
+            block_timestamp: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_remap_event_dead_letter() {
+        let config: NFTMarketplaceConfig = serde_json::from_value(serde_json::json!({
+            "name": "market",
+            "contract_address": standardize_address("0x1"),
+            "event_model_mapping": { "market::ListEvent": "list" },
+            "events": {
+                "market::ListEvent": {
+                    "event_fields": {
+                        "$.token.inner": [
+                            {
+                                "table": "nft_marketplace_activities",
+                                "column": "token_addr",
+                                "transforms": [{ "type": "standardize_address" }]
+                            }
+                        ],
+                        "$.price": [
+                            { "table": "nft_marketplace_activities", "column": "price" }
+                        ]
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let remapper = EventRemapper::new(&config).unwrap();
+
+        let event = list_event(serde_json::json!({ "token": { "inner": "0x2" }, "price": "5" }));
+        let Some(RemappedEvent::Activity(activity)) =
+            remapper.remap_event("0xabc", &event, &[]).unwrap()
+        else {
+            panic!("expected an activity");
+        };
+        assert_eq!(activity.token_addr, Some(standardize_address("0x2")));
+        assert!(activity.field_errors.is_empty());
+
+        // The nft can't be told without the token, the price alone doesn't matter
+        let event = list_event(serde_json::json!({ "price": "5" }));
+        let Some(RemappedEvent::DeadLetter(dead_letter)) =
+            remapper.remap_event("0xabc", &event, &[]).unwrap()
+        else {
+            panic!("expected a dead letter");
+        };
+        assert_eq!(dead_letter.json_path, "$.token.inner");
+        assert_eq!(dead_letter.error, "token_addr is missing");
+        assert_eq!(dead_letter.get_event().unwrap().data, event.data);
+
+        // The activity is kept without its price, which is recorded as a field error
+        let event = list_event(serde_json::json!({ "token": { "inner": "0x2" } }));
+        let Some(RemappedEvent::Activity(activity)) =
+            remapper.remap_event("0xabc", &event, &[]).unwrap()
+        else {
+            panic!("expected an activity");
+        };
+        assert_eq!(activity.price, None);
+        assert_eq!(activity.field_errors.len(), 1);
+        assert_eq!(activity.field_errors[0].field, "price");
+        assert_eq!(activity.field_errors[0].error, "No value found at $.price");
+
+        // The token is found but can't be parsed
+        let event = list_event(serde_json::json!({ "token": { "inner": 2 }, "price": "5" }));
+        let Some(RemappedEvent::DeadLetter(dead_letter)) =
+            remapper.remap_event("0xabc", &event, &[]).unwrap()
+        else {
+            panic!("expected a dead letter");
+        };
+        assert_eq!(dead_letter.json_path, "$.token.inner");
+        assert_eq!(
+            dead_letter.error,
+            "token_addr is missing: 2 is not an address"
+        );
+    }
+
+    #[test]
+    fn test_remap_event_by_deployment_version() {
+        // The contract was upgraded in place at version 100 and renamed the token field
+        let deployment = |name: &str, path: &str, versions: serde_json::Value| {
+            let mut deployment = serde_json::json!({
+                "name": name,
+                "contract_address": standardize_address("0x1"),
+                "event_model_mapping": { "market::ListEvent": "list" },
+                "events": {
+                    "market::ListEvent": {
+                        "event_fields": {
+                            path: [
+                                { "table": "nft_marketplace_activities", "column": "token_addr" }
+                            ]
+                        }
+                    }
+                }
+            });
+            deployment
+                .as_object_mut()
+                .unwrap()
+                .extend(versions.as_object().unwrap().clone());
+            deployment
+        };
+        let config: NFTMarketplaceConfig = serde_json::from_value(serde_json::json!({
+            "name": "market",
+            "deployments": [
+                deployment("v1", "$.token", serde_json::json!({ "ending_version": 99 })),
+                deployment("v2", "$.token_v2", serde_json::json!({ "starting_version": 100 })),
+            ]
+        }))
+        .unwrap();
+        let remapper = EventRemapper::new(&config).unwrap();
+
+        let mut event = list_event(serde_json::json!({ "token": "0x2", "token_v2": "0x3" }));
+        event.transaction_version = 99;
+        let Some(RemappedEvent::Activity(activity)) =
+            remapper.remap_event("0xabc", &event, &[]).unwrap()
+        else {
+            panic!("expected an activity");
+        };
+        assert_eq!(activity.token_addr, Some("0x2".to_string()));
+
+        event.transaction_version = 100;
+        let Some(RemappedEvent::Activity(activity)) =
+            remapper.remap_event("0xabc", &event, &[]).unwrap()
+        else {
+            panic!("expected an activity");
+        };
+        assert_eq!(activity.token_addr, Some("0x3".to_string()));
     }
 }
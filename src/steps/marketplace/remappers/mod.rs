@@ -4,11 +4,13 @@ use crate::models::marketplace::{
     NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME,
 };
 
+pub mod event_mapping;
 pub mod event_remapper;
 pub mod resource_remapper;
+pub mod value_transform;
 
 #[derive(Debug, PartialEq, Eq)]
-enum TableType {
+pub(crate) enum TableType {
     Activities,
     Listings,
     TokenOffers,
@@ -16,7 +18,7 @@ enum TableType {
 }
 
 impl TableType {
-    fn from_str(table_name: &str) -> Option<Self> {
+    pub(crate) fn from_str(table_name: &str) -> Option<Self> {
         match table_name {
             NFT_MARKETPLACE_ACTIVITIES_TABLE_NAME => Some(TableType::Activities),
             CURRENT_NFT_MARKETPLACE_LISTINGS_TABLE_NAME => Some(TableType::Listings),
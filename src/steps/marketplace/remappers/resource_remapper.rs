@@ -74,6 +74,10 @@ impl ResourceMapper {
                         db_mappings.iter().try_for_each(|db_mapping| {
                             // TODO: handle types when move_type is supported
                             let value = extract_string(json_path, &data).unwrap_or_default();
+                            if value.is_empty() {
+                                return anyhow::Ok(());
+                            }
+
                             resource_updates
                                 .entry(resource_address.clone()) // Use resource address as key
                                 .or_default()
@@ -0,0 +1,217 @@
+use crate::{
+    config::marketplace_config::{DbColumn, ValueTransform},
+    steps::marketplace::HashableJsonPath,
+};
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::utils::convert::standardize_address;
+use bigdecimal::{BigDecimal, RoundingMode};
+use serde_json::Value as SerdeJsonValue;
+use std::str::FromStr;
+
+impl DbColumn {
+    /// Extracts the value of the mapping from the event data, trying the fallback paths in
+    /// order when `json_path` matches nothing.
+    pub fn find_value(
+        &self,
+        json_path: &HashableJsonPath,
+        data: &SerdeJsonValue,
+    ) -> Option<SerdeJsonValue> {
+        std::iter::once(json_path)
+            .chain(&self.fallback_paths)
+            .find_map(|path| path.extract_from(data).ok())
+    }
+
+    /// Applies the transforms of the mapping to an extracted value and renders it as the
+    /// string of its column. Returns `None` when no value is left, e.g. for an empty Move
+    /// `Option` or an object.
+    pub fn transform(
+        &self,
+        value: SerdeJsonValue,
+        data: &SerdeJsonValue,
+    ) -> Result<Option<String>> {
+        let mut value = value;
+        for transform in &self.transforms {
+            match transform.apply(value, data)? {
+                Some(transformed) => value = transformed,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(to_column_value(&value))
+    }
+}
+
+impl ValueTransform {
+    fn apply(
+        &self,
+        value: SerdeJsonValue,
+        data: &SerdeJsonValue,
+    ) -> Result<Option<SerdeJsonValue>> {
+        let transformed = match self {
+            ValueTransform::UnwrapOption => {
+                let vec = value
+                    .get("vec")
+                    .and_then(|vec| vec.as_array())
+                    .with_context(|| format!("{value} is not a Move Option"))?;
+                return Ok(vec.first().cloned());
+            },
+            ValueTransform::Multiply { path } => {
+                let factor = path.extract_from(data)?;
+                (to_decimal(&value)? * to_decimal(&factor)?).to_string()
+            },
+            ValueTransform::Divide { by } => {
+                if *by == 0 {
+                    anyhow::bail!("Can't divide by zero");
+                }
+                (to_decimal(&value)? / BigDecimal::from(*by))
+                    .with_scale_round(0, RoundingMode::Down)
+                    .to_string()
+            },
+            ValueTransform::Concat { paths, separator } => {
+                let mut parts =
+                    vec![to_column_value(&value).with_context(|| format!("Can't concat {value}"))?];
+                for path in paths {
+                    let part = path.extract_from(data)?;
+                    parts.push(
+                        to_column_value(&part).with_context(|| format!("Can't concat {part}"))?,
+                    );
+                }
+                parts.join(separator)
+            },
+            ValueTransform::StandardizeAddress => standardize_address(
+                value
+                    .as_str()
+                    .with_context(|| format!("{value} is not an address"))?,
+            ),
+        };
+
+        Ok(Some(SerdeJsonValue::String(transformed)))
+    }
+}
+
+/// Strings, numbers and booleans are written as is, other values leave the column empty
+pub(crate) fn to_column_value(value: &SerdeJsonValue) -> Option<String> {
+    match value {
+        SerdeJsonValue::String(s) => Some(s.clone()),
+        SerdeJsonValue::Number(n) => Some(n.to_string()),
+        SerdeJsonValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Move integers above u32 are serialized as strings
+fn to_decimal(value: &SerdeJsonValue) -> Result<BigDecimal> {
+    let raw = match value {
+        SerdeJsonValue::String(s) => s.clone(),
+        SerdeJsonValue::Number(n) => n.to_string(),
+        _ => anyhow::bail!("{value} is not a number"),
+    };
+
+    BigDecimal::from_str(&raw).with_context(|| format!("{raw:?} is not a number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn db_column(config: SerdeJsonValue) -> DbColumn {
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn remap(
+        db_column: &DbColumn,
+        json_path: &str,
+        data: &SerdeJsonValue,
+    ) -> Result<Option<String>> {
+        let json_path = HashableJsonPath::new(json_path).unwrap();
+        match db_column.find_value(&json_path, data) {
+            Some(value) => db_column.transform(value, data),
+            None => Ok(None),
+        }
+    }
+
+    #[test]
+    fn test_transforms() {
+        let data = json!({
+            "price": "1500",
+            "amount": 3,
+            "expiration_ms": "1700000000999",
+            "token": { "vec": ["0xa"] },
+            "no_token": { "vec": [] },
+            "creator": "0x1",
+            "collection": "Cats",
+        });
+
+        let price = db_column(json!({
+            "table": "nft_marketplace_activities",
+            "column": "price",
+            "transforms": [{ "type": "multiply", "path": "$.amount" }]
+        }));
+        assert_eq!(
+            remap(&price, "$.price", &data).unwrap(),
+            Some("4500".to_string())
+        );
+
+        let expiration_time = db_column(json!({
+            "table": "nft_marketplace_activities",
+            "column": "expiration_time",
+            "transforms": [{ "type": "divide", "by": 1000 }]
+        }));
+        assert_eq!(
+            remap(&expiration_time, "$.expiration_ms", &data).unwrap(),
+            Some("1700000000".to_string())
+        );
+
+        let token_addr = db_column(json!({
+            "table": "nft_marketplace_activities",
+            "column": "token_addr",
+            "transforms": [{ "type": "unwrap_option" }, { "type": "standardize_address" }]
+        }));
+        assert_eq!(
+            remap(&token_addr, "$.token", &data).unwrap(),
+            Some(standardize_address("0xa"))
+        );
+        assert_eq!(remap(&token_addr, "$.no_token", &data).unwrap(), None);
+        assert!(remap(&token_addr, "$.price", &data).is_err());
+
+        let collection = db_column(json!({
+            "table": "nft_marketplace_activities",
+            "column": "collection_addr",
+            "transforms": [{ "type": "concat", "paths": ["$.collection"] }]
+        }));
+        assert_eq!(
+            remap(&collection, "$.creator", &data).unwrap(),
+            Some("0x1::Cats".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fallback_paths() {
+        let seller = db_column(json!({
+            "table": "nft_marketplace_activities",
+            "column": "seller",
+            "fallback_paths": ["$.owner", "$.lister"]
+        }));
+
+        let data = json!({ "seller": "0x1", "lister": "0x3" });
+        assert_eq!(
+            remap(&seller, "$.seller", &data).unwrap(),
+            Some("0x1".to_string())
+        );
+        let data = json!({ "lister": "0x3" });
+        assert_eq!(
+            remap(&seller, "$.seller", &data).unwrap(),
+            Some("0x3".to_string())
+        );
+        let data = json!({ "buyer": "0x2" });
+        assert_eq!(remap(&seller, "$.seller", &data).unwrap(), None);
+
+        assert!(serde_json::from_value::<DbColumn>(json!({
+            "table": "nft_marketplace_activities",
+            "column": "seller",
+            "fallback_paths": ["$[?("]
+        }))
+        .is_err());
+    }
+}
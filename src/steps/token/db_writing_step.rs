@@ -1,27 +1,214 @@
 use crate::{
-    models::db::{action::Action, attributes::Attribute, collection::Collection, nft::Nft},
+    models::db::{
+        action::Action,
+        attributes::Attribute,
+        collection::Collection,
+        collection_stats::{refresh_collection_stats, CollectionStats},
+        commission::Commission,
+        listing::{invalidate_stale_listings, Listing},
+        market_depth::MarketDepthChanges,
+        nft::Nft,
+        rarity::{insert_attribute_changes, insert_supply_changes, CollectionRarity},
+        wallet_transfer::{insert_wallet_transfers, WalletTransfer},
+        webhook::enqueue_webhook_deliveries,
+    },
     postgres::postgres_utils::{execute_in_chunks, ArcDbPool},
     schema,
+    utils::event_bus::EventBus,
 };
+use ahash::AHashSet;
 use aptos_indexer_processor_sdk::{
     traits::{async_step::AsyncRunType, AsyncStep, NamedStep, Processable},
     types::transaction_context::TransactionContext,
     utils::errors::ProcessorError,
 };
+use chrono::Utc;
 use diesel::{
+    dsl::sql,
     pg::{upsert::excluded, Pg},
     query_builder::QueryFragment,
+    sql_types::{Integer, Nullable, Text},
     ExpressionMethods,
 };
 use tonic::async_trait;
 
 pub struct DBWritingStep {
     pub db_pool: ArcDbPool,
+    pub event_bus: EventBus,
 }
 
 impl DBWritingStep {
-    pub fn new(db_pool: ArcDbPool) -> Self {
-        Self { db_pool }
+    pub fn new(db_pool: ArcDbPool, event_bus: EventBus) -> Self {
+        Self { db_pool, event_bus }
+    }
+
+    /// Returns the stored `(collection_id, owner)` pairs of the given nfts.
+    async fn get_collection_owners(
+        &self,
+        nfts: &[Nft],
+        burn_nfts: &[Nft],
+    ) -> Result<Vec<(String, String)>, ProcessorError> {
+        let nft_ids = nfts
+            .iter()
+            .chain(burn_nfts.iter())
+            .map(|nft| nft.id.clone())
+            .collect::<Vec<_>>();
+        if nft_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        Nft::get_collection_owners(&mut conn, &nft_ids)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to query nft owners. {e:?}"),
+                query: None,
+            })
+    }
+
+    /// Applies the nfts that changed hands or were burned to the owner counts of their
+    /// collections, from their owners before the batch and after it, then recomputes the
+    /// listing stats of the collections whose supply, owners or sales changed.
+    async fn refresh_collection_stats(
+        &self,
+        actions: &[Action],
+        collections: &[Collection],
+        nfts: &[Nft],
+        burn_nfts: &[Nft],
+        previous_owners: Vec<(String, String)>,
+    ) -> Result<(), ProcessorError> {
+        let mut owners = previous_owners;
+        owners.extend(self.get_collection_owners(nfts, burn_nfts).await?);
+
+        let collection_ids = actions
+            .iter()
+            .filter_map(|action| action.collection_id.clone())
+            .chain(collections.iter().map(|collection| collection.id.clone()))
+            .chain(
+                nfts.iter()
+                    .chain(burn_nfts.iter())
+                    .filter_map(|nft| nft.collection_id.clone()),
+            );
+
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        CollectionStats::count_owners(&mut conn, &owners)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to count owners in collection stats. {e:?}"),
+                query: None,
+            })?;
+
+        refresh_collection_stats(&mut conn, collection_ids)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to refresh collection stats. {e:?}"),
+                query: None,
+            })?;
+
+        Ok(())
+    }
+
+    /// Invalidates the listings of nfts that were transferred or burned after they were listed,
+    /// refreshes the market depth of their nfts and collections, and returns them.
+    async fn invalidate_stale_listings(
+        &self,
+        actions: &[Action],
+    ) -> Result<Vec<Listing>, ProcessorError> {
+        let nft_ids = actions
+            .iter()
+            .filter(|action| action.is_ownership_change())
+            .filter_map(|action| action.nft_id.clone());
+
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        let invalidated = invalidate_stale_listings(&mut conn, nft_ids)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to invalidate stale listings. {e:?}"),
+                query: None,
+            })?;
+
+        let mut depth_changes = MarketDepthChanges::default();
+        invalidated
+            .iter()
+            .for_each(|listing| depth_changes.add_listing(listing));
+        depth_changes
+            .refresh(&mut conn)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to refresh market depth. {e:?}"),
+                query: None,
+            })?;
+
+        Ok(invalidated)
+    }
+
+    /// Publishes the stored actions and the listings they invalidated to the subscribers.
+    async fn publish_changes(
+        &self,
+        actions: &[Action],
+        invalidated_listings: &[Listing],
+    ) -> Result<(), ProcessorError> {
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        self.event_bus
+            .publish_actions(&mut conn, actions, invalidated_listings)
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to publish changes. {e:?}"),
+                query: None,
+            })
+    }
+
+    /// Adds the stored actions to the outbox of the webhooks they match.
+    async fn enqueue_webhook_deliveries(&self, actions: &[Action]) -> Result<(), ProcessorError> {
+        let mut conn = self
+            .db_pool
+            .get()
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to get database connection. {e:?}"),
+                query: None,
+            })?;
+
+        enqueue_webhook_deliveries(&mut conn, actions, Utc::now().naive_utc())
+            .await
+            .map_err(|e| ProcessorError::DBStoreError {
+                message: format!("Failed to enqueue webhook deliveries. {e:?}"),
+                query: None,
+            })?;
+
+        Ok(())
     }
 }
 
@@ -33,6 +220,8 @@ impl Processable for DBWritingStep {
         Vec<Nft>,
         Vec<Attribute>,
         Vec<Nft>,
+        Vec<WalletTransfer>,
+        Vec<Commission>,
     );
     type Output = ();
     type RunType = AsyncRunType;
@@ -41,7 +230,10 @@ impl Processable for DBWritingStep {
         &mut self,
         input: TransactionContext<Self::Input>,
     ) -> Result<Option<TransactionContext<()>>, ProcessorError> {
-        let (actions, collections, nfts, attributes, burn_nfts) = input.data;
+        let (actions, collections, nfts, attributes, burn_nfts, wallet_transfers, commissions) =
+            input.data;
+        // Owner counts are updated from the owners before and after the batch
+        let previous_owners = self.get_collection_owners(&nfts, &burn_nfts).await?;
 
         let action_fut = execute_in_chunks(self.db_pool.clone(), insert_actions, &actions, 200);
         let nft_fut = execute_in_chunks(self.db_pool.clone(), insert_nfts, &nfts, 200);
@@ -60,12 +252,65 @@ impl Processable for DBWritingStep {
             burn_nft_fut
         );
 
+        // Rarity depends on the supply and attributes, mark the changed collections for recompute
+        let now = Utc::now().naive_utc();
+        let supply_changes = collections
+            .iter()
+            .map(|collection| CollectionRarity::new(collection.id.clone(), collection.supply, now))
+            .collect::<Vec<_>>();
+        let attribute_changes = attributes
+            .iter()
+            .filter_map(|attribute| attribute.collection_id.clone())
+            .collect::<AHashSet<_>>()
+            .into_iter()
+            .map(|collection_id| CollectionRarity::new(collection_id, None, now))
+            .collect::<Vec<_>>();
+
+        let supply_change_fut = execute_in_chunks(
+            self.db_pool.clone(),
+            insert_supply_changes,
+            &supply_changes,
+            200,
+        );
+        let attribute_change_fut = execute_in_chunks(
+            self.db_pool.clone(),
+            insert_attribute_changes,
+            &attribute_changes,
+            200,
+        );
+
+        let wallet_transfer_fut = execute_in_chunks(
+            self.db_pool.clone(),
+            insert_wallet_transfers,
+            &wallet_transfers,
+            200,
+        );
+
+        let commission_fut =
+            execute_in_chunks(self.db_pool.clone(), insert_commissions, &commissions, 200);
+
+        let (
+            supply_change_result,
+            attribute_change_result,
+            wallet_transfer_result,
+            commission_result,
+        ) = tokio::join!(
+            supply_change_fut,
+            attribute_change_fut,
+            wallet_transfer_fut,
+            commission_fut
+        );
+
         for result in [
             action_result,
             nft_result,
             collection_result,
             attribute_result,
             burn_nft_result,
+            supply_change_result,
+            attribute_change_result,
+            wallet_transfer_result,
+            commission_result,
         ] {
             match result {
                 Ok(_) => (),
@@ -78,6 +323,14 @@ impl Processable for DBWritingStep {
             }
         }
 
+        // Before the stats, so that the floor price doesn't include the invalidated listings
+        let invalidated_listings = self.invalidate_stale_listings(&actions).await?;
+        self.refresh_collection_stats(&actions, &collections, &nfts, &burn_nfts, previous_owners)
+            .await?;
+        self.enqueue_webhook_deliveries(&actions).await?;
+        self.publish_changes(&actions, &invalidated_listings)
+            .await?;
+
         Ok(Some(TransactionContext {
             data: (),
             metadata: input.metadata,
@@ -134,15 +387,20 @@ pub fn insert_nfts(
         .set((
             owner.eq(excluded(owner)),
             name.eq(excluded(name)),
-            image_url.eq(excluded(image_url)),
+            // image_url is resolved from the metadata by the attribute worker
+            uri.eq(excluded(uri)),
             description.eq(excluded(description)),
             properties.eq(excluded(properties)),
-            background_color.eq(excluded(background_color)),
-            image_data.eq(excluded(image_data)),
-            animation_url.eq(excluded(animation_url)),
-            youtube_url.eq(excluded(youtube_url)),
-            avatar_url.eq(excluded(avatar_url)),
-            external_url.eq(excluded(external_url)),
+            // Like image_url, the other metadata columns are filled by the attribute worker,
+            // which fetches the metadata again when the uri changes
+            metadata_status.eq(sql::<Nullable<Text>>(
+                "CASE WHEN nfts.uri IS DISTINCT FROM excluded.uri THEN NULL \
+                 ELSE nfts.metadata_status END",
+            )),
+            metadata_attempts.eq(sql::<Nullable<Integer>>(
+                "CASE WHEN nfts.uri IS DISTINCT FROM excluded.uri THEN 0 \
+                 ELSE nfts.metadata_attempts END",
+            )),
             burned.eq(excluded(burned)),
         ))
 }
@@ -169,3 +427,18 @@ pub fn insert_attributes(
         .on_conflict((collection_id, nft_id, attr_type, value))
         .do_nothing()
 }
+
+pub fn insert_commissions(
+    items_to_insert: Vec<Commission>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::commissions::dsl::*;
+
+    diesel::insert_into(schema::commissions::table)
+        .values(items_to_insert)
+        .on_conflict(id)
+        .do_update()
+        .set((
+            royalty.eq(excluded(royalty)),
+            payee_address.eq(excluded(payee_address)),
+        ))
+}
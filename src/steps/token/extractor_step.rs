@@ -3,7 +3,7 @@ use crate::{
     models::{
         db::{
             action::Action, attributes::Attribute, collection::Collection, commission::Commission,
-            nft::Nft,
+            nft::Nft, wallet_transfer::WalletTransfer,
         },
         resources::{FromWriteResource, V2TokenResource},
         EventModel,
@@ -43,6 +43,8 @@ impl Processable for TokenExtractor {
         Vec<Nft>,
         Vec<Attribute>,
         Vec<Nft>,
+        Vec<WalletTransfer>,
+        Vec<Commission>,
     );
     type RunType = AsyncRunType;
 
@@ -57,6 +59,7 @@ impl Processable for TokenExtractor {
         let mut current_burn_nfts: AHashMap<String, Nft> = AHashMap::new();
         let mut current_attributes: AHashMap<(String, String, String, String), Attribute> =
             AHashMap::new();
+        let mut wallet_transfers: Vec<WalletTransfer> = vec![];
 
         let table_handler_to_owner =
             TableMetadataForToken::get_table_handle_to_owner_from_transactions(&transactions.data);
@@ -71,6 +74,10 @@ impl Processable for TokenExtractor {
                 let txn_ts =
                     parse_timestamp(txn.timestamp.as_ref().unwrap(), txn_version).naive_utc();
 
+                if let Some(transfer) = WalletTransfer::from_transaction(txn, &txn_id, txn_ts) {
+                    wallet_transfers.push(transfer);
+                }
+
                 let txn_data = match txn.txn_data.as_ref() {
                     Some(data) => data,
                     None => continue,
@@ -329,9 +336,18 @@ impl Processable for TokenExtractor {
         let nfts = current_nfts.drain().map(|(_, v)| v).collect();
         let attributes = current_attributes.drain().map(|(_, v)| v).collect();
         let burn_nfts = current_burn_nfts.drain().map(|(_, v)| v).collect();
+        let commissions = current_commissions.drain().map(|(_, v)| v).collect();
 
         Ok(Some(TransactionContext {
-            data: (actions, collections, nfts, attributes, burn_nfts),
+            data: (
+                actions,
+                collections,
+                nfts,
+                attributes,
+                burn_nfts,
+                wallet_transfers,
+                commissions,
+            ),
             metadata: transactions.metadata,
         }))
     }
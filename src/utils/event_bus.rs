@@ -0,0 +1,84 @@
+use crate::models::{
+    change_event::{ChangeEvent, ChangeEventType, StoredChangeEvent},
+    db::{action::Action, listing::Listing},
+};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use diesel::QueryResult;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+/// Number of changes buffered per subscriber before it lags behind
+pub const EVENT_BUS_CAPACITY: usize = 10_000;
+
+/// In-process broadcast of the changes committed by the writing steps and the workers.
+///
+/// Every change is stored in the `change_events` outbox before it's sent, and its id there is
+/// the position clients resume from. The pipelines commit in parallel and in no particular
+/// `tx_index` order, so changes are stored and sent one publish at a time, which keeps the ids
+/// in the order subscribers receive them.
+#[derive(Clone, Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<StoredChangeEvent>>,
+    publish_lock: Arc<Mutex<()>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_BUS_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            publish_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Publishes the changes of the stored actions in `tx_index` order, then the listings they
+    /// invalidated. Must be called after the actions are committed, so subscribers can query
+    /// the stored state.
+    pub async fn publish_actions(
+        &self,
+        conn: &mut DbPoolConnection<'_>,
+        actions: &[Action],
+        invalidated_listings: &[Listing],
+    ) -> QueryResult<()> {
+        let mut events = actions
+            .iter()
+            .filter_map(ChangeEvent::from_action)
+            .collect::<Vec<_>>();
+        events.sort_by_key(|event| event.tx_index);
+        events.extend(invalidated_listings.iter().map(|listing| {
+            ChangeEvent::from_listing(listing, ChangeEventType::ListingInvalidated)
+        }));
+
+        self.publish(conn, events).await
+    }
+
+    /// Stores the changes in the outbox, then sends the newly stored ones to the subscribers.
+    pub async fn publish(
+        &self,
+        conn: &mut DbPoolConnection<'_>,
+        events: Vec<ChangeEvent>,
+    ) -> QueryResult<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let _guard = self.publish_lock.lock().await;
+        // Changes already published by a previous attempt of the batch are not stored or sent
+        for change in ChangeEvent::insert_all(conn, &events).await? {
+            // Only fails when no one is subscribed
+            let _ = self.sender.send(Arc::new(change));
+        }
+
+        Ok(())
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<StoredChangeEvent>> {
+        self.sender.subscribe()
+    }
+}
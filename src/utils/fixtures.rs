@@ -0,0 +1,50 @@
+use anyhow::{Context, Result};
+use aptos_indexer_processor_sdk::aptos_protos::transaction::v1::Transaction;
+use prost::Message;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the transaction files read from directories
+const FIXTURE_EXTENSIONS: [&str; 2] = ["json", "pb"];
+
+/// Reads transaction files, each holding one transaction either in the JSON format of the
+/// transaction stream or, with a `.pb` extension, protobuf encoded. Directories are read for
+/// their `.json` and `.pb` files.
+pub fn load_fixtures(paths: &[PathBuf]) -> Result<Vec<(PathBuf, Transaction)>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            entries.retain(|entry| {
+                entry
+                    .extension()
+                    .is_some_and(|ext| FIXTURE_EXTENSIONS.iter().any(|known| ext == *known))
+            });
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|file| {
+            let transaction = read_fixture(&file)?;
+            Ok((file, transaction))
+        })
+        .collect()
+}
+
+fn read_fixture(file: &Path) -> Result<Transaction> {
+    let data = std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    if file.extension().is_some_and(|ext| ext == "pb") {
+        Transaction::decode(data.as_slice())
+            .with_context(|| format!("{} is not a protobuf transaction", file.display()))
+    } else {
+        serde_json::from_slice(&data)
+            .with_context(|| format!("{} is not a JSON transaction", file.display()))
+    }
+}
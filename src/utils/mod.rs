@@ -1,9 +1,14 @@
 use aptos_protos::util::timestamp::Timestamp;
 use bigdecimal::{BigDecimal, Zero};
+use std::str::FromStr;
 use uuid::Uuid;
 
+pub mod event_bus;
+pub mod fixtures;
 pub mod marketplace_resource_utils;
 pub mod object_utils;
+pub mod payment_utils;
+pub mod rarity;
 pub mod token_utils;
 
 pub const MAX_TIMESTAMP_SECS: i64 = 253_402_300_799;
@@ -30,6 +35,15 @@ pub fn calc_royalty(denominator: &BigDecimal, numerator: &BigDecimal) -> BigDeci
     }
 }
 
+/// Parses an on-chain amount, i.e. an unsigned integer of any size such as a Move `u64`.
+pub fn parse_amount(value: &str) -> anyhow::Result<BigDecimal> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        anyhow::bail!("{value:?} is not an unsigned integer");
+    }
+
+    Ok(BigDecimal::from_str(value)?)
+}
+
 pub fn generate_uuid_from_str(value: &str) -> Uuid {
     Uuid::new_v5(&Uuid::NAMESPACE_DNS, value.as_bytes())
 }
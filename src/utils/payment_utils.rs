@@ -0,0 +1,82 @@
+use crate::{
+    models::resources::FromWriteResource,
+    utils::{object_utils::ObjectCore, parse_amount},
+};
+use aptos_indexer_processor_sdk::{
+    aptos_protos::transaction::v1::{transaction::TxnData, write_set_change::Change, Transaction},
+    utils::convert::standardize_address,
+};
+use bigdecimal::BigDecimal;
+use serde_json::Value;
+use std::collections::HashMap;
+
+const COIN_DEPOSIT_EVENT_V1: &str = "0x1::coin::DepositEvent";
+const COIN_DEPOSIT_EVENT: &str = "0x1::coin::CoinDeposit";
+const FA_DEPOSIT_EVENT_V1: &str = "0x1::fungible_asset::DepositEvent";
+const FA_DEPOSIT_EVENT: &str = "0x1::fungible_asset::Deposit";
+
+/// Returns the total amount of coins and fungible assets deposited to each wallet by a
+/// transaction.
+///
+/// Fungible assets are deposited to a store, which is resolved to its owner from the
+/// `ObjectCore` resources written by the transaction. Deposits to stores that the transaction
+/// doesn't write are skipped.
+pub fn get_deposits_by_wallet(txn: &Transaction) -> HashMap<String, BigDecimal> {
+    let mut deposits = HashMap::new();
+    let Some(TxnData::User(user_txn)) = txn.txn_data.as_ref() else {
+        return deposits;
+    };
+
+    let store_owners = get_object_owners(txn);
+    for event in &user_txn.events {
+        let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+            continue;
+        };
+        let event_account = event
+            .key
+            .as_ref()
+            .map(|key| standardize_address(&key.account_address));
+
+        let wallet = match event.type_str.as_str() {
+            COIN_DEPOSIT_EVENT_V1 => event_account,
+            COIN_DEPOSIT_EVENT => data["account"].as_str().map(standardize_address),
+            FA_DEPOSIT_EVENT_V1 => {
+                event_account.and_then(|store| store_owners.get(&store).cloned())
+            },
+            FA_DEPOSIT_EVENT => data["store"]
+                .as_str()
+                .and_then(|store| store_owners.get(&standardize_address(store)).cloned()),
+            _ => continue,
+        };
+        let amount = data["amount"]
+            .as_str()
+            .and_then(|amount| parse_amount(amount).ok());
+
+        if let (Some(wallet), Some(amount)) = (wallet, amount) {
+            *deposits.entry(wallet).or_default() += amount;
+        }
+    }
+
+    deposits
+}
+
+/// Maps the address of each object written by the transaction to its owner.
+fn get_object_owners(txn: &Transaction) -> HashMap<String, String> {
+    let mut owners = HashMap::new();
+    let Some(txn_info) = txn.info.as_ref() else {
+        return owners;
+    };
+
+    for wsc in &txn_info.changes {
+        if let Some(Change::WriteResource(wr)) = wsc.change.as_ref() {
+            if let Ok(Some(object_core)) = ObjectCore::from_write_resource(wr) {
+                owners.insert(
+                    standardize_address(&wr.address),
+                    object_core.get_owner_address(),
+                );
+            }
+        }
+    }
+
+    owners
+}
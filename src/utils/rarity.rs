@@ -0,0 +1,206 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+
+/// Trait type under which the number of traits of an nft is scored
+pub const TRAIT_COUNT_TYPE: &str = "trait_count";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraitRarity {
+    pub count: i64,
+    /// Share of the collection that has the trait
+    pub frequency: f64,
+    /// Rarity score of the trait, the inverse of its frequency
+    pub score: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NftRarityScore {
+    pub nft_id: String,
+    pub trait_count: i64,
+    /// Sum of the trait scores, including the trait count
+    pub rarity_score: f64,
+    /// Product of the trait frequencies, including the trait count
+    pub statistical_rarity: f64,
+    pub rarity_rank: i64,
+    pub statistical_rank: i64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CollectionRarityScores {
+    /// (attr_type, value) -> rarity of the trait
+    pub traits: HashMap<(String, String), TraitRarity>,
+    pub nfts: Vec<NftRarityScore>,
+}
+
+/// Computes trait frequencies and per nft rarity rankings of a collection.
+///
+/// `attributes` are (nft_id, attr_type, value) rows of the collection. The supply defaults to
+/// the number of nfts with attributes when it is unknown or lower.
+pub fn compute_rarity(
+    attributes: &[(String, String, String)],
+    supply: Option<i64>,
+) -> CollectionRarityScores {
+    let mut nft_traits: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    let mut trait_counts: HashMap<(&str, &str), i64> = HashMap::new();
+    for (nft_id, attr_type, value) in attributes {
+        nft_traits
+            .entry(nft_id.as_str())
+            .or_default()
+            .push((attr_type.as_str(), value.as_str()));
+        *trait_counts
+            .entry((attr_type.as_str(), value.as_str()))
+            .or_default() += 1;
+    }
+
+    if nft_traits.is_empty() {
+        return CollectionRarityScores::default();
+    }
+
+    let total = supply.unwrap_or_default().max(nft_traits.len() as i64) as f64;
+
+    let mut trait_count_counts: HashMap<usize, i64> = HashMap::new();
+    for traits in nft_traits.values() {
+        *trait_count_counts.entry(traits.len()).or_default() += 1;
+    }
+
+    let to_rarity = |count: i64| TraitRarity {
+        count,
+        frequency: count as f64 / total,
+        score: total / count as f64,
+    };
+
+    let mut nfts = nft_traits
+        .iter()
+        .map(|(nft_id, traits)| {
+            let trait_count_rarity = to_rarity(trait_count_counts[&traits.len()]);
+            let (rarity_score, statistical_rarity) = traits.iter().fold(
+                (trait_count_rarity.score, trait_count_rarity.frequency),
+                |(score, frequency), key| {
+                    let rarity = to_rarity(trait_counts[key]);
+                    (score + rarity.score, frequency * rarity.frequency)
+                },
+            );
+
+            NftRarityScore {
+                nft_id: nft_id.to_string(),
+                trait_count: traits.len() as i64,
+                rarity_score,
+                statistical_rarity,
+                rarity_rank: 0,
+                statistical_rank: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Highest rarity score first
+    nfts.sort_by(|a, b| {
+        b.rarity_score
+            .partial_cmp(&a.rarity_score)
+            .unwrap_or(Ordering::Equal)
+    });
+    assign_ranks(&mut nfts, |nft| nft.rarity_score, |nft, rank| {
+        nft.rarity_rank = rank
+    });
+
+    // Lowest probability first
+    nfts.sort_by(|a, b| {
+        a.statistical_rarity
+            .partial_cmp(&b.statistical_rarity)
+            .unwrap_or(Ordering::Equal)
+    });
+    assign_ranks(&mut nfts, |nft| nft.statistical_rarity, |nft, rank| {
+        nft.statistical_rank = rank
+    });
+
+    let mut traits: HashMap<(String, String), TraitRarity> = trait_counts
+        .into_iter()
+        .map(|((attr_type, value), count)| {
+            ((attr_type.to_string(), value.to_string()), to_rarity(count))
+        })
+        .collect();
+    for (trait_count, count) in trait_count_counts {
+        traits.insert(
+            (TRAIT_COUNT_TYPE.to_string(), trait_count.to_string()),
+            to_rarity(count),
+        );
+    }
+
+    CollectionRarityScores { traits, nfts }
+}
+
+/// Assigns 1-based ranks to sorted nfts, equal values share the same rank.
+fn assign_ranks(
+    nfts: &mut [NftRarityScore],
+    value: impl Fn(&NftRarityScore) -> f64,
+    set_rank: impl Fn(&mut NftRarityScore, i64),
+) {
+    let mut previous: Option<(f64, i64)> = None;
+    for (index, nft) in nfts.iter_mut().enumerate() {
+        let current = value(nft);
+        let rank = match previous {
+            Some((previous_value, previous_rank)) if previous_value == current => previous_rank,
+            _ => index as i64 + 1,
+        };
+
+        set_rank(nft, rank);
+        previous = Some((current, rank));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(nft_id: &str, attr_type: &str, value: &str) -> (String, String, String) {
+        (nft_id.to_string(), attr_type.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_compute_rarity() {
+        let attributes = vec![
+            attribute("0x1", "hat", "red"),
+            attribute("0x1", "eyes", "blue"),
+            attribute("0x2", "hat", "red"),
+            attribute("0x2", "eyes", "blue"),
+            attribute("0x3", "hat", "gold"),
+        ];
+
+        let rarity = compute_rarity(&attributes, Some(4));
+
+        let red_hat = &rarity.traits[&("hat".to_string(), "red".to_string())];
+        assert_eq!(red_hat.count, 2);
+        assert_eq!(red_hat.frequency, 0.5);
+        assert_eq!(red_hat.score, 2.0);
+
+        let one_trait = &rarity.traits[&(TRAIT_COUNT_TYPE.to_string(), "1".to_string())];
+        assert_eq!(one_trait.count, 1);
+
+        // 0x3 has the rarest hat and is the only nft with a single trait
+        let rarest = &rarity.nfts[0];
+        assert_eq!(rarest.nft_id, "0x3");
+        assert_eq!(rarest.rarity_rank, 1);
+        assert_eq!(rarest.statistical_rank, 1);
+    }
+
+    #[test]
+    fn test_equal_nfts_share_rank() {
+        let attributes = vec![
+            attribute("0x1", "hat", "red"),
+            attribute("0x2", "hat", "red"),
+            attribute("0x3", "hat", "gold"),
+        ];
+
+        let rarity = compute_rarity(&attributes, None);
+        let ranks: HashMap<String, i64> = rarity
+            .nfts
+            .iter()
+            .map(|nft| (nft.nft_id.clone(), nft.rarity_rank))
+            .collect();
+
+        assert_eq!(ranks["0x3"], 1);
+        assert_eq!(ranks["0x1"], 2);
+        assert_eq!(ranks["0x2"], 2);
+    }
+}
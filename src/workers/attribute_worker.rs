@@ -1,133 +1,293 @@
 use crate::{
+    config::worker_config::AttributeWorkerConfig,
     models::{
-        db::{attributes::Attribute, nft::Nft},
+        db::{
+            attributes::Attribute,
+            nft::{Nft, METADATA_STATUS_FETCHED},
+            rarity::{insert_attribute_changes, CollectionRarity},
+        },
         nft_metadata::NFTMetadata,
     },
     postgres::postgres_utils::{execute_in_chunks, ArcDbPool},
     schema,
 };
+use ahash::AHashSet;
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
 use diesel::{pg::Pg, query_builder::QueryFragment, upsert::excluded, ExpressionMethods};
-use futures::future::join_all;
-use std::{collections::HashMap, time::Duration};
+use futures::{stream, StreamExt};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use std::time::Duration;
 use tokio::time::sleep;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
+
+/// Largest metadata json downloaded, larger bodies are likely media
+const MAX_METADATA_BYTES: usize = 1024 * 1024;
+/// Upper bound of the backoff between two retries of a request
+const MAX_RETRY_BACKOFF_MS: u64 = 60_000;
 
 pub struct AttributeWorker {
+    config: AttributeWorkerConfig,
+    client: Client,
     db_pool: ArcDbPool,
 }
 
 impl AttributeWorker {
-    pub fn new(db_pool: ArcDbPool) -> Self {
-        Self { db_pool }
+    pub fn new(config: AttributeWorkerConfig, db_pool: ArcDbPool) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.request_timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            config,
+            client,
+            db_pool,
+        })
     }
 
     pub async fn start(&self) -> anyhow::Result<()> {
         info!("Attribute worker is starting!");
 
         loop {
-            let result = self.process_attributes().await;
-            if let Err(e) = result {
-                error!("Error while processing tokens: {:?}", e);
+            match self.process_attributes().await {
+                // Keep going without waiting while there is a backlog of nfts
+                Ok(processed) if processed as i64 >= self.config.batch_size => continue,
+                Ok(_) => (),
+                Err(e) => error!("Error while processing tokens: {:?}", e),
             }
 
-            sleep(Duration::from_secs(60)).await;
+            sleep(Duration::from_secs(self.config.poll_interval_secs)).await;
         }
     }
 
-    async fn process_attributes(&self) -> anyhow::Result<()> {
-        let mut conn = self.db_pool.get().await?;
+    /// Fetches the metadata of a batch of nfts that were never fetched, are due for a retry or
+    /// went stale. Returns the number of nfts processed.
+    async fn process_attributes(&self) -> anyhow::Result<usize> {
+        let now = Utc::now().naive_utc();
+        let retry_before = now - ChronoDuration::seconds(self.config.retry_after_secs);
+        let stale_before = now - ChronoDuration::seconds(self.config.stale_after_secs);
 
-        let total_attributes = Nft::count_nfts(&mut conn).await?;
-        let batch_size = 20i64;
-        let mut offset = 0;
+        let nfts = {
+            let mut conn = self.db_pool.get().await?;
+            Nft::get_nfts_pending_metadata(
+                &mut conn,
+                retry_before,
+                stale_before,
+                self.config.max_attempts,
+                self.config.batch_size,
+            )
+            .await?
+        };
 
-        if total_attributes <= 0 {
-            return Ok(());
+        if nfts.is_empty() {
+            return Ok(0);
         }
 
-        while offset < total_attributes {
-            let mut nfts = Nft::get_nfts(&mut conn, offset, batch_size).await?;
-            let nft_metadata_fut = nfts.iter().map(|nft| async move {
-                let image_url = nft.image_url.as_ref().unwrap();
-                let response = reqwest::get(image_url).await;
-                if response.is_err() {
-                    return (nft.id.clone(), None);
-                }
-
-                let value = response.unwrap().json::<NFTMetadata>().await;
-                if value.is_err() {
-                    return (nft.id.clone(), None);
-                }
-
-                (nft.id.clone(), Some(value.unwrap()))
-            });
-
-            let nft_metadata = join_all(nft_metadata_fut).await.into_iter().fold(
-                HashMap::new(),
-                |mut acc, item| {
-                    let (nft_id, nft_metadata) = item;
-                    if let Some(nft_metadata) = nft_metadata {
-                        acc.insert(nft_id, nft_metadata);
-                    }
-
-                    acc
+        let results = stream::iter(nfts)
+            .map(|nft| async move {
+                let uri = nft.uri.clone().unwrap_or_default();
+                let result = self.fetch_metadata(&uri).await;
+                (nft, result)
+            })
+            .buffer_unordered(self.config.concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        let processed = results.len();
+        let mut fetched_nfts = Vec::new();
+        let mut attributes = Vec::new();
+        let mut conn = self.db_pool.get().await?;
+
+        for (mut nft, result) in results {
+            match result {
+                Ok(nft_metadata) => {
+                    attributes.extend(apply_metadata(&mut nft, nft_metadata, now));
+                    fetched_nfts.push(nft);
+                },
+                Err(FetchError::Unsupported(reason)) => {
+                    debug!(nft_id = %nft.id, "Nft uri doesn't serve metadata: {}", reason);
+                    Nft::mark_metadata_unsupported(&mut conn, &nft.id, &reason, now).await?;
+                },
+                Err(FetchError::Request(e)) => {
+                    warn!(nft_id = %nft.id, "Failed to fetch nft metadata: {:?}", e);
+                    Nft::mark_metadata_failed(&mut conn, &nft.id, &e.to_string(), now).await?;
                 },
-            );
-
-            let mut attributes = Vec::new();
-
-            for nft in nfts.iter_mut() {
-                if let Some(nft_metadata) = nft_metadata.get(&nft.id).cloned() {
-                    nft.image_url = nft_metadata.image;
-                    nft.youtube_url = nft_metadata.youtube_url;
-                    nft.background_color = nft_metadata.background_color;
-                    nft.external_url = nft_metadata.external_url;
-                    nft.animation_url = nft_metadata.animation_url;
-                    nft.avatar_url = nft_metadata.avatar_url;
-                    nft.image_data = nft_metadata.image_data;
-                    if nft.name.is_none() {
-                        nft.name = nft_metadata.name;
-                    }
-
-                    if nft.description.is_none() {
-                        nft.description = nft_metadata.description;
-                    }
-
-                    for attribute in nft_metadata.attributes {
-                        let attribute = Attribute {
-                            collection_id: nft.collection_id.clone(),
-                            nft_id: Some(nft.id.clone()),
-                            attr_type: Some(attribute.trait_type.to_lowercase()),
-                            value: Some(attribute.value.to_lowercase()),
-                            score: None,
-                            rarity: None,
-                        };
-
-                        attributes.push(attribute);
-                    }
-                }
             }
+        }
+
+        if let Err(e) =
+            execute_in_chunks(self.db_pool.clone(), insert_nfts, &fetched_nfts, 200).await
+        {
+            error!("Failed to store: {:?}", e);
+        }
+
+        // Refetched metadata replaces the previous traits, including the ones it dropped
+        let fetched_nft_ids = fetched_nfts
+            .iter()
+            .map(|nft| nft.id.clone())
+            .collect::<Vec<_>>();
+        if let Err(e) = Attribute::replace_for_nfts(&mut conn, &fetched_nft_ids, &attributes).await
+        {
+            error!("Failed to store attributes: {:?}", e);
+        }
 
-            let nft_fut = execute_in_chunks(self.db_pool.clone(), insert_nfts, &nfts, 200);
-            let attribute_fut =
-                execute_in_chunks(self.db_pool.clone(), insert_attributes, &attributes, 200);
+        // Changed attributes change the rarity of their collections
+        let attribute_changes = fetched_nfts
+            .iter()
+            .filter_map(|nft| nft.collection_id.clone())
+            .collect::<AHashSet<_>>()
+            .into_iter()
+            .map(|collection_id| CollectionRarity::new(collection_id, None, now))
+            .collect::<Vec<_>>();
+        if let Err(e) = execute_in_chunks(
+            self.db_pool.clone(),
+            insert_attribute_changes,
+            &attribute_changes,
+            200,
+        )
+        .await
+        {
+            error!("Failed to mark collections for rarity: {:?}", e);
+        }
+
+        Ok(processed)
+    }
 
-            let (nft_result, attribute_result) = tokio::join!(nft_fut, attribute_fut);
+    /// Fetches the metadata json, retrying transient failures with exponential backoff.
+    async fn fetch_metadata(&self, uri: &str) -> Result<NFTMetadata, FetchError> {
+        let mut attempt = 0;
 
-            for result in [nft_result, attribute_result] {
-                match result {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error!("Failed to store: {:?}", e);
-                    },
-                }
+        loop {
+            match self.try_fetch_metadata(uri).await {
+                Err(FetchError::Request(e))
+                    if attempt < self.config.request_retries && is_retryable(&e) =>
+                {
+                    let backoff = retry_backoff_ms(self.config.retry_backoff_ms, attempt);
+                    debug!("Retrying {} in {}ms: {:?}", uri, backoff, e);
+                    sleep(Duration::from_millis(backoff)).await;
+                    attempt += 1;
+                },
+                result => return result,
             }
+        }
+    }
 
-            offset += nfts.len() as i64;
+    /// Fetches and parses the metadata json. Media content types and bodies that are too large
+    /// or not json are unsupported, without downloading more than `MAX_METADATA_BYTES`.
+    async fn try_fetch_metadata(&self, uri: &str) -> Result<NFTMetadata, FetchError> {
+        let mut response = self.client.get(uri).send().await?.error_for_status()?;
+
+        if let Some(content_type) = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            if is_media_content_type(content_type) {
+                return Err(FetchError::Unsupported(format!(
+                    "Content type is {content_type}"
+                )));
+            }
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > MAX_METADATA_BYTES {
+                return Err(FetchError::Unsupported(format!(
+                    "Body is larger than {MAX_METADATA_BYTES} bytes"
+                )));
+            }
+            body.extend_from_slice(&chunk);
         }
 
-        Ok(())
+        serde_json::from_slice::<NFTMetadata>(&body)
+            .map_err(|e| FetchError::Unsupported(format!("Body is not metadata json: {e}")))
+    }
+}
+
+/// Why the metadata of an nft couldn't be fetched
+#[derive(Debug)]
+enum FetchError {
+    /// The uri doesn't serve metadata, fetching it again won't help
+    Unsupported(String),
+    Request(reqwest::Error),
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Request(e)
+    }
+}
+
+/// Returns true for the content types of media, which some uris point to instead of the
+/// metadata json.
+fn is_media_content_type(content_type: &str) -> bool {
+    let content_type = content_type.trim().to_ascii_lowercase();
+    ["image/", "video/", "audio/", "model/", "font/"]
+        .iter()
+        .any(|prefix| content_type.starts_with(prefix))
+}
+
+/// Backoff before the retry after `attempt`, doubled on every attempt up to
+/// `MAX_RETRY_BACKOFF_MS`.
+fn retry_backoff_ms(base_ms: u64, attempt: u32) -> u64 {
+    base_ms
+        .saturating_mul(2u64.saturating_pow(attempt))
+        .min(MAX_RETRY_BACKOFF_MS)
+}
+
+fn is_retryable(e: &reqwest::Error) -> bool {
+    e.is_timeout()
+        || e.is_connect()
+        || e.status().map_or(false, |status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+/// Copies the fetched metadata into the nft and returns its attributes.
+fn apply_metadata(
+    nft: &mut Nft,
+    nft_metadata: NFTMetadata,
+    fetched_at: NaiveDateTime,
+) -> Vec<Attribute> {
+    if nft_metadata.image.is_some() {
+        nft.image_url = nft_metadata.image;
     }
+
+    nft.youtube_url = nft_metadata.youtube_url;
+    nft.background_color = nft_metadata.background_color;
+    nft.external_url = nft_metadata.external_url;
+    nft.animation_url = nft_metadata.animation_url;
+    nft.avatar_url = nft_metadata.avatar_url;
+    nft.image_data = nft_metadata.image_data;
+    if nft.name.is_none() {
+        nft.name = nft_metadata.name;
+    }
+
+    if nft.description.is_none() {
+        nft.description = nft_metadata.description;
+    }
+
+    nft.metadata_status = Some(METADATA_STATUS_FETCHED.to_string());
+    nft.metadata_attempts = Some(0);
+    nft.metadata_error = None;
+    nft.metadata_updated_at = Some(fetched_at);
+
+    // Attributes are keyed by collection, so they can't be stored without one
+    if nft.collection_id.is_none() {
+        return vec![];
+    }
+
+    nft_metadata
+        .attributes
+        .into_iter()
+        .map(|attribute| Attribute {
+            collection_id: nft.collection_id.clone(),
+            nft_id: Some(nft.id.clone()),
+            attr_type: Some(attribute.trait_type.to_lowercase()),
+            value: Some(attribute.value.to_lowercase()),
+            score: None,
+            rarity: None,
+        })
+        .collect()
 }
 
 pub fn insert_nfts(
@@ -149,16 +309,32 @@ pub fn insert_nfts(
             youtube_url.eq(excluded(youtube_url)),
             avatar_url.eq(excluded(avatar_url)),
             external_url.eq(excluded(external_url)),
+            metadata_status.eq(excluded(metadata_status)),
+            metadata_attempts.eq(excluded(metadata_attempts)),
+            metadata_error.eq(excluded(metadata_error)),
+            metadata_updated_at.eq(excluded(metadata_updated_at)),
         ))
 }
 
-pub fn insert_attributes(
-    items_to_insert: Vec<Attribute>,
-) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
-    use crate::schema::attributes::dsl::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    diesel::insert_into(schema::attributes::table)
-        .values(items_to_insert)
-        .on_conflict((collection_id, nft_id, attr_type, value))
-        .do_nothing()
+    #[test]
+    fn test_retry_backoff_is_capped() {
+        assert_eq!(retry_backoff_ms(500, 0), 500);
+        assert_eq!(retry_backoff_ms(500, 3), 4000);
+        assert_eq!(retry_backoff_ms(500, 64), MAX_RETRY_BACKOFF_MS);
+        assert_eq!(retry_backoff_ms(u64::MAX, 1), MAX_RETRY_BACKOFF_MS);
+    }
+
+    #[test]
+    fn test_is_media_content_type() {
+        assert!(is_media_content_type("image/png"));
+        assert!(is_media_content_type("Video/MP4"));
+        assert!(!is_media_content_type("application/json; charset=utf-8"));
+        // Some gateways serve json without a precise content type
+        assert!(!is_media_content_type("text/plain"));
+        assert!(!is_media_content_type("application/octet-stream"));
+    }
 }
@@ -0,0 +1,168 @@
+use crate::{
+    models::{
+        change_event::{ChangeEvent, ChangeEventType},
+        db::{
+            bid::Bid, collection_stats::refresh_collection_stats, listing::Listing,
+            market_depth::MarketDepthChanges,
+        },
+    },
+    postgres::postgres_utils::ArcDbPool,
+    utils::event_bus::EventBus,
+    workers::{get_min_progress, ProcessorProgress},
+};
+use chrono::NaiveDateTime;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+const POLL_INTERVAL_SECS: u64 = 30;
+
+/// Expires the bids and listings whose `expires_at` has passed.
+///
+/// Expiry is measured against chain time, the timestamp of the last transaction processed by
+/// the marketplace's processor, rather than wall clock, so an order is only expired once the
+/// indexer has seen every event before its expiry and a backfill expires the same orders as a
+/// live run. The chain time of a marketplace with several deployments is the oldest of its
+/// running deployments, the same rule the wash trade worker uses for its versions, since a
+/// retired deployment stops at its ending version while one that is still catching up hasn't
+/// seen the events after its timestamp yet. Marketplaces with a deployment without a processor
+/// status yet are skipped. The stats of the collections with expired listings are refreshed,
+/// and the expired orders are published to the subscribers of real-time changes.
+pub struct ExpiryWorker {
+    db_pool: ArcDbPool,
+    /// Name of each marketplace with the checkpoint names and ending versions of its deployments
+    marketplaces: Vec<(String, Vec<(String, Option<u64>)>)>,
+    event_bus: EventBus,
+}
+
+impl ExpiryWorker {
+    pub fn new(
+        db_pool: ArcDbPool,
+        marketplaces: Vec<(String, Vec<(String, Option<u64>)>)>,
+        event_bus: EventBus,
+    ) -> Self {
+        Self {
+            db_pool,
+            marketplaces,
+            event_bus,
+        }
+    }
+
+    pub async fn start(&self) {
+        info!("Expiry worker is starting!");
+
+        loop {
+            for (marketplace, deployments) in &self.marketplaces {
+                if let Err(e) = self.expire_orders(marketplace, deployments).await {
+                    error!("Error while expiring {} orders: {:?}", marketplace, e);
+                }
+            }
+
+            sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        }
+    }
+
+    async fn expire_orders(
+        &self,
+        marketplace: &str,
+        deployments: &[(String, Option<u64>)],
+    ) -> anyhow::Result<()> {
+        let mut conn = self.db_pool.get().await?;
+        let mut progress = vec![];
+        for (checkpoint, ending_version) in deployments {
+            progress.push(ProcessorProgress::get(&mut conn, checkpoint, *ending_version).await?);
+        }
+        let Some(chain_time) = get_chain_time(&progress) else {
+            return Ok(());
+        };
+
+        let expired_bids = Bid::expire(&mut conn, marketplace, chain_time).await?;
+        let expired_listings = Listing::expire(&mut conn, marketplace, chain_time).await?;
+        if expired_bids.is_empty() && expired_listings.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Expired {} bids and {} listings of {} at {}",
+            expired_bids.len(),
+            expired_listings.len(),
+            marketplace,
+            chain_time
+        );
+
+        let mut depth_changes = MarketDepthChanges::default();
+        expired_bids
+            .iter()
+            .for_each(|bid| depth_changes.add_bid(bid));
+        expired_listings
+            .iter()
+            .for_each(|listing| depth_changes.add_listing(listing));
+        depth_changes.refresh(&mut conn).await?;
+        refresh_collection_stats(
+            &mut conn,
+            expired_listings
+                .iter()
+                .filter_map(|listing| listing.collection_id.clone()),
+        )
+        .await?;
+
+        let events =
+            expired_bids
+                .iter()
+                .map(|bid| ChangeEvent::from_bid(bid, ChangeEventType::BidExpired))
+                .chain(expired_listings.iter().map(|listing| {
+                    ChangeEvent::from_listing(listing, ChangeEventType::ListingExpired)
+                }))
+                .collect();
+        self.event_bus.publish(&mut conn, events).await?;
+
+        Ok(())
+    }
+}
+
+/// Returns the oldest timestamp processed by the running deployments of a marketplace.
+fn get_chain_time(progress: &[ProcessorProgress]) -> Option<NaiveDateTime> {
+    get_min_progress(progress, |deployment| deployment.last_transaction_timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn timestamp(secs: i64) -> NaiveDateTime {
+        DateTime::from_timestamp(secs, 0).unwrap().naive_utc()
+    }
+
+    fn progress(version: Option<i64>, secs: i64, ending_version: Option<u64>) -> ProcessorProgress {
+        ProcessorProgress {
+            last_success_version: version,
+            last_transaction_timestamp: version.map(|_| timestamp(secs)),
+            ending_version,
+        }
+    }
+
+    #[test]
+    fn test_get_chain_time() {
+        // A retired v1 stays at its ending version while v2 keeps going
+        let retired_v1 = progress(Some(100), 1_000, Some(100));
+        let active_v2 = progress(Some(500), 2_000, None);
+        assert_eq!(
+            get_chain_time(&[retired_v1, active_v2]),
+            Some(timestamp(2_000))
+        );
+
+        // A v1 still catching up holds back the expiry of orders it hasn't seen the events of
+        let catching_up_v1 = progress(Some(50), 500, Some(100));
+        let active_v2 = progress(Some(500), 2_000, None);
+        assert_eq!(
+            get_chain_time(&[catching_up_v1, active_v2]),
+            Some(timestamp(500))
+        );
+
+        // Nothing is expired until every deployment has processed a batch
+        let retired_v1 = progress(Some(100), 1_000, Some(100));
+        let new_v2 = progress(None, 0, None);
+        assert_eq!(get_chain_time(&[retired_v1, new_v2]), None);
+    }
+}
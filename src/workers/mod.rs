@@ -1,2 +1,89 @@
+use aptos_indexer_processor_sdk::postgres::{
+    models::processor_status::ProcessorStatusQuery, utils::database::DbPoolConnection,
+};
+use chrono::NaiveDateTime;
+use diesel::QueryResult;
+use std::{future::Future, time::Duration};
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{error, warn};
+
 pub mod attribute_worker;
+pub mod expiry_worker;
+pub mod outbox_worker;
 pub mod price_worker;
+pub mod rarity_worker;
+pub mod stats_worker;
+pub mod wash_trade_worker;
+pub mod webhook_worker;
+
+/// Delay before a worker that stopped is started again
+pub const WORKER_RESTART_DELAY_SECS: u64 = 10;
+
+/// Runs a background worker as a managed task, restarting it if it panics or returns.
+pub fn spawn_worker<F, Fut>(name: &'static str, run: F) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            match tokio::spawn(run()).await {
+                Ok(()) => warn!(worker = name, "Worker stopped, restarting"),
+                Err(e) => error!(worker = name, "Worker panicked, restarting: {:?}", e),
+            }
+
+            sleep(Duration::from_secs(WORKER_RESTART_DELAY_SECS)).await;
+        }
+    })
+}
+
+/// Progress of a processor from `processor_status`, and the version it stops at if any
+pub struct ProcessorProgress {
+    pub last_success_version: Option<i64>,
+    pub last_transaction_timestamp: Option<NaiveDateTime>,
+    pub ending_version: Option<u64>,
+}
+
+impl ProcessorProgress {
+    pub async fn get(
+        conn: &mut DbPoolConnection<'_>,
+        processor: &str,
+        ending_version: Option<u64>,
+    ) -> QueryResult<Self> {
+        let status = ProcessorStatusQuery::get_by_processor(processor, conn).await?;
+
+        Ok(Self {
+            last_success_version: status.as_ref().map(|status| status.last_success_version),
+            last_transaction_timestamp: status.and_then(|status| status.last_transaction_timestamp),
+            ending_version,
+        })
+    }
+
+    pub fn is_retired(&self) -> bool {
+        match (self.last_success_version, self.ending_version) {
+            (Some(version), Some(ending_version)) => version as u64 >= ending_version,
+            _ => false,
+        }
+    }
+}
+
+/// Returns the lowest `value` of the processors that are still running, or `None` if one of
+/// them hasn't stored any batch yet. A processor that is still catching up with its ending
+/// version holds the others back, while a retired one doesn't. When every processor is
+/// retired, the lowest of their values is used.
+pub fn get_min_progress<T: Ord + Copy>(
+    progress: &[ProcessorProgress],
+    value: impl Fn(&ProcessorProgress) -> Option<T>,
+) -> Option<T> {
+    let mut values = vec![];
+    for processor in progress {
+        values.push((value(processor)?, processor.is_retired()));
+    }
+
+    values
+        .iter()
+        .filter(|(_, is_retired)| !is_retired)
+        .map(|(value, _)| *value)
+        .min()
+        .or_else(|| values.iter().map(|(value, _)| *value).min())
+}
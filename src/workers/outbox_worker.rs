@@ -0,0 +1,42 @@
+use crate::{models::change_event::ChangeEvent, postgres::postgres_utils::ArcDbPool};
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+/// Days the published changes are kept for clients to resume from
+pub const CHANGE_EVENT_RETENTION_DAYS: i64 = 7;
+const POLL_INTERVAL_SECS: u64 = 3600;
+
+/// Prunes the `change_events` outbox, outside of the publish path of the writing steps.
+pub struct OutboxWorker {
+    db_pool: ArcDbPool,
+}
+
+impl OutboxWorker {
+    pub fn new(db_pool: ArcDbPool) -> Self {
+        Self { db_pool }
+    }
+
+    pub async fn start(&self) {
+        info!("Outbox worker is starting!");
+
+        loop {
+            match self.prune().await {
+                Ok(0) => (),
+                Ok(count) => info!("Pruned {} published changes", count),
+                Err(e) => error!("Error while pruning published changes: {:?}", e),
+            }
+
+            sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        }
+    }
+
+    async fn prune(&self) -> anyhow::Result<usize> {
+        let mut conn = self.db_pool.get().await?;
+        let created_before =
+            Utc::now().naive_utc() - ChronoDuration::days(CHANGE_EVENT_RETENTION_DAYS);
+
+        Ok(ChangeEvent::delete_before(&mut conn, created_before).await?)
+    }
+}
@@ -1,5 +1,13 @@
 use crate::{
-    models::db::price::Price as PostgrePrice, postgres::postgres_utils::ArcDbPool, schema,
+    config::payment_token_config::{PaymentTokenConfig, PriceSourceConfig},
+    models::db::{
+        action::Action,
+        collection_stats::CollectionStats,
+        payment_token::{normalize_payment_token, APT_COIN_TYPE},
+        price::Price as PostgrePrice,
+    },
+    postgres::postgres_utils::ArcDbPool,
+    schema,
 };
 use aptos_indexer_processor_sdk::{
     postgres::utils::database::execute_in_chunks, utils::convert::deserialize_from_string,
@@ -13,6 +21,9 @@ use std::time::Duration;
 use tokio::time::sleep;
 use tracing::{error, info};
 
+/// Maximum number of sale actions repaired per backfill query
+const USD_PRICE_BACKFILL_BATCH_SIZE: i64 = 1000;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Price {
@@ -34,76 +45,56 @@ pub struct PriceResponse {
     result: Price,
 }
 
-pub struct PriceWorker {
+/// Tapp index of the APT/USD price, used for APT unless the config sets another source
+const APT_USD_INDEX: &str =
+    "0x000000000000000000000000000000000000000000000000000000000000000a_usd";
+
+/// Provides the current USD price of one whole payment token.
+#[async_trait::async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Returns `None` when the price is unavailable, the candle is then skipped.
+    async fn fetch_price(&self) -> anyhow::Result<Option<BigDecimal>>;
+}
+
+/// Index price from the JSON-RPC endpoint at `tapp_url`.
+pub struct TappPriceSource {
     tapp_url: String,
+    index: String,
     client: Client,
-    db_pool: ArcDbPool,
 }
 
-impl PriceWorker {
-    pub fn new(url: &str, db_pool: ArcDbPool) -> Self {
+impl TappPriceSource {
+    pub fn new(tapp_url: &str, index: &str, client: Client) -> Self {
         Self {
-            tapp_url: url.to_string(),
-            client: Client::new(),
-            db_pool,
-        }
-    }
-
-    pub async fn start(&self) {
-        info!("Price worker is starting!");
-
-        loop {
-            let now = Utc::now();
-            let rounded = Utc.with_ymd_and_hms(
-                now.year(),
-                now.month(),
-                now.day(),
-                now.hour(),
-                now.minute(),
-                0,
-            );
-
-            let price_res = self.fetch_price().await.unwrap();
-
-            if let Some(price) = price_res {
-                let pg_price = PostgrePrice {
-                    price,
-                    created_at: rounded.unwrap().naive_utc(),
-                };
-
-                let prices = vec![pg_price];
-                match execute_in_chunks(self.db_pool.clone(), insert_price, &prices, 200).await {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error!("{:#?}", e);
-                    },
-                };
-            }
-
-            let _ = sleep(Duration::from_secs(300)).await;
+            tapp_url: tapp_url.to_string(),
+            index: index.to_string(),
+            client,
         }
     }
+}
 
+#[async_trait::async_trait]
+impl PriceSource for TappPriceSource {
     async fn fetch_price(&self) -> anyhow::Result<Option<BigDecimal>> {
         let body = serde_json::json!({
             "method": "public/get_index_price",
             "jsonrpc": "2.0",
             "id": 16222222,
             "params": {
-                "name": "0x000000000000000000000000000000000000000000000000000000000000000a_usd"
+                "name": self.index
             }
         });
 
         let response_res = self.client.post(&self.tapp_url).json(&body).send().await;
         if let Err(e) = response_res {
-            error!("Failed to fetch price: {:?}", e);
+            error!("Failed to fetch price of {}: {:?}", self.index, e);
 
             return Ok(None);
         }
 
         let value_res = response_res.unwrap().json::<PriceResponse>().await;
         if let Err(e) = value_res {
-            error!("Failed to parse price value: {:?}", e);
+            error!("Failed to parse price value of {}: {:?}", self.index, e);
 
             return Ok(None);
         }
@@ -114,6 +105,120 @@ impl PriceWorker {
     }
 }
 
+/// Constant price, e.g. for a USD stablecoin.
+pub struct FixedPriceSource {
+    usd_price: BigDecimal,
+}
+
+impl FixedPriceSource {
+    pub fn new(usd_price: BigDecimal) -> Self {
+        Self { usd_price }
+    }
+}
+
+#[async_trait::async_trait]
+impl PriceSource for FixedPriceSource {
+    async fn fetch_price(&self) -> anyhow::Result<Option<BigDecimal>> {
+        Ok(Some(self.usd_price.clone()))
+    }
+}
+
+/// Records a USD price candle of every payment token with a price source every 5 minutes.
+pub struct PriceWorker {
+    /// Payment token -> where its price comes from
+    sources: Vec<(String, Box<dyn PriceSource>)>,
+    db_pool: ArcDbPool,
+}
+
+impl PriceWorker {
+    pub fn new(url: &str, payment_tokens: &[PaymentTokenConfig], db_pool: ArcDbPool) -> Self {
+        let client = Client::new();
+        let mut sources: Vec<(String, Box<dyn PriceSource>)> = vec![];
+        for payment_token in payment_tokens {
+            let source: Box<dyn PriceSource> = match &payment_token.price_source {
+                Some(PriceSourceConfig::Tapp { index }) => {
+                    Box::new(TappPriceSource::new(url, index, client.clone()))
+                },
+                Some(PriceSourceConfig::Fixed { usd_price }) => {
+                    Box::new(FixedPriceSource::new(usd_price.clone()))
+                },
+                None => continue,
+            };
+            sources.push((normalize_payment_token(&payment_token.id), source));
+        }
+
+        if !sources.iter().any(|(id, _)| id == APT_COIN_TYPE) {
+            sources.push((
+                APT_COIN_TYPE.to_string(),
+                Box::new(TappPriceSource::new(url, APT_USD_INDEX, client)),
+            ));
+        }
+
+        Self { sources, db_pool }
+    }
+
+    pub async fn start(&self) {
+        info!("Price worker is starting!");
+
+        loop {
+            let now = Utc::now();
+            let rounded = Utc.with_ymd_and_hms(
+                now.year(),
+                now.month(),
+                now.day(),
+                now.hour(),
+                now.minute(),
+                0,
+            );
+
+            let mut prices = vec![];
+            for (payment_token, source) in &self.sources {
+                match source.fetch_price().await {
+                    Ok(Some(price)) => prices.push(PostgrePrice {
+                        price,
+                        created_at: rounded.unwrap().naive_utc(),
+                        payment_token: payment_token.clone(),
+                    }),
+                    Ok(None) => (),
+                    Err(e) => error!("Failed to fetch price of {}: {:?}", payment_token, e),
+                }
+            }
+
+            match execute_in_chunks(self.db_pool.clone(), insert_price, &prices, 200).await {
+                Ok(_) => (),
+                Err(e) => {
+                    error!("{:#?}", e);
+                },
+            };
+
+            if let Err(e) = self.backfill_usd_prices().await {
+                error!("Failed to backfill usd prices: {:?}", e);
+            }
+
+            let _ = sleep(Duration::from_secs(300)).await;
+        }
+    }
+
+    /// Repairs the usd price of historical sales once the price history covers them.
+    async fn backfill_usd_prices(&self) -> anyhow::Result<()> {
+        let mut conn = self.db_pool.get().await?;
+
+        loop {
+            let keys =
+                Action::backfill_usd_prices(&mut conn, USD_PRICE_BACKFILL_BATCH_SIZE).await?;
+            if !keys.is_empty() {
+                info!("Backfilled usd price of {} actions", keys.len());
+                // The usd volumes of their collections were missing these sales
+                CollectionStats::count_sales(&mut conn, &keys).await?;
+            }
+
+            if (keys.len() as i64) < USD_PRICE_BACKFILL_BATCH_SIZE {
+                return Ok(());
+            }
+        }
+    }
+}
+
 fn insert_price(
     items_to_insert: Vec<PostgrePrice>,
 ) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
@@ -121,6 +226,6 @@ fn insert_price(
 
     diesel::insert_into(schema::prices::table)
         .values(items_to_insert)
-        .on_conflict(created_at)
+        .on_conflict((payment_token, created_at))
         .do_nothing()
 }
@@ -0,0 +1,134 @@
+use crate::{
+    models::db::{
+        attributes::Attribute,
+        rarity::{insert_nft_rarities, CollectionRarity, NftRarity},
+    },
+    postgres::postgres_utils::{execute_in_chunks, ArcDbPool},
+    schema,
+    utils::rarity::compute_rarity,
+};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use bigdecimal::{BigDecimal, FromPrimitive};
+use chrono::{Duration as ChronoDuration, Utc};
+use diesel::{pg::Pg, query_builder::QueryFragment, upsert::excluded, ExpressionMethods};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+const POLL_INTERVAL_SECS: u64 = 30;
+/// Collections are recomputed once they have had no changes for this long, so that a
+/// collection whose attributes are still being fetched isn't recomputed on every batch
+const DEBOUNCE_SECS: i64 = 60;
+const BATCH_SIZE: i64 = 10;
+const SCORE_SCALE: i64 = 10;
+
+pub struct RarityWorker {
+    db_pool: ArcDbPool,
+}
+
+impl RarityWorker {
+    pub fn new(db_pool: ArcDbPool) -> Self {
+        Self { db_pool }
+    }
+
+    pub async fn start(&self) {
+        info!("Rarity worker is starting!");
+
+        loop {
+            if let Err(e) = self.process_rarities().await {
+                error!("Error while computing rarities: {:?}", e);
+            }
+
+            sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        }
+    }
+
+    async fn process_rarities(&self) -> anyhow::Result<()> {
+        let mut conn = self.db_pool.get().await?;
+        let marked_before = Utc::now().naive_utc() - ChronoDuration::seconds(DEBOUNCE_SECS);
+        let collection_ids =
+            CollectionRarity::get_pending_collection_ids(&mut conn, marked_before, BATCH_SIZE)
+                .await?;
+
+        for collection_id in collection_ids {
+            if let Err(e) = self.compute_collection(&mut conn, &collection_id).await {
+                error!(
+                    collection_id = %collection_id,
+                    "Failed to compute rarity: {:?}", e
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes the trait scores and nft rankings of a whole collection.
+    async fn compute_collection(
+        &self,
+        conn: &mut DbPoolConnection<'_>,
+        collection_id: &str,
+    ) -> anyhow::Result<()> {
+        // Changes marked while computing are picked up by the next round
+        let started_at = Utc::now().naive_utc();
+
+        let traits = Attribute::get_traits_by_collection(conn, collection_id).await?;
+        let supply = CollectionRarity::get_supply(conn, collection_id).await?;
+        let rarity = compute_rarity(&traits, supply);
+
+        let attributes = traits
+            .into_iter()
+            .filter_map(|(nft_id, attr_type, value)| {
+                let trait_rarity = rarity.traits.get(&(attr_type.clone(), value.clone()))?;
+                Some(Attribute {
+                    collection_id: Some(collection_id.to_string()),
+                    nft_id: Some(nft_id),
+                    attr_type: Some(attr_type),
+                    value: Some(value),
+                    score: to_decimal(trait_rarity.score),
+                    rarity: to_decimal(trait_rarity.frequency),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let nft_rarities = rarity
+            .nfts
+            .into_iter()
+            .map(|nft| NftRarity {
+                nft_id: nft.nft_id,
+                collection_id: collection_id.to_string(),
+                trait_count: Some(nft.trait_count),
+                rarity_score: to_decimal(nft.rarity_score),
+                statistical_rarity: to_decimal(nft.statistical_rarity),
+                rarity_rank: Some(nft.rarity_rank),
+                statistical_rank: Some(nft.statistical_rank),
+            })
+            .collect::<Vec<_>>();
+
+        execute_in_chunks(self.db_pool.clone(), update_scores, &attributes, 200)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store attribute scores: {e:?}"))?;
+        execute_in_chunks(self.db_pool.clone(), insert_nft_rarities, &nft_rarities, 200)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store nft rarities: {e:?}"))?;
+
+        CollectionRarity::set_computed(conn, collection_id, started_at).await?;
+
+        Ok(())
+    }
+}
+
+fn to_decimal(value: f64) -> Option<BigDecimal> {
+    BigDecimal::from_f64(value).map(|value| value.round(SCORE_SCALE))
+}
+
+pub fn update_scores(
+    items_to_insert: Vec<Attribute>,
+) -> impl QueryFragment<Pg> + diesel::query_builder::QueryId + Send {
+    use crate::schema::attributes::dsl::*;
+
+    diesel::insert_into(schema::attributes::table)
+        .values(items_to_insert)
+        .on_conflict((collection_id, nft_id, attr_type, value))
+        .do_update()
+        .set((score.eq(excluded(score)), rarity.eq(excluded(rarity))))
+}
@@ -0,0 +1,52 @@
+use crate::{models::db::collection_stats::CollectionStats, postgres::postgres_utils::ArcDbPool};
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+const POLL_INTERVAL_SECS: u64 = 60;
+/// The 24h/7d/30d volumes shrink as sales age out, so the windows of stats with recent
+/// volume are recomputed at least this often
+const REFRESH_AFTER_SECS: i64 = 600;
+const BATCH_SIZE: i64 = 100;
+
+pub struct StatsWorker {
+    db_pool: ArcDbPool,
+}
+
+impl StatsWorker {
+    pub fn new(db_pool: ArcDbPool) -> Self {
+        Self { db_pool }
+    }
+
+    pub async fn start(&self) {
+        info!("Stats worker is starting!");
+
+        loop {
+            match self.refresh_stale_stats().await {
+                // Keep going while there is a backlog
+                Ok(count) if count as i64 >= BATCH_SIZE => continue,
+                Ok(_) => (),
+                Err(e) => error!("Error while refreshing collection stats: {:?}", e),
+            }
+
+            sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+        }
+    }
+
+    async fn refresh_stale_stats(&self) -> anyhow::Result<usize> {
+        let mut conn = self.db_pool.get().await?;
+        let updated_before = Utc::now().naive_utc() - ChronoDuration::seconds(REFRESH_AFTER_SECS);
+        let collection_ids =
+            CollectionStats::get_stale_collection_ids(&mut conn, updated_before, BATCH_SIZE)
+                .await?;
+
+        if collection_ids.is_empty() {
+            return Ok(0);
+        }
+
+        CollectionStats::refresh_windows(&mut conn, &collection_ids).await?;
+
+        Ok(collection_ids.len())
+    }
+}
@@ -0,0 +1,324 @@
+use crate::{
+    config::worker_config::WashTradeWorkerConfig,
+    models::db::{
+        action::Action,
+        collection_stats::CollectionStats,
+        trade_flag::{insert_trade_flags, TradeFlag, TradeFlagReason},
+        wallet_transfer::WalletTransfer,
+        worker_checkpoint::WorkerCheckpoint,
+    },
+    postgres::postgres_utils::{execute_in_chunks, ArcDbPool},
+    workers::{get_min_progress, ProcessorProgress},
+};
+use aptos_indexer_processor_sdk::postgres::utils::database::DbPoolConnection;
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use chrono::Duration as ChronoDuration;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+const WORKER_NAME: &str = "wash_trade_worker";
+/// Name of the token processor in `processor_status`, which indexes the wallet transfers
+const TOKEN_PROCESSOR_NAME: &str = "token";
+
+/// Flags likely wash trades among the stored sales in `trade_flags`.
+///
+/// Sales are checked in tx_index order, and only up to the version every marketplace processor
+/// (and the token processor, which indexes the wallet transfers the funding check relies on)
+/// has reached, so a sale is never checked before the history it's compared against is stored.
+/// Deployments that reached their ending version are done and don't hold the others back.
+pub struct WashTradeWorker {
+    config: WashTradeWorkerConfig,
+    db_pool: ArcDbPool,
+    /// Checkpoint name and ending version of every marketplace deployment
+    deployments: Vec<(String, Option<u64>)>,
+}
+
+impl WashTradeWorker {
+    pub fn new(
+        config: WashTradeWorkerConfig,
+        db_pool: ArcDbPool,
+        deployments: Vec<(String, Option<u64>)>,
+    ) -> Self {
+        Self {
+            config,
+            db_pool,
+            deployments,
+        }
+    }
+
+    pub async fn start(&self) {
+        info!("Wash trade worker is starting!");
+
+        loop {
+            match self.check_sales().await {
+                // Keep going while there is a backlog
+                Ok(count) if count as i64 >= self.config.batch_size => continue,
+                Ok(_) => (),
+                Err(e) => error!("Error while checking sales for wash trades: {:?}", e),
+            }
+
+            sleep(Duration::from_secs(self.config.poll_interval_secs)).await;
+        }
+    }
+
+    async fn check_sales(&self) -> anyhow::Result<usize> {
+        let mut conn = self.db_pool.get().await?;
+        let Some(up_to_tx_index) = self.get_indexed_tx_index(&mut conn).await? else {
+            return Ok(0);
+        };
+        let after_tx_index = WorkerCheckpoint::get(&mut conn, WORKER_NAME).await?;
+
+        let sales = Action::get_sales_between(
+            &mut conn,
+            after_tx_index,
+            up_to_tx_index,
+            self.config.batch_size,
+        )
+        .await?;
+
+        let mut flags = vec![];
+        for sale in &sales {
+            flags.extend(self.check_sale(&mut conn, sale).await?);
+        }
+
+        execute_in_chunks(self.db_pool.clone(), insert_trade_flags, &flags, 200)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store trade flags: {e:?}"))?;
+        if !flags.is_empty() {
+            info!(
+                "Flagged {} of {} sales as wash trades",
+                flags.len(),
+                sales.len()
+            );
+            // Volumes exclude the flagged sales
+            let keys = flags
+                .iter()
+                .map(|flag| (flag.tx_index, flag.tx_id.clone()))
+                .collect::<Vec<_>>();
+            CollectionStats::count_sales(&mut conn, &keys).await?;
+        }
+
+        // A full batch may stop in the middle of the indexed range
+        let checkpoint = match sales.last() {
+            Some(sale) if sales.len() as i64 >= self.config.batch_size => sale.tx_index,
+            _ => up_to_tx_index,
+        };
+        WorkerCheckpoint::save(&mut conn, WORKER_NAME, checkpoint).await?;
+
+        Ok(sales.len())
+    }
+
+    /// Returns the last tx_index of the lowest version reached by the processors the checks
+    /// depend on, or `None` if one of them hasn't stored any batch yet.
+    async fn get_indexed_tx_index(
+        &self,
+        conn: &mut DbPoolConnection<'_>,
+    ) -> anyhow::Result<Option<i64>> {
+        let mut processors = self
+            .deployments
+            .iter()
+            .map(|(checkpoint, ending_version)| (checkpoint.as_str(), *ending_version))
+            .collect::<Vec<_>>();
+        if self.config.funding_lookback_txns > 0 {
+            processors.push((TOKEN_PROCESSOR_NAME, None));
+        }
+
+        let mut progress = vec![];
+        for (processor, ending_version) in processors {
+            progress.push(ProcessorProgress::get(conn, processor, ending_version).await?);
+        }
+
+        Ok(get_min_version(&progress).map(|version| (version + 1) * 100_000 - 1))
+    }
+
+    async fn check_sale(
+        &self,
+        conn: &mut DbPoolConnection<'_>,
+        sale: &Action,
+    ) -> anyhow::Result<Vec<TradeFlag>> {
+        let mut flags = vec![];
+        let (Some(seller), Some(buyer)) = (&sale.sender, &sale.receiver) else {
+            return Ok(flags);
+        };
+
+        if seller == buyer {
+            flags.push(TradeFlag::new(
+                sale,
+                TradeFlagReason::RoundTrip,
+                format!("{buyer} bought from itself"),
+            ));
+        } else if let Some(block_time) = sale.block_time {
+            let since = block_time - ChronoDuration::seconds(self.config.round_trip_window_secs);
+            if let Some(previous_sale) =
+                Action::get_last_sale_between(conn, buyer, seller, since, sale.tx_index).await?
+            {
+                flags.push(TradeFlag::new(
+                    sale,
+                    TradeFlagReason::RoundTrip,
+                    format!(
+                        "{buyer} sold to {seller} at tx_index {}",
+                        previous_sale.tx_index
+                    ),
+                ));
+            }
+        }
+
+        if self.config.funding_lookback_txns > 0 && seller != buyer {
+            for (funded, funder) in [(buyer, seller), (seller, buyer)] {
+                let senders = WalletTransfer::get_recent_senders(
+                    conn,
+                    funded,
+                    sale.tx_index,
+                    self.config.funding_lookback_txns,
+                )
+                .await?;
+                if senders.contains(funder) {
+                    flags.push(TradeFlag::new(
+                        sale,
+                        TradeFlagReason::MutualFunding,
+                        format!("{funded} was funded by {funder}"),
+                    ));
+                    break;
+                }
+            }
+        }
+
+        if let (Some(collection_id), Some(price)) = (&sale.collection_id, &sale.price) {
+            let prices = Action::get_recent_sale_prices(
+                conn,
+                collection_id,
+                sale.payment_token.as_deref(),
+                sale.tx_index,
+                self.config.median_window,
+            )
+            .await?;
+            if prices.len() as i64 >= self.config.min_median_sales {
+                if let Some(median) = median(prices) {
+                    if is_price_outlier(price, &median, self.config.price_outlier_multiplier) {
+                        flags.push(TradeFlag::new(
+                            sale,
+                            TradeFlagReason::PriceOutlier,
+                            format!(
+                                "price {price} is above {}x the median {median}",
+                                self.config.price_outlier_multiplier
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+/// Returns the lowest version reached by the processors that are still running, or `None` if
+/// one of them hasn't stored any batch yet. When every processor is retired, the lowest of
+/// their versions is used.
+fn get_min_version(progress: &[ProcessorProgress]) -> Option<i64> {
+    get_min_progress(progress, |processor| processor.last_success_version)
+}
+
+/// Returns the median of the prices, rounded down to the smallest unit.
+fn median(mut prices: Vec<BigDecimal>) -> Option<BigDecimal> {
+    if prices.is_empty() {
+        return None;
+    }
+
+    prices.sort_unstable();
+    let mid = prices.len() / 2;
+    if prices.len() % 2 == 0 {
+        let sum = &prices[mid - 1] + &prices[mid];
+        Some((sum / BigDecimal::from(2)).with_scale_round(0, RoundingMode::Down))
+    } else {
+        Some(prices[mid].clone())
+    }
+}
+
+fn is_price_outlier(price: &BigDecimal, median: &BigDecimal, multiplier: i64) -> bool {
+    *median > BigDecimal::zero() && *price > median * BigDecimal::from(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amounts(amounts: &[i64]) -> Vec<BigDecimal> {
+        amounts
+            .iter()
+            .map(|amount| BigDecimal::from(*amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_min_version() {
+        let retired_v1 = ProcessorProgress {
+            last_success_version: Some(100),
+            last_transaction_timestamp: None,
+            ending_version: Some(100),
+        };
+        let active_v2 = ProcessorProgress {
+            last_success_version: Some(500),
+            last_transaction_timestamp: None,
+            ending_version: None,
+        };
+        assert_eq!(get_min_version(&[retired_v1, active_v2]), Some(500));
+
+        // v1 still has to catch up with its ending version
+        let catching_up_v1 = ProcessorProgress {
+            last_success_version: Some(50),
+            last_transaction_timestamp: None,
+            ending_version: Some(100),
+        };
+        let active_v2 = ProcessorProgress {
+            last_success_version: Some(500),
+            last_transaction_timestamp: None,
+            ending_version: None,
+        };
+        assert_eq!(get_min_version(&[catching_up_v1, active_v2]), Some(50));
+
+        let retired_v1 = ProcessorProgress {
+            last_success_version: Some(100),
+            last_transaction_timestamp: None,
+            ending_version: Some(100),
+        };
+        let new_v2 = ProcessorProgress {
+            last_success_version: None,
+            last_transaction_timestamp: None,
+            ending_version: None,
+        };
+        assert_eq!(get_min_version(&[retired_v1, new_v2]), None);
+
+        let retired_v1 = ProcessorProgress {
+            last_success_version: Some(100),
+            last_transaction_timestamp: None,
+            ending_version: Some(100),
+        };
+        assert_eq!(get_min_version(&[retired_v1]), Some(100));
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![]), None);
+        assert_eq!(median(amounts(&[5, 1, 3])), Some(BigDecimal::from(3)));
+        assert_eq!(median(amounts(&[4, 1, 3, 2])), Some(BigDecimal::from(2)));
+        assert_eq!(
+            median(amounts(&[i64::MAX, i64::MAX])),
+            Some(BigDecimal::from(i64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_is_price_outlier() {
+        let median = BigDecimal::from(100);
+        assert!(is_price_outlier(&BigDecimal::from(1_001), &median, 10));
+        assert!(!is_price_outlier(&BigDecimal::from(1_000), &median, 10));
+        // Collections whose recent sales were free have no meaningful median
+        assert!(!is_price_outlier(
+            &BigDecimal::from(1_000),
+            &BigDecimal::zero(),
+            10
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    config::worker_config::WebhookWorkerConfig,
+    models::db::webhook::{Webhook, WebhookDelivery},
+    postgres::postgres_utils::ArcDbPool,
+};
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
+use futures::{stream, StreamExt};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+/// Hex encoded `sha256=` HMAC of `{timestamp}.{body}` keyed with the webhook secret
+pub const SIGNATURE_HEADER: &str = "x-nft-aggregator-signature";
+/// Unix time in seconds at which the payload was signed
+pub const TIMESTAMP_HEADER: &str = "x-nft-aggregator-timestamp";
+pub const WEBHOOK_ID_HEADER: &str = "x-nft-aggregator-webhook-id";
+
+/// Maximum length of the response body kept as the error of a failed attempt
+const MAX_ERROR_LENGTH: usize = 512;
+
+pub struct WebhookWorker {
+    config: WebhookWorkerConfig,
+    client: Client,
+    db_pool: ArcDbPool,
+}
+
+impl WebhookWorker {
+    pub fn new(config: WebhookWorkerConfig, db_pool: ArcDbPool) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.request_timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            config,
+            client,
+            db_pool,
+        })
+    }
+
+    pub async fn start(&self) {
+        info!("Webhook worker is starting!");
+
+        loop {
+            match self.process_deliveries().await {
+                // Keep going without waiting while there is a backlog of deliveries
+                Ok(processed) if processed as i64 >= self.config.batch_size => continue,
+                Ok(_) => (),
+                Err(e) => error!("Error while delivering webhooks: {:?}", e),
+            }
+
+            sleep(Duration::from_secs(self.config.poll_interval_secs)).await;
+        }
+    }
+
+    /// Attempts the due deliveries of the outbox. Returns the number of attempts made.
+    async fn process_deliveries(&self) -> anyhow::Result<usize> {
+        let deliveries = {
+            let mut conn = self.db_pool.get().await?;
+            WebhookDelivery::get_due(&mut conn, Utc::now().naive_utc(), self.config.batch_size)
+                .await?
+        };
+
+        let count = deliveries.len();
+        stream::iter(deliveries)
+            .for_each_concurrent(self.config.concurrency, |(delivery, webhook)| async move {
+                if let Err(e) = self.attempt(&delivery, &webhook).await {
+                    error!(
+                        webhook_id = %webhook.id,
+                        tx_index = delivery.tx_index,
+                        "Failed to record webhook delivery: {:?}", e
+                    );
+                }
+            })
+            .await;
+
+        Ok(count)
+    }
+
+    async fn attempt(&self, delivery: &WebhookDelivery, webhook: &Webhook) -> anyhow::Result<()> {
+        let result = post_payload(&self.client, webhook, &delivery.payload).await;
+        let now = Utc::now().naive_utc();
+        let mut conn = self.db_pool.get().await?;
+
+        match result {
+            Ok(status_code) => {
+                debug!(webhook_id = %webhook.id, tx_index = delivery.tx_index, "Webhook delivered");
+                WebhookDelivery::mark_delivered(&mut conn, delivery, status_code, now).await?;
+            },
+            Err((status_code, error)) => {
+                let retry_at = next_attempt_at(&self.config, delivery.attempts + 1, now);
+                if retry_at.is_none() {
+                    warn!(
+                        webhook_id = %webhook.id,
+                        tx_index = delivery.tx_index,
+                        "Webhook delivery dead-lettered after {} attempts: {}",
+                        delivery.attempts + 1,
+                        error
+                    );
+                }
+                WebhookDelivery::mark_failed(
+                    &mut conn,
+                    delivery,
+                    status_code,
+                    &error,
+                    now,
+                    retry_at,
+                )
+                .await?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns when to retry after `attempts` failed attempts, or None once they are exhausted.
+pub fn next_attempt_at(
+    config: &WebhookWorkerConfig,
+    attempts: i32,
+    now: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+
+    let backoff = config
+        .retry_backoff_secs
+        .saturating_mul(2_i64.saturating_pow(attempts.saturating_sub(1) as u32))
+        .min(config.max_retry_backoff_secs);
+
+    Some(now + ChronoDuration::seconds(backoff))
+}
+
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// POSTs the signed payload to the webhook. Returns the status code on a 2xx response, the
+/// status code if any and an error otherwise.
+pub async fn post_payload(
+    client: &Client,
+    webhook: &Webhook,
+    payload: &serde_json::Value,
+) -> Result<i32, (Option<i32>, String)> {
+    let body = serde_json::to_vec(payload).map_err(|e| (None, e.to_string()))?;
+    let timestamp = Utc::now().timestamp();
+
+    let response = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(
+            SIGNATURE_HEADER,
+            sign_payload(&webhook.secret, timestamp, &body),
+        )
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(WEBHOOK_ID_HEADER, &webhook.id)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(i32::from(status.as_u16()));
+    }
+
+    let mut error = response.text().await.unwrap_or_default();
+    error.truncate(MAX_ERROR_LENGTH);
+    Err((
+        Some(i32::from(status.as_u16())),
+        format!("Endpoint responded with {status}: {error}"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Accepts a single request and answers it with `status_line`, returning the raw request.
+    async fn serve_once(status_line: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+
+            let response = format!("HTTP/1.1 {status_line}\r\ncontent-length: 0\r\n\r\n");
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn test_next_attempt_backs_off_exponentially() {
+        let config = WebhookWorkerConfig {
+            retry_backoff_secs: 10,
+            max_retry_backoff_secs: 60,
+            max_attempts: 5,
+            ..Default::default()
+        };
+        let now = Utc::now().naive_utc();
+        let delay =
+            |attempts| next_attempt_at(&config, attempts, now).map(|at| (at - now).num_seconds());
+
+        assert_eq!(delay(1), Some(10));
+        assert_eq!(delay(2), Some(20));
+        assert_eq!(delay(3), Some(40));
+        assert_eq!(delay(4), Some(60));
+        assert_eq!(delay(5), None);
+    }
+
+    #[tokio::test]
+    async fn test_post_payload_is_signed() {
+        let (url, handle) = serve_once("200 OK").await;
+        let webhook = Webhook {
+            id: "sales".to_string(),
+            url,
+            secret: "secret".to_string(),
+            ..Default::default()
+        };
+        let payload = json!({ "event_type": "buy", "action": { "tx_index": 1 } });
+
+        let status = post_payload(&Client::new(), &webhook, &payload).await;
+        assert_eq!(status, Ok(200));
+
+        let request = handle.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let header = |name: &str| {
+            head.lines()
+                .find_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+                })
+                .unwrap()
+        };
+
+        let timestamp = header(TIMESTAMP_HEADER).parse::<i64>().unwrap();
+        assert_eq!(
+            header(SIGNATURE_HEADER),
+            sign_payload("secret", timestamp, body.as_bytes())
+        );
+        assert_eq!(header(WEBHOOK_ID_HEADER), "sales");
+    }
+
+    #[tokio::test]
+    async fn test_post_payload_fails_on_error_status() {
+        let (url, _handle) = serve_once("503 Service Unavailable").await;
+        let webhook = Webhook {
+            url,
+            ..Default::default()
+        };
+
+        let result = post_payload(&Client::new(), &webhook, &json!({})).await;
+        assert_eq!(result.map_err(|(status, _)| status), Err(Some(503)));
+    }
+}